@@ -0,0 +1,213 @@
+//! Throughput benchmarks for seavan.
+//!
+//! Run with `cargo bench`. Docker isn't needed: builds run against a runner
+//! which accepts every command, so only seavan's own work is measured.
+//!
+//! Runs are compared with criterion's saved baselines: save one with
+//! `cargo bench -- --save-baseline main`, then compare a later run against
+//! it with `cargo bench -- --baseline main`. Criterion reports each
+//! benchmark's change and whether it's significant.
+//!
+//! Environment variables:
+//!
+//! * `SEAVAN_BENCH_LARGE_MIB`: Also hash and stream a file of this many MiB,
+//!   to reproduce performance on large files.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use seavan::{set::SeavanSet, utils, Seavan};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::Arc;
+
+// Number of names generated per run of the naming benchmarks.
+const NAMES: usize = 1000;
+
+// Number of files in the batch scheduling benchmark.
+const BATCH_SIZE: usize = 100;
+
+// Samples taken of benchmarks processing files of at least a MiB, rather
+// than criterion's default of 100, so large files don't take minutes.
+const LARGE_SAMPLES: usize = 10;
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+
+// Returns the sizes of file to benchmark with.
+fn sizes() -> Vec<u64> {
+    let mut sizes = vec![KIB, MIB, 64 * MIB];
+    if let Ok(large) = std::env::var("SEAVAN_BENCH_LARGE_MIB") {
+        let large: u64 = large.parse().expect("SEAVAN_BENCH_LARGE_MIB is a number");
+        sizes.push(large * MIB);
+    }
+    sizes
+}
+
+// Writes a file of `size` pseudo-random bytes, the same on every run.
+fn write_file(path: &Path, size: u64) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut written = 0;
+    while written < size {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let bytes = state.to_le_bytes();
+        let length = (size - written).min(bytes.len() as u64) as usize;
+        file.write_all(&bytes[..length])?;
+        written += length as u64;
+    }
+    file.flush()
+}
+
+// Returns a label for a size, e.g. `64MiB`.
+fn size_label(size: u64) -> String {
+    match size {
+        size if size >= MIB => format!("{}MiB", size / MIB),
+        size => format!("{}KiB", size / KIB),
+    }
+}
+
+// Accepts every command without running anything. Streamed build contexts
+// are read to the end by a child process, so the pipe isn't a bottleneck.
+#[cfg(unix)]
+struct NullRunner;
+
+#[cfg(unix)]
+impl seavan::runner::CommandRunner for NullRunner {
+    fn run(&self, _: &mut Command, _: Option<File>) -> io::Result<Output> {
+        use std::os::unix::process::ExitStatusExt;
+        Ok(Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        })
+    }
+
+    fn spawn(&self, _: &mut Command) -> io::Result<std::process::Child> {
+        use std::process::Stdio;
+        Command::new("sh")
+            .args(["-c", "cat > /dev/null"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+}
+
+// Benchmarks hashing a file of each size, with both hash schemes.
+fn hashing(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group("hash");
+    for size in sizes() {
+        let path = dir.path().join(format!("hash-{}.bin", size));
+        write_file(&path, size).unwrap();
+        if size >= MIB {
+            let _ = group.sample_size(LARGE_SAMPLES);
+        }
+        let _ = group.throughput(Throughput::Bytes(size));
+
+        let wrap = Seavan::new(&path).unwrap();
+        let id = BenchmarkId::new("flat", size_label(size));
+        let _ = group.bench_with_input(id, &wrap, |b, wrap| {
+            b.iter(|| wrap.repository_name_and_tag().unwrap())
+        });
+        let wrap = Seavan::new(&path)
+            .unwrap()
+            .with_tree_hash(Some(8 * MIB))
+            .unwrap();
+        let id = BenchmarkId::new("tree", size_label(size));
+        let _ = group.bench_with_input(id, &wrap, |b, wrap| {
+            b.iter(|| wrap.repository_name_and_tag().unwrap())
+        });
+    }
+    group.finish();
+}
+
+// Benchmarks generating names for files with awkward names.
+fn naming(c: &mut Criterion) {
+    let names: Vec<String> = (0..NAMES)
+        .map(|i| format!("Quarterly Report ({}) - FINAL_v{}.tar.gz", i, i % 7))
+        .collect();
+    let mut group = c.benchmark_group("naming");
+    let _ = group.throughput(Throughput::Elements(NAMES as u64));
+    let _ = group.bench_function("sanitize", |b| {
+        b.iter(|| {
+            for name in &names {
+                let _ = utils::docker_safe_string(name);
+            }
+        })
+    });
+
+    let wraps = names
+        .iter()
+        .map(|name| Seavan::from_content(name, b""))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let _ = group.bench_function("reference", |b| {
+        b.iter(|| {
+            for wrap in &wraps {
+                let _ = wrap.repository_name_and_tag().unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+// Benchmarks streaming a build context holding a file of each size.
+#[cfg(unix)]
+fn context(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group("context");
+    for size in sizes() {
+        let path = dir.path().join(format!("context-{}.bin", size));
+        write_file(&path, size).unwrap();
+        if size >= MIB {
+            let _ = group.sample_size(LARGE_SAMPLES);
+        }
+        let _ = group.throughput(Throughput::Bytes(size));
+
+        let wrap = Seavan::new(&path)
+            .unwrap()
+            .with_streaming(true)
+            .unwrap()
+            .with_command_runner(Arc::new(NullRunner))
+            .unwrap();
+        let id = BenchmarkId::from_parameter(size_label(size));
+        let _ =
+            group.bench_with_input(id, &wrap, |b, wrap| b.iter(|| wrap.create_image().unwrap()));
+    }
+    group.finish();
+}
+
+// Benchmarks the overhead of building a batch of small files together.
+#[cfg(unix)]
+fn batch(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let runner = Arc::new(NullRunner);
+    let mut wraps = Vec::with_capacity(BATCH_SIZE);
+    for i in 0..BATCH_SIZE {
+        let path = dir.path().join(format!("batch-{}.txt", i));
+        std::fs::write(&path, format!("file {}\n", i)).unwrap();
+        wraps.push(
+            Seavan::new(&path)
+                .unwrap()
+                .with_command_runner(runner.clone())
+                .unwrap(),
+        );
+    }
+    let set = SeavanSet::new(wraps).with_command_runner(runner);
+    let mut group = c.benchmark_group("batch");
+    let _ = group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    let _ = group.bench_function(BenchmarkId::from_parameter(BATCH_SIZE), |b| {
+        b.iter(|| set.create_images().unwrap())
+    });
+    group.finish();
+}
+
+#[cfg(unix)]
+criterion_group!(benches, hashing, naming, context, batch);
+#[cfg(not(unix))]
+criterion_group!(benches, hashing, naming);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+/*
+ * C API for seavan, available when the crate is built with the ffi feature,
+ * e.g. as a shared library with
+ *   cargo rustc --lib --release --features ffi --crate-type cdylib
+ *
+ * Strings returned by seavan are owned by the caller and must be released
+ * with seavan_string_free. Functions which fail return NULL; the reason can
+ * be retrieved with seavan_last_error. Panics inside seavan are reported as
+ * failures rather than unwinding into the caller.
+ */
+#ifndef SEAVAN_H
+#define SEAVAN_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Wraps the file at path in a container image using Docker, returning the
+ * image's repository name and tag. */
+char *seavan_create_image(const char *path);
+
+/* Returns the repository name and tag the file at path would be wrapped
+ * under, without building an image. */
+char *seavan_repository_name_and_tag(const char *path);
+
+/* Extracts the file wrapped in the image reference into the directory
+ * destination, pulling the image if it isn't available locally. Returns the
+ * path of the extracted file. */
+char *seavan_extract(const char *reference, const char *destination);
+
+/* Checks whether the file or directory at path matches the image reference.
+ * Returns 1 if it matches, 0 if it doesn't, or -1 on failure. */
+int seavan_verify(const char *reference, const char *path);
+
+/* Returns the message of the last error on the calling thread, or NULL. The
+ * message is owned by seavan and remains valid until the next failing call
+ * on the same thread. */
+const char *seavan_last_error(void);
+
+/* Releases a string returned by seavan. */
+void seavan_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SEAVAN_H */
@@ -0,0 +1,229 @@
+//! Python bindings for seavan.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use std::path::PathBuf;
+
+create_exception!(seavan, SeavanError, PyException);
+
+// Converts a seavan error into a Python exception carrying its exit code.
+fn to_py_err(error: seavan::error::SeavanError) -> PyErr {
+    let exit_code = error.exit_code();
+    let err = SeavanError::new_err(error.to_string());
+    Python::attach(|py| {
+        let _ = err.value(py).setattr("exit_code", exit_code);
+    });
+    err
+}
+
+/// A file wrapped in a Docker container shell.
+#[pyclass(name = "Seavan", module = "seavan", from_py_object)]
+#[derive(Clone)]
+struct PySeavan {
+    inner: seavan::Seavan,
+}
+
+#[pymethods]
+impl PySeavan {
+    /// Creates a new Seavan for the file at `path`.
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: seavan::Seavan::new(path).map_err(to_py_err)?,
+        })
+    }
+
+    /// Creates a reference-only Seavan for content held in memory.
+    #[staticmethod]
+    fn from_content(filename: &str, content: &[u8]) -> PyResult<Self> {
+        Ok(Self {
+            inner: seavan::Seavan::from_content(filename, content).map_err(to_py_err)?,
+        })
+    }
+
+    /// Sets the tag used for the image.
+    fn with_tag<'py>(mut slf: PyRefMut<'py, Self>, tag: &str) -> PyResult<PyRefMut<'py, Self>> {
+        let _ = slf.inner.set_tag(tag).map_err(to_py_err)?;
+        Ok(slf)
+    }
+
+    /// Sets the registry the image is named for.
+    fn with_registry<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        registry: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let _ = slf.inner.set_registry(registry).map_err(to_py_err)?;
+        Ok(slf)
+    }
+
+    /// Sets the path component placed in front of generated repository names.
+    fn with_package_root<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        package_root: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let _ = slf
+            .inner
+            .set_package_root(package_root)
+            .map_err(to_py_err)?;
+        Ok(slf)
+    }
+
+    /// Truncates the content hash in the repository name to `length`
+    /// characters.
+    fn with_hash_length<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        length: usize,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let _ = slf.inner.set_hash_length(length).map_err(to_py_err)?;
+        Ok(slf)
+    }
+
+    /// Adds an environment variable to pass to the docker processes.
+    fn with_docker_env<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        key: &str,
+        value: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let _ = slf.inner.set_docker_env(key, value).map_err(to_py_err)?;
+        Ok(slf)
+    }
+
+    /// Returns the generated repository name and tag for the image.
+    fn repository_name_and_tag(&self) -> PyResult<String> {
+        self.inner.repository_name_and_tag().map_err(to_py_err)
+    }
+
+    /// Creates the container image using Docker, returning its repository
+    /// name and tag. The GIL is released while Docker runs.
+    fn create_image(&self, py: Python<'_>) -> PyResult<String> {
+        py.detach(|| self.inner.create_image()).map_err(to_py_err)
+    }
+
+    /// Pushes the image built by `create_image` to the registry, returning
+    /// what was pushed. The GIL is released while Docker runs.
+    fn push_image(&self, py: Python<'_>) -> PyResult<PushedImage> {
+        let pushed = py.detach(|| self.inner.push_image()).map_err(to_py_err)?;
+        Ok(PushedImage {
+            reference: pushed.reference,
+            digest: pushed.digest,
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+/// An image pushed to a registry.
+#[pyclass(module = "seavan", get_all, frozen)]
+struct PushedImage {
+    /// The repository name and tag the image was pushed as.
+    reference: String,
+    /// The digest of the pushed manifest, or None if it wasn't reported.
+    digest: Option<String>,
+}
+
+#[pymethods]
+impl PushedImage {
+    fn __repr__(&self) -> String {
+        format!(
+            "PushedImage(reference={:?}, digest={:?})",
+            self.reference, self.digest
+        )
+    }
+}
+
+/// The result of checking a file against an image with `verify`.
+#[pyclass(module = "seavan", get_all, frozen)]
+struct VerificationReport {
+    /// The image reference the file was checked against.
+    reference: String,
+    /// The file which was checked.
+    path: PathBuf,
+    /// The name of the algorithm the file was hashed with.
+    algorithm: &'static str,
+    /// The hash in the image's repository name, which may be truncated.
+    expected: String,
+    /// The full content hash of the file.
+    actual: String,
+    /// Whether the file matches the image.
+    verified: bool,
+    /// The paths of a bundle's files which differ from the directory checked.
+    mismatched: Vec<String>,
+}
+
+#[pymethods]
+impl VerificationReport {
+    fn __bool__(&self) -> bool {
+        self.verified
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "VerificationReport(reference={:?}, path={:?}, verified={})",
+            self.reference,
+            self.path,
+            if self.verified { "True" } else { "False" }
+        )
+    }
+}
+
+/// Creates images for several Seavans with a single Docker invocation,
+/// returning their repository names and tags in the same order.
+#[pyfunction]
+fn create_images(py: Python<'_>, wraps: Vec<PySeavan>) -> PyResult<Vec<String>> {
+    let set = seavan::set::SeavanSet::new(wraps.into_iter().map(|w| w.inner).collect());
+    py.detach(|| set.create_images()).map_err(to_py_err)
+}
+
+/// Extracts the file wrapped in the image `reference` into the directory
+/// `destination`, pulling the image if needed, and returns its path. The GIL
+/// is released while Docker runs.
+#[pyfunction]
+fn extract_from_reference(
+    py: Python<'_>,
+    reference: &str,
+    destination: PathBuf,
+) -> PyResult<PathBuf> {
+    py.detach(|| seavan::Seavan::extract_from_reference(reference, destination))
+        .map_err(to_py_err)
+}
+
+/// Checks whether the file or directory at `path` matches the image
+/// `reference`. The GIL is released while Docker runs.
+#[pyfunction]
+fn verify(py: Python<'_>, reference: &str, path: PathBuf) -> PyResult<VerificationReport> {
+    let report = py
+        .detach(|| seavan::Seavan::verify(reference, path))
+        .map_err(to_py_err)?;
+    Ok(VerificationReport {
+        reference: report.reference,
+        path: report.path,
+        algorithm: report.algorithm.name(),
+        expected: report.expected,
+        actual: report.actual,
+        verified: report.verified,
+        mismatched: report.mismatched,
+    })
+}
+
+/// Checks that `reference` is a valid image reference.
+#[pyfunction]
+fn validate_reference(reference: &str) -> PyResult<()> {
+    seavan::naming::validate_reference(reference).map_err(to_py_err)
+}
+
+#[pymodule]
+#[pyo3(name = "seavan")]
+fn seavan_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("SeavanError", m.py().get_type::<SeavanError>())?;
+    m.add_class::<PySeavan>()?;
+    m.add_class::<PushedImage>()?;
+    m.add_class::<VerificationReport>()?;
+    m.add_function(wrap_pyfunction!(create_images, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_from_reference, m)?)?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_reference, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! Helpers for storing images in Azure Container Registry.
+//!
+//! Authentication uses the `az` CLI, which must be installed. Tokens can be
+//! obtained for a logged-in user or for the managed identity of the Azure
+//! resource seavan is running on.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    naming,
+    registry::{Registry, StorageUsage},
+};
+use serde::Deserialize;
+use std::process::{Command, Output};
+
+// Registry names must be between 5 and 50 alphanumeric characters.
+const MIN_REGISTRY_NAME_LENGTH: usize = 5;
+const MAX_REGISTRY_NAME_LENGTH: usize = 50;
+
+// Longest repository name, including the registry host, that may be used in
+// an image reference.
+const MAX_REPOSITORY_LENGTH: usize = 255;
+
+// Longest tag that may be used in an image reference.
+const MAX_TAG_LENGTH: usize = 128;
+
+// Username used when logging in to ACR with an access token.
+const TOKEN_USERNAME: &str = "00000000-0000-0000-0000-000000000000";
+
+// Runs an az CLI command, returning its output on success.
+fn az(args: &[&str]) -> SeavanResult<Output> {
+    let output = Command::new("az").args(args).output()?;
+    match output.status.success() {
+        true => Ok(output),
+        false => Err(SeavanError::AzCliFailure(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )),
+    }
+}
+
+// A single usage metric reported by `az acr show-usage`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Usage {
+    name: String,
+    current_value: u64,
+    limit: u64,
+}
+
+// The output of `az acr show-usage`.
+#[derive(Deserialize)]
+struct UsageList {
+    value: Vec<Usage>,
+}
+
+// Returns whether the registry is hosted by ACR.
+pub(crate) fn is_acr(registry: &Registry) -> bool {
+    let host = registry.name().split('/').next().unwrap_or_default();
+    host.ends_with(".azurecr.io")
+}
+
+// Returns the registry name for an ACR login server, e.g. `myregistry` for
+// `myregistry.azurecr.io`.
+fn registry_name(registry: &Registry) -> &str {
+    let host = registry.name().split('/').next().unwrap_or_default();
+    host.strip_suffix(".azurecr.io").unwrap_or(host)
+}
+
+/// Returns a `Registry` for the named Azure Container Registry. Registry
+/// names must be between 5 and 50 alphanumeric characters.
+///
+/// # Arguments
+///
+/// * `name`: The name of the registry, without the `.azurecr.io` suffix.
+///
+/// # Examples
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{acr, Seavan};
+/// let wrap = Seavan::new("README.md")?.with_registry(acr::registry("myregistry")?)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn registry(name: &str) -> SeavanResult<Registry> {
+    let valid = (MIN_REGISTRY_NAME_LENGTH..=MAX_REGISTRY_NAME_LENGTH).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric());
+    if !valid {
+        return Err(SeavanError::InvalidRegistryComponent(name.into()));
+    }
+    Ok(Registry::new(&format!(
+        "{}.azurecr.io",
+        name.to_ascii_lowercase()
+    )))
+}
+
+/// Checks that an image reference satisfies ACR's naming rules: repository
+/// names may only contain lower-case alphanumeric characters, `.`, `-`, `_`
+/// and `/`, and the repository and tag must not be too long.
+///
+/// # Arguments
+///
+/// * `reference`: The image reference, such as one returned by
+///   `Seavan::repository_name_and_tag`.
+pub fn validate_reference(reference: &str) -> SeavanResult<()> {
+    let invalid = |reason: &str| SeavanError::InvalidReference(reference.into(), reason.into());
+
+    let repository = naming::repository_of(reference);
+    let tag = reference[repository.len()..].trim_start_matches(':');
+    let path = repository
+        .split_once('/')
+        .map(|(_, path)| path)
+        .unwrap_or(repository);
+
+    let valid_path = path.chars().all(|c| {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-' | '_' | '/')
+    });
+    if !valid_path {
+        return Err(invalid("repository contains unsupported characters"));
+    }
+    if repository.len() > MAX_REPOSITORY_LENGTH {
+        return Err(invalid("repository name is too long"));
+    }
+    if tag.len() > MAX_TAG_LENGTH {
+        return Err(invalid("tag is too long"));
+    }
+    Ok(())
+}
+
+/// Logs the `az` CLI in using the managed identity of the Azure resource
+/// seavan is running on.
+///
+/// # Arguments
+///
+/// * `client_id`: The client ID of a user-assigned identity, or `None` to
+///   use the system-assigned identity.
+pub fn login_with_managed_identity(client_id: Option<&str>) -> SeavanResult<()> {
+    let mut args = vec!["login", "--identity"];
+    if let Some(client_id) = client_id {
+        args.extend(["--username", client_id]);
+    }
+    let _ = az(&args)?;
+    Ok(())
+}
+
+/// Fetches an access token for the registry using the `az` CLI's current
+/// login.
+///
+/// # Arguments
+///
+/// * `registry`: The ACR registry, as returned by `registry`.
+pub fn access_token(registry: &Registry) -> SeavanResult<String> {
+    let output = az(&[
+        "acr",
+        "login",
+        "--name",
+        registry_name(registry),
+        "--expose-token",
+        "--output",
+        "tsv",
+        "--query",
+        "accessToken",
+    ])?;
+    let token = String::from_utf8(output.stdout).map_err(|_| SeavanError::FailedStrConversion)?;
+    Ok(token.trim().to_string())
+}
+
+/// Returns the username and an access token from the `az` CLI to
+/// authenticate to the registry with. Pass them to
+/// `Seavan::with_credentials` or `client::Client::with_credentials`, which
+/// give them to docker without changing the user's own docker
+/// configuration.
+///
+/// # Arguments
+///
+/// * `registry`: The ACR registry, as returned by `registry`.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{acr, Seavan};
+/// let registry = acr::registry("myregistry")?;
+/// let (username, password) = acr::credentials(&registry)?;
+/// let pushed = Seavan::new("README.md")?
+///     .with_registry(registry)?
+///     .with_credentials(&username, &password)?
+///     .create_and_push()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn credentials(registry: &Registry) -> SeavanResult<(String, String)> {
+    Ok((TOKEN_USERNAME.into(), access_token(registry)?))
+}
+
+/// Returns the storage used by the registry and its quota, using the `az`
+/// CLI's current login.
+///
+/// # Arguments
+///
+/// * `registry`: The ACR registry, as returned by `registry`.
+pub fn storage_usage(registry: &Registry) -> SeavanResult<StorageUsage> {
+    let output = az(&[
+        "acr",
+        "show-usage",
+        "--name",
+        registry_name(registry),
+        "--output",
+        "json",
+    ])?;
+    let usages: UsageList = serde_json::from_slice(&output.stdout)?;
+    let size = usages
+        .value
+        .into_iter()
+        .find(|usage| usage.name == "Size")
+        .ok_or_else(|| SeavanError::AzCliFailure("No storage usage reported".into()))?;
+    Ok(StorageUsage {
+        used: size.current_value,
+        limit: Some(size.limit),
+    })
+}
@@ -0,0 +1,76 @@
+//! Archives of images, for moving them between hosts without a registry.
+//!
+//! Images are saved with `docker save` and loaded with `docker load`. Docker
+//! 25 and later write archives which are both Docker archives and OCI image
+//! layouts; earlier versions only write Docker archives.
+
+use crate::{
+    docker::Docker,
+    error::{SeavanError, SeavanResult},
+    labels,
+};
+use std::fs::File;
+use std::path::Path;
+
+// File marking the root of an OCI image layout.
+const OCI_LAYOUT_FILE: &str = "oci-layout";
+
+/// The formats images can be saved in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A Docker archive, as written by `docker save`, which can be loaded
+    /// by any version of Docker. This is the default.
+    #[default]
+    Docker,
+
+    /// An `oci-archive`: a tar of an OCI image layout, which can also be read
+    /// by tools such as skopeo and crane.
+    Oci,
+}
+
+// Saves the local image `reference` to an archive at `path`.
+pub(crate) fn save(
+    docker: &Docker,
+    reference: &str,
+    path: &Path,
+    format: ArchiveFormat,
+) -> SeavanResult<()> {
+    docker.save(reference, path, format)?;
+    if format == ArchiveFormat::Oci && !is_oci_layout(path)? {
+        return Err(SeavanError::DockerSaveFailure(
+            "the archive is not an OCI image layout; Docker 25 or later is needed".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Loads the archive at `path`, returning the reference of the seavan image
+// it holds.
+pub(crate) fn load(docker: &Docker, path: &Path) -> SeavanResult<String> {
+    let references = docker.load(path)?;
+    for reference in &references {
+        if docker
+            .local_label_value(reference, labels::CONTENT_DIGEST)
+            .is_some()
+        {
+            let version = docker.local_label_value(reference, labels::FORMAT_VERSION);
+            let _ = labels::check_format_version(reference, version.as_deref())?;
+            return Ok(reference.clone());
+        }
+    }
+    Err(SeavanError::NotWrapped(match references.is_empty() {
+        true => path.display().to_string(),
+        false => references.join(", "),
+    }))
+}
+
+// Checks whether the tar at `path` holds an OCI image layout.
+fn is_oci_layout(path: &Path) -> SeavanResult<bool> {
+    let mut archive = tar::Archive::new(File::open(path)?);
+    for entry in archive.entries()? {
+        if entry?.path()?.as_ref() == Path::new(OCI_LAYOUT_FILE) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
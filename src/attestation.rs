@@ -0,0 +1,102 @@
+//! Attestations describing wrapped files, so scanners which only read image
+//! metadata can see what an image holds.
+//!
+//! Images built with `Seavan::with_attestation` carry an in-toto statement
+//! in their `labels::ATTESTATION` label. Its subject is the wrapped file,
+//! named as it was wrapped and identified by its content hash, and its
+//! predicate records the file's size and when the statement was made.
+
+use crate::retention;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The type of in-toto statements.
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+
+/// The type of the predicate of seavan's statements.
+pub const PREDICATE_TYPE: &str = "https://github.com/cmeister2/seavan/attestation/v1";
+
+// Environment variable holding the time to record instead of the current
+// time, in seconds since the Unix epoch, for reproducible builds.
+const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// An in-toto statement about a wrapped file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Statement {
+    /// The statement's type, `STATEMENT_TYPE`.
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+
+    /// The wrapped file.
+    pub subject: Vec<Subject>,
+
+    /// The predicate's type, `PREDICATE_TYPE`.
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+
+    /// Details of the wrapped file.
+    pub predicate: Predicate,
+}
+
+/// A file an in-toto statement is about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subject {
+    /// The name of the file, or its content hash if the filename is hidden.
+    pub name: String,
+
+    /// The file's content hash, keyed by hash scheme, e.g. `sha256`.
+    pub digest: BTreeMap<String, String>,
+}
+
+/// Details of a wrapped file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Predicate {
+    /// The size of the file in bytes.
+    pub size: u64,
+
+    /// When the statement was made, as an RFC 3339 timestamp in UTC. This is
+    /// taken from `SOURCE_DATE_EPOCH` when it's set, and is otherwise the
+    /// file's modification time, or the Unix epoch for content which isn't
+    /// a file.
+    pub created: String,
+
+    /// The version of seavan which made the statement.
+    pub builder: String,
+}
+
+impl Statement {
+    // Creates a statement about a file with the given name, size, content
+    // digest, e.g. `sha256:<hex>`, and modification time.
+    pub(crate) fn new(name: &str, size: u64, digest: &str, modified: Option<SystemTime>) -> Self {
+        let (scheme, hash) = digest.split_once(':').unwrap_or(("", digest));
+        Self {
+            statement_type: STATEMENT_TYPE.into(),
+            subject: vec![Subject {
+                name: name.into(),
+                digest: BTreeMap::from([(scheme.to_string(), hash.to_string())]),
+            }],
+            predicate_type: PREDICATE_TYPE.into(),
+            predicate: Predicate {
+                size,
+                created: retention::rfc3339(created(modified)),
+                builder: format!("seavan {}", env!("CARGO_PKG_VERSION")),
+            },
+        }
+    }
+}
+
+// Returns the time to record in statements about a file modified at
+// `modified`, in seconds since the Unix epoch. The current time is never
+// used, so rebuilding an unchanged file gives the same statement.
+fn created(modified: Option<SystemTime>) -> u64 {
+    match std::env::var(SOURCE_DATE_EPOCH)
+        .ok()
+        .and_then(|s| s.parse().ok())
+    {
+        Some(secs) => secs,
+        None => modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |elapsed| elapsed.as_secs()),
+    }
+}
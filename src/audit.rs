@@ -0,0 +1,124 @@
+//! Audit logging of images created by seavan.
+
+use crate::{error::SeavanResult, utils::current_user};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The action recorded by an `AuditEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    /// An image was created.
+    Create,
+    /// An image was pushed to a registry.
+    Push,
+    /// An image was removed.
+    Remove,
+}
+
+/// A record of a single action taken by seavan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// What was done.
+    pub action: AuditAction,
+    /// The image reference the action applied to.
+    pub reference: String,
+    /// The user which ran seavan.
+    pub user: String,
+    /// When the action happened, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl AuditEvent {
+    /// Creates a new `AuditEvent` for the current user and time.
+    ///
+    /// # Arguments
+    ///
+    /// * `action`: What was done.
+    /// * `reference`: The image reference the action applied to.
+    pub fn new(action: AuditAction, reference: &str) -> Self {
+        let user = current_user().unwrap_or_else(|| "unknown".to_string());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            action,
+            reference: reference.into(),
+            user,
+            timestamp,
+        }
+    }
+}
+
+/// A destination for audit events.
+///
+/// This is implemented for closures taking an `&AuditEvent`, so a callback
+/// can be used directly.
+pub trait AuditSink: Send + Sync {
+    /// Records an audit event.
+    fn record(&self, event: &AuditEvent) -> SeavanResult<()>;
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditEvent) -> SeavanResult<()> + Send + Sync,
+{
+    fn record(&self, event: &AuditEvent) -> SeavanResult<()> {
+        self(event)
+    }
+}
+
+/// An append-only audit log which writes each event as a line of JSON.
+#[derive(Debug)]
+pub struct JsonlAuditLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonlAuditLog {
+    /// Creates a new `JsonlAuditLog`. The file is created when the first
+    /// event is recorded, and events are always appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the log file.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl AuditSink for JsonlAuditLog {
+    fn record(&self, event: &AuditEvent) -> SeavanResult<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        // Serialise writers within this process so lines never interleave.
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+// A shareable audit sink.
+#[derive(Clone)]
+pub(crate) struct Auditor(pub(crate) Arc<dyn AuditSink>);
+
+impl fmt::Debug for Auditor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Auditor")
+    }
+}
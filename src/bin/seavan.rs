@@ -0,0 +1,554 @@
+//! Command line interface to seavan, for use from shell pipelines. Built
+//! with the `cli` feature.
+//!
+//! Each command prints a line for each reference or path it produced, only
+//! the references or paths with `--quiet`, or a JSON document with
+//! `--output json`. The progress of hashing files, the steps of builds and
+//! the status of each layer being pushed are shown on stderr as they happen,
+//! unless `--quiet` or `--output json` is given. Docker only reports how
+//! much of a layer it's uploaded when its output is a terminal, which it
+//! isn't here, so pushes are shown layer by layer rather than byte by byte.
+//! Failures are reported on stderr, with the exit codes documented on
+//! `SeavanError::exit_code`; `verify` exits with
+//! `EXIT_VERIFICATION_MISMATCH` if an image's content doesn't match its
+//! name.
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use clap_mangen::Man;
+use seavan::{
+    error::{SeavanError, SeavanResult, EXIT_USAGE},
+    report::{BuildEvent, PushedImage},
+    Seavan,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process::exit,
+};
+
+// The widest progress line drawn on a terminal, so redrawing it never has
+// to clear a wrapped line.
+const PROGRESS_WIDTH: usize = 79;
+
+/// Wrap files in single container layers for later composition.
+#[derive(Debug, Parser)]
+#[command(name = "seavan", version)]
+struct Cli {
+    /// How to print results
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    output: Format,
+
+    /// Print only the resulting references or paths, one per line
+    #[arg(long, short, global = true, conflicts_with = "output")]
+    quiet: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+// The formats results can be printed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Lines describing what each command did
+    Text,
+    /// A JSON document
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Wrap a file in an image, optionally pushing it to the registry
+    Wrap {
+        #[command(flatten)]
+        image: Image,
+
+        /// Push the image once it is built
+        #[arg(long)]
+        push: bool,
+    },
+    /// Push the image of a file wrapped earlier
+    Push {
+        #[command(flatten)]
+        image: Image,
+    },
+    /// Extract the file wrapped in an image
+    Extract {
+        /// The image reference
+        reference: String,
+
+        /// The directory to extract the file into
+        #[arg(long, short, default_value = ".")]
+        destination: PathBuf,
+    },
+    /// Pull an image, extract its file and check it against the content
+    /// hash in the image's name, failing if they differ
+    Verify {
+        /// The image reference
+        reference: String,
+    },
+    /// List the local images built by seavan
+    List,
+    /// Remove local images built by seavan
+    Rm {
+        /// The image references
+        #[arg(required = true)]
+        references: Vec<String>,
+    },
+    /// Print a completion script for a shell
+    Completions {
+        /// The shell to complete commands for
+        shell: Shell,
+    },
+    /// Write man pages for seavan and each of its commands
+    Man {
+        /// The directory to write the pages into
+        #[arg(default_value = ".")]
+        directory: PathBuf,
+    },
+}
+
+// The file to wrap, and where its image goes.
+#[derive(Debug, Args)]
+struct Image {
+    /// The file to wrap
+    file: PathBuf,
+
+    /// The registry to push the image to
+    #[arg(long)]
+    registry: Option<String>,
+
+    /// The tag of the image
+    #[arg(long)]
+    tag: Option<String>,
+}
+
+impl Image {
+    // Creates the `Seavan` for the file, with the registry and tag.
+    fn wrap(&self) -> SeavanResult<Seavan> {
+        let mut wrap = Seavan::new(&self.file)?;
+        if let Some(registry) = &self.registry {
+            let _ = wrap.set_registry(registry.as_str())?;
+        }
+        if let Some(tag) = &self.tag {
+            let _ = wrap.set_tag(tag)?;
+        }
+        Ok(wrap)
+    }
+}
+
+// Shows the progress of a build or push on stderr: redrawing one line on a
+// terminal, or printing a line as each step finishes, each layer changes
+// status, and periodically while hashing otherwise.
+struct Progress {
+    enabled: bool,
+    terminal: bool,
+    steps: BTreeMap<u32, String>,
+    drawn: bool,
+}
+
+impl Progress {
+    fn new(enabled: bool) -> Self {
+        Progress {
+            enabled,
+            terminal: io::stderr().is_terminal(),
+            steps: BTreeMap::new(),
+            drawn: false,
+        }
+    }
+
+    // Returns the line describing the step, layer or hashing `event` is
+    // about, and whether it's printed when stderr isn't a terminal.
+    fn line(&mut self, event: &BuildEvent) -> Option<(String, bool)> {
+        let (step, status) = match event {
+            BuildEvent::Hashing(hashed, size) => {
+                let percent = match size {
+                    0 => 100,
+                    size => hashed * 100 / size,
+                };
+                let line = format!(
+                    "Hashing {} of {} ({}%)",
+                    mebibytes(*hashed),
+                    mebibytes(*size),
+                    percent
+                );
+                return Some((line, true));
+            }
+            BuildEvent::LayerStatus(layer, status) => {
+                return Some((format!("Layer {}: {}", layer, status), true));
+            }
+            BuildEvent::StepStarted(step, description) => {
+                let _ = self.steps.insert(*step, description.clone());
+                (*step, "running".to_string())
+            }
+            BuildEvent::StepDone(step, Some(took)) => {
+                (*step, format!("done in {:.1}s", took.as_secs_f64()))
+            }
+            BuildEvent::StepDone(step, None) => (*step, "done".into()),
+            BuildEvent::StepCached(step) => (*step, "cached".into()),
+            BuildEvent::StepFailed(step, error) => (*step, format!("failed: {}", error)),
+            BuildEvent::StepOutput(_, _) | BuildEvent::Output(_) => return None,
+        };
+        let description = self.steps.get(&step).map_or("", String::as_str);
+        let finished = !matches!(event, BuildEvent::StepStarted(_, _));
+        Some((format!("#{} {} ({})", step, description, status), finished))
+    }
+
+    fn event(&mut self, event: &BuildEvent) {
+        if !self.enabled {
+            return;
+        }
+        match self.line(event) {
+            Some((line, _)) if self.terminal => {
+                let line: String = line.chars().take(PROGRESS_WIDTH).collect();
+                eprint!("\r\x1b[K{}", line);
+                self.drawn = true;
+            }
+            Some((line, true)) => eprintln!("{}", line),
+            _ => {}
+        }
+    }
+
+    // Ends the line drawn on a terminal, so what follows starts on its own
+    // line.
+    fn finish(&mut self) {
+        if self.drawn {
+            eprintln!();
+            self.drawn = false;
+        }
+    }
+}
+
+// What a command produced: the references or paths it resulted in, which
+// are all `--quiet` prints, lines describing it, and a JSON document.
+struct Outcome {
+    results: Vec<String>,
+    lines: Vec<String>,
+    json: Value,
+}
+
+impl Outcome {
+    fn new(results: Vec<String>, lines: Vec<String>, json: Value) -> Self {
+        Outcome {
+            results,
+            lines,
+            json,
+        }
+    }
+}
+
+// Runs the command, showing the progress of builds and pushes on `progress`.
+fn run(command: &Command, progress: &mut Progress) -> SeavanResult<Outcome> {
+    match command {
+        Command::Wrap { image, push: true } => {
+            let pushed = image
+                .wrap()?
+                .create_and_push_with_progress(|event| progress.event(event))?;
+            Ok(pushed_outcome(pushed))
+        }
+        Command::Wrap { image, push: false } => {
+            let reference = image
+                .wrap()?
+                .create_image_with_progress(|event| progress.event(event))?;
+            let json = json!({ "reference": reference });
+            let line = format!("Built {}", reference);
+            Ok(Outcome::new(vec![reference], vec![line], json))
+        }
+        Command::Push { image } => {
+            let pushed = image
+                .wrap()?
+                .push_image_with_progress(|event| progress.event(event))?;
+            Ok(pushed_outcome(pushed))
+        }
+        Command::Extract {
+            reference,
+            destination,
+        } => {
+            let path = Seavan::extract_from_reference(reference, destination)?;
+            let path = path.display().to_string();
+            let json = json!({ "reference": reference, "path": path });
+            let line = format!("Extracted {} to {}", reference, path);
+            Ok(Outcome::new(vec![path], vec![line], json))
+        }
+        Command::Verify { reference } => {
+            let staging = tempfile::tempdir()?;
+            let path = Seavan::extract_from_reference(reference, staging.path())?;
+            let report = Seavan::verify(reference, path)?;
+            if !report.verified {
+                return Err(SeavanError::ContentMismatch(
+                    report.reference,
+                    report.expected,
+                    report.actual,
+                ));
+            }
+            let json = json!({
+                "reference": report.reference,
+                "digest": report.actual,
+                "verified": true,
+            });
+            let line = format!("Verified {} ({})", report.reference, report.actual);
+            Ok(Outcome::new(vec![report.reference], vec![line], json))
+        }
+        Command::List => {
+            let references = Seavan::list_images()?;
+            let json = json!(references);
+            Ok(Outcome::new(references.clone(), references, json))
+        }
+        Command::Rm { references } => {
+            for reference in references {
+                Seavan::remove_image(reference)?;
+            }
+            let json = json!({ "removed": references });
+            let lines = references.iter().map(|r| format!("Removed {}", r));
+            Ok(Outcome::new(references.clone(), lines.collect(), json))
+        }
+        Command::Completions { shell } => {
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut Cli::command(), "seavan", &mut script);
+            let script = String::from_utf8_lossy(&script).trim_end().to_string();
+            let json = json!({ "script": script });
+            Ok(Outcome::new(vec![script.clone()], vec![script], json))
+        }
+        Command::Man { directory } => {
+            let mut command = Cli::command().disable_help_subcommand(true);
+            command.build();
+            fs::create_dir_all(directory)?;
+            let mut pages = vec![Man::new(command.clone()).generate_to(directory)?];
+            for subcommand in command.get_subcommands() {
+                pages.push(Man::new(subcommand.clone()).generate_to(directory)?);
+            }
+            let pages: Vec<String> = pages.iter().map(|p| p.display().to_string()).collect();
+            let json = json!({ "pages": pages });
+            let lines = pages.iter().map(|p| format!("Wrote {}", p));
+            Ok(Outcome::new(pages.clone(), lines.collect(), json))
+        }
+    }
+}
+
+// Formats a number of bytes in MiB, e.g. `12.5 MiB`.
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+// Describes an image which was pushed.
+fn pushed_outcome(pushed: PushedImage) -> Outcome {
+    let json = json!({ "reference": pushed.reference, "digest": pushed.digest });
+    let line = match &pushed.digest {
+        Some(digest) => format!("Pushed {} ({})", pushed.reference, digest),
+        None => format!("Pushed {}", pushed.reference),
+    };
+    Outcome::new(vec![pushed.reference], vec![line], json)
+}
+
+// Describes a failure, followed by each of its causes, e.g.
+// `io error: No such file or directory (os error 2)`.
+fn error_message(error: &SeavanError) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+fn main() {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        // Help and version requests aren't errors.
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            exit(EXIT_USAGE);
+        }
+    };
+
+    let mut progress = Progress::new(cli.output == Format::Text && !cli.quiet);
+    let result = run(&cli.command, &mut progress);
+    progress.finish();
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("seavan: {}", error_message(&e));
+            exit(e.exit_code());
+        }
+    };
+    match (cli.output, cli.quiet) {
+        (Format::Json, _) => println!("{}", outcome.json),
+        (Format::Text, true) => outcome.results.iter().for_each(|r| println!("{}", r)),
+        (Format::Text, false) => outcome.lines.iter().for_each(|l| println!("{}", l)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+    use std::time::Duration;
+
+    fn parse_str(line: &str) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("seavan").chain(line.split_whitespace()))
+    }
+
+    #[test]
+    fn definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn options() -> Result<(), clap::Error> {
+        let cli =
+            parse_str("--output json wrap data.csv --registry=acr.azurecr.io --tag v1 --push")?;
+        assert_eq!(cli.output, Format::Json);
+        match cli.command {
+            Command::Wrap { image, push } => {
+                assert!(push);
+                assert_eq!(image.file, PathBuf::from("data.csv"));
+                assert_eq!(image.registry.as_deref(), Some("acr.azurecr.io"));
+                assert_eq!(image.tag.as_deref(), Some("v1"));
+            }
+            command => panic!("parsed {:?}", command),
+        }
+
+        // Values may hold `=`, and `-` is an operand.
+        match parse_str("extract --destination=a=b -")?.command {
+            Command::Extract {
+                reference,
+                destination,
+            } => {
+                assert_eq!(reference, "-");
+                assert_eq!(destination, PathBuf::from("a=b"));
+            }
+            command => panic!("parsed {:?}", command),
+        }
+        assert_eq!(parse_str("list --output=json")?.output, Format::Json);
+        assert!(parse_str("list -q")?.quiet);
+        assert!(matches!(
+            parse_str("verify seavanpkg/abcd--a-txt")?.command,
+            Command::Verify { reference } if reference == "seavanpkg/abcd--a-txt"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn error_messages() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "No such file or directory");
+        assert_eq!(
+            error_message(&SeavanError::IoError(missing)),
+            "io error: No such file or directory"
+        );
+        let unavailable = io::Error::new(io::ErrorKind::PermissionDenied, "permission denied");
+        assert_eq!(
+            error_message(&SeavanError::DockerUnavailable(unavailable)),
+            "Docker unavailable: permission denied"
+        );
+        assert_eq!(
+            error_message(&SeavanError::BannedRegistryPrefix),
+            SeavanError::BannedRegistryPrefix.to_string()
+        );
+    }
+
+    #[test]
+    fn completions() -> SeavanResult<()> {
+        for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+            let cli = parse_str(&format!("completions {}", shell)).unwrap();
+            let outcome = run(&cli.command, &mut Progress::new(false))?;
+            assert!(outcome.results[0].contains("verify"), "{}", shell);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn man() -> SeavanResult<()> {
+        let dir = tempfile::tempdir()?;
+        let cli = parse_str(&format!("man {}", dir.path().join("man1").display())).unwrap();
+        let outcome = run(&cli.command, &mut Progress::new(false))?;
+        let pages = outcome.results;
+        assert_eq!(outcome.lines[0], format!("Wrote {}", pages[0]));
+        assert_eq!(outcome.json["pages"][1], pages[1].as_str());
+        let names: Vec<_> = pages
+            .iter()
+            .map(|page| PathBuf::from(page).file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names[..2], ["seavan.1", "seavan-wrap.1"]);
+        assert!(names.iter().any(|name| name == "seavan-verify.1"));
+        let page = fs::read_to_string(&pages[1])?;
+        assert!(page.contains("\\-\\-registry"));
+        Ok(())
+    }
+
+    #[test]
+    fn progress() {
+        let mut progress = Progress::new(false);
+        let mut line = |event| progress.line(&event);
+        assert_eq!(
+            line(BuildEvent::StepStarted(2, "[1/1] COPY data.csv /".into())),
+            Some(("#2 [1/1] COPY data.csv / (running)".into(), false))
+        );
+        assert_eq!(line(BuildEvent::StepOutput(2, "copying".into())), None);
+        assert_eq!(
+            line(BuildEvent::StepDone(2, Some(Duration::from_millis(1250)))),
+            Some(("#2 [1/1] COPY data.csv / (done in 1.2s)".into(), true))
+        );
+        assert_eq!(
+            line(BuildEvent::StepFailed(3, "no space left".into())),
+            Some(("#3  (failed: no space left)".into(), true))
+        );
+        assert_eq!(
+            line(BuildEvent::Hashing(64 << 20, 256 << 20)),
+            Some(("Hashing 64.0 MiB of 256.0 MiB (25%)".into(), true))
+        );
+        assert_eq!(
+            line(BuildEvent::Hashing(0, 0)),
+            Some(("Hashing 0.0 MiB of 0.0 MiB (100%)".into(), true))
+        );
+        assert_eq!(
+            line(BuildEvent::LayerStatus(
+                "5f70bf18a086".into(),
+                "Pushed".into()
+            )),
+            Some(("Layer 5f70bf18a086: Pushed".into(), true))
+        );
+    }
+
+    #[test]
+    fn end_of_options() -> Result<(), clap::Error> {
+        let cli = parse_str("rm -- --quiet -x")?;
+        assert!(!cli.quiet);
+        assert!(
+            matches!(cli.command, Command::Rm { references } if references == ["--quiet", "-x"])
+        );
+        assert!(matches!(
+            parse_str("wrap -- --tag")?.command,
+            Command::Wrap { image, .. } if image.file.as_os_str() == "--tag"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let error = |line| parse_str(line).err().map(|e| e.kind());
+        assert_eq!(error("wrap data.csv --help"), Some(ErrorKind::DisplayHelp));
+        assert_eq!(
+            error("wrap data.csv --verbose"),
+            Some(ErrorKind::UnknownArgument)
+        );
+        assert_eq!(error("wrap data.csv --tag"), Some(ErrorKind::InvalidValue));
+        assert_eq!(error("--quiet"), Some(ErrorKind::MissingSubcommand));
+        assert_eq!(error("list --output yaml"), Some(ErrorKind::InvalidValue));
+        assert_eq!(
+            error("list --quiet --output json"),
+            Some(ErrorKind::ArgumentConflict)
+        );
+        assert_eq!(error("frobnicate"), Some(ErrorKind::InvalidSubcommand));
+        assert_eq!(error("wrap"), Some(ErrorKind::MissingRequiredArgument));
+        assert_eq!(error("push a b"), Some(ErrorKind::UnknownArgument));
+        assert_eq!(error("list a"), Some(ErrorKind::UnknownArgument));
+        assert_eq!(error("rm"), Some(ErrorKind::MissingRequiredArgument));
+        assert_eq!(error("verify"), Some(ErrorKind::MissingRequiredArgument));
+        assert_eq!(error("completions tcsh"), Some(ErrorKind::InvalidValue));
+    }
+}
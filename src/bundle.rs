@@ -0,0 +1,805 @@
+//! Bundles of several files or directories wrapped in one image.
+//!
+//! Each input of a bundle is copied into the image in its own layer: files
+//! at `/<filename>` and directories, with everything beneath them, at
+//! `/<directory name>/`. Symbolic links to files beneath a directory are
+//! copied as the files they point to, if those files are beneath the
+//! directory too; symbolic links leaving the directory, or to directories,
+//! aren't followed, and other special files are left out. With
+//! `SeavanBundle::with_layer_per_subdirectory`, a directory is instead split
+//! into a layer for each of its top-level subdirectories, plus one for the
+//! files directly inside it, so a new version of the bundle shares the layers
+//! of the subdirectories that didn't change. Files and directories keep
+//! their modification times, permissions and extended attributes, and
+//! empty directories are kept too, unless `SeavanBundle::with_file_metadata`
+//! says otherwise. The content hash of a
+//! bundle is the SHA-256 of a `sha256sum`-style listing of every file in it,
+//! sorted by path, so it only changes when a file's content or path changes.
+//! The listing is embedded in the image at `/.seavan/SHA256SUMS`, where
+//! `sha256sum -c` can check the files against it, and recorded in the
+//! image's `labels::BUNDLE` label, with the size of each file, which
+//! `Seavan::verify` checks a directory against. The total size of the files
+//! is recorded in the `labels::PAYLOAD_SIZE` label.
+//!
+//! Bundles are built under the same checks as single files: each file is
+//! checked against the bundle's policies and scanners, the bundle against
+//! its rules, with the size limit applying to the total size of its files,
+//! and built bundles are recorded in the audit log and catalog. Policies
+//! can only reject a file in a bundle; their other actions apply to whole
+//! images, so aren't used. Bundles aren't pushed by seavan, so have no
+//! pre-push scanners.
+
+use crate::{
+    audit::{AuditAction, AuditSink},
+    catalog::{self, CatalogEntry, CatalogSink},
+    context::{self, DOCKERFILE_PATH},
+    docker,
+    engine::ContainerEngine,
+    error::{SeavanError, SeavanResult},
+    hash::{self, Scheme},
+    labels,
+    policy::{Policy, PolicyAction},
+    registry::Registry,
+    rules::{Rules, Violation},
+    runner::CommandRunner,
+    scan::Scanner,
+    Seavan,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tar::{Builder, Header, HeaderMode};
+use tempfile::tempfile;
+
+/// Path of the listing of every file in a bundle, within its build context
+/// and its image. No input can be copied to `/.seavan`, so this can't clash
+/// with them.
+const MANIFEST_PATH: &str = ".seavan/SHA256SUMS";
+
+/// A file in a `SeavanBundle`, as recorded in its `labels::BUNDLE` label.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleEntry {
+    /// The path of the file in the image, without a leading `/`.
+    pub path: String,
+    /// The SHA-256 hash of the file.
+    pub hash: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// How the metadata of a bundle's files and directories is copied into its
+/// image. Owners are never kept: everything in the image is owned by root.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileMetadata {
+    /// Keep the modification time, permissions and extended attributes of
+    /// each file and directory, recorded as PAX `SCHILY.xattr` records, and
+    /// keep directories holding no files, so they're restored as they were.
+    /// This is the default.
+    #[default]
+    Preserve,
+
+    /// Give every file and directory the same fixed modification time, and
+    /// permissions of `0644`, or `0755` for directories and executable
+    /// files, and leave out extended attributes and directories holding no
+    /// files, so images of the same content are identical wherever and
+    /// whenever they're built.
+    Normalize,
+}
+
+/// Several files or directories wrapped together in one image.
+///
+/// Bundles are checked against their policies, scanners and rules before
+/// they're built, and recorded in the audit log and catalog, as single files
+/// are. They have no pre-push scanners, since seavan doesn't push them.
+#[derive(Debug, Clone)]
+pub struct SeavanBundle {
+    // Holds the bundle's name and image settings, as if it were a file.
+    settings: Seavan,
+    inputs: Vec<PathBuf>,
+    layer_per_subdirectory: bool,
+    file_metadata: FileMetadata,
+}
+
+impl SeavanBundle {
+    /// Creates a new `SeavanBundle` of the given files and directories. The
+    /// repository name is derived from `name` as it would be from a
+    /// filename.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the bundle.
+    /// * `inputs`: The files and directories to be wrapped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::bundle::SeavanBundle;
+    /// let reference = SeavanBundle::new("configs", &["app.yaml", "nginx.conf", "certs"])?
+    ///     .with_registry("acr.azurecr.io")?
+    ///     .create_image()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new<P: AsRef<Path>>(name: &str, inputs: &[P]) -> SeavanResult<Self> {
+        let path = PathBuf::from(name);
+        if path.file_name() != Some(OsStr::new(name)) {
+            return Err(SeavanError::NoFileName(path));
+        }
+        let inputs = inputs
+            .iter()
+            .map(|input| {
+                let input = input.as_ref().canonicalize()?;
+                let _ = input_name(&input)?;
+                Ok(input)
+            })
+            .collect::<SeavanResult<Vec<_>>>()?;
+        Ok(Self {
+            settings: Seavan::from_parts(path, None)?,
+            inputs,
+            layer_per_subdirectory: false,
+            file_metadata: FileMetadata::default(),
+        })
+    }
+
+    /// Specifies the registry to be used for the image, as
+    /// `Seavan::with_registry` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry`: The registry to use.
+    pub fn with_registry<R: Into<Registry>>(mut self, registry: R) -> SeavanResult<Self> {
+        let _ = self.settings.set_registry(registry)?;
+        Ok(self)
+    }
+
+    /// Specifies the tag to be used for the image, as `Seavan::with_tag`
+    /// does.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag`: The image tag to be used.
+    pub fn with_tag(mut self, tag: &str) -> SeavanResult<Self> {
+        let _ = self.settings.set_tag(tag)?;
+        Ok(self)
+    }
+
+    /// Sets the runner used to execute docker commands, as
+    /// `Seavan::with_command_runner` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `runner`: The runner to execute docker commands with.
+    pub fn with_command_runner(mut self, runner: Arc<dyn CommandRunner>) -> SeavanResult<Self> {
+        let _ = self.settings.set_command_runner(runner)?;
+        Ok(self)
+    }
+
+    /// Sets the container engine used in place of Docker, as
+    /// `Seavan::with_engine` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine`: The container engine to use.
+    pub fn with_engine<E: ContainerEngine + 'static>(mut self, engine: E) -> SeavanResult<Self> {
+        let _ = self.settings.set_engine(engine)?;
+        Ok(self)
+    }
+
+    /// Adds a policy applied to each file in the bundle, as
+    /// `Seavan::with_policy` does. A file matching a policy with
+    /// `PolicyAction::Reject` fails the build with
+    /// `SeavanError::PolicyRejected`; other actions aren't used.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy`: The policy to add.
+    pub fn with_policy(mut self, policy: Policy) -> SeavanResult<Self> {
+        let _ = self.settings.set_policy(policy)?;
+        Ok(self)
+    }
+
+    /// Adds a scanner which is run on each file in the bundle before the
+    /// image is built, as `Seavan::with_scanner` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `scanner`: The scanner to run.
+    pub fn with_scanner(mut self, scanner: Arc<dyn Scanner>) -> SeavanResult<Self> {
+        let _ = self.settings.set_scanner(scanner)?;
+        Ok(self)
+    }
+
+    /// Adds rules which the bundle and its image must follow, as
+    /// `Seavan::with_rules` does. The size limit applies to the total size
+    /// of the bundle's files.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules`: The rules to add to any already set.
+    pub fn with_rules(mut self, rules: Rules) -> SeavanResult<Self> {
+        let _ = self.settings.set_rules(rules)?;
+        Ok(self)
+    }
+
+    /// Sets the audit log which records each image created, as
+    /// `Seavan::with_audit_log` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink`: The audit log to record actions in.
+    pub fn with_audit_log(mut self, sink: Arc<dyn AuditSink>) -> SeavanResult<Self> {
+        let _ = self.settings.set_audit_log(sink)?;
+        Ok(self)
+    }
+
+    /// Sets the artifact catalog which records each image created, as
+    /// `Seavan::with_catalog` does. Entries are recorded under the bundle's
+    /// name, with the total size of its files.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink`: The catalog to record entries in.
+    pub fn with_catalog(mut self, sink: Arc<dyn CatalogSink>) -> SeavanResult<Self> {
+        let _ = self.settings.set_catalog(sink)?;
+        Ok(self)
+    }
+
+    /// Copies each top-level subdirectory of a directory input into the
+    /// image in its own layer, with the files directly inside the directory
+    /// in one more, rather than copying the whole directory in one layer.
+    /// Registries and image stores then keep a single copy of subdirectories
+    /// which are the same in several versions of the bundle, and pushing a
+    /// new version only uploads the subdirectories which changed. Defaults to
+    /// `false`.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether to give each subdirectory its own layer.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::bundle::SeavanBundle;
+    /// let reference = SeavanBundle::new("site", &["public"])?
+    ///     .with_layer_per_subdirectory(true)?
+    ///     .create_image()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_layer_per_subdirectory(mut self, enabled: bool) -> SeavanResult<Self> {
+        self.layer_per_subdirectory = enabled;
+        Ok(self)
+    }
+
+    /// Sets how the modification times, permissions and extended attributes
+    /// of files and directories, and empty directories, are copied into the
+    /// image. Defaults to `FileMetadata::Preserve`. They aren't part of the
+    /// content hash, so they don't change the image's repository name.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata`: How to copy file and directory metadata.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::bundle::{FileMetadata, SeavanBundle};
+    /// let reference = SeavanBundle::new("configs", &["app.yaml", "certs"])?
+    ///     .with_file_metadata(FileMetadata::Normalize)?
+    ///     .create_image()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_file_metadata(mut self, metadata: FileMetadata) -> SeavanResult<Self> {
+        self.file_metadata = metadata;
+        Ok(self)
+    }
+
+    /// Returns every file in the bundle with its hash and size, sorted by
+    /// path. Fails with `SeavanError::DuplicateBundlePath` if two inputs hold
+    /// a file at the same path.
+    pub fn entries(&self) -> SeavanResult<Vec<BundleEntry>> {
+        hash_files(self.files()?)
+    }
+
+    /// Returns the generated repository name and tag for the container image,
+    /// as `Seavan::repository_name_and_tag` does.
+    pub fn repository_name_and_tag(&self) -> SeavanResult<String> {
+        let entries = self.entries()?;
+        self.settings
+            .repository_name_and_tag_for(&Self::hash(&entries), self.settings.hash_length)
+    }
+
+    /// Creates a container image containing every file in the bundle, as
+    /// `Seavan::create_image` does for single files. Files rejected by a
+    /// policy or scanner, or bundles breaking a rule, aren't built.
+    ///
+    /// Returns the generated repository name and tag for the container image.
+    pub fn create_image(&self) -> SeavanResult<String> {
+        self.check_buildable()?;
+
+        // Each file is hashed as it's written into the context, so the
+        // content that's hashed is the content that's built.
+        let mut context = tempfile()?;
+        let (hash, entries) = self.write_context(&mut context)?;
+        context.rewind()?;
+
+        let docker = &self.settings.docker;
+        let temporary_reference = docker::temporary_reference();
+        debug!(
+            "Building bundle under temporary reference {}",
+            temporary_reference
+        );
+        let output = docker.run(
+            docker
+                .command()
+                .args(["build", "-t", &temporary_reference])
+                .args(["-f", DOCKERFILE_PATH, "-"])
+                .env("DOCKER_BUILDKIT", "1"),
+            Some(context),
+        )?;
+        if !output.status.success() {
+            return Err(docker::build_failure(output));
+        }
+        docker::log_output(&output);
+
+        let repository_name_and_tag = match self.settings.resolve_repository_name_and_tag_for(&hash)
+        {
+            Ok(repository_name_and_tag) => repository_name_and_tag,
+            Err(e) => {
+                let _ = docker.remove_image(&temporary_reference);
+                return Err(e);
+            }
+        };
+        docker.promote(&temporary_reference, &repository_name_and_tag)?;
+        self.settings
+            .audit(AuditAction::Create, &repository_name_and_tag)?;
+        self.catalog(&repository_name_and_tag, &entries, &hash)?;
+        Ok(repository_name_and_tag)
+    }
+
+    // Rejects bundles holding a file rejected by a policy or scanner, or
+    // breaking a rule.
+    fn check_buildable(&self) -> SeavanResult<()> {
+        let settings = &self.settings;
+        let violation = |violation| SeavanError::RuleViolation(settings.path.clone(), violation);
+        let registry = settings.registry.as_ref().map(Registry::name);
+        if !settings.rules.allows_registry(registry) {
+            return Err(violation(Violation::RegistryNotAllowed(
+                registry.map(String::from),
+            )));
+        }
+        for key in &settings.rules.required_labels {
+            if !Self::label_keys().contains(&key.as_str()) {
+                return Err(violation(Violation::MissingLabel(key.clone())));
+            }
+        }
+
+        let mut size = 0;
+        for (_, path) in self.files()? {
+            // Check each file as if it were wrapped on its own.
+            let mut file = settings.clone();
+            file.path = path;
+            if file.policy_actions()?.contains(&&PolicyAction::Reject) {
+                return Err(SeavanError::PolicyRejected(file.path));
+            }
+            file.run_scanners(&file.scanners)?;
+            size += std::fs::metadata(&file.path)?.len();
+        }
+        match settings.rules.max_size {
+            Some(limit) if size > limit => Err(violation(Violation::TooLarge(size, limit))),
+            _ => Ok(()),
+        }
+    }
+
+    // Records the built image in the catalog, if there is one.
+    fn catalog(&self, reference: &str, entries: &[BundleEntry], hash: &str) -> SeavanResult<()> {
+        let catalog = match &self.settings.catalog {
+            Some(catalog) => catalog,
+            None => return Ok(()),
+        };
+        catalog.0.record(&CatalogEntry {
+            reference: reference.into(),
+            digest: hash::digest(Scheme::default(), hash),
+            filename: Some(self.settings.filename_lossy()?.into_owned()),
+            size: Some(entries.iter().map(|entry| entry.size).sum()),
+            labels: self
+                .labels(entries, hash)?
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            timestamp: catalog::now(),
+        })
+    }
+
+    // Computes the content hash of the bundle from its entries.
+    fn hash(entries: &[BundleEntry]) -> String {
+        format!("{:x}", Sha256::digest(listing(entries)))
+    }
+
+    // Lists every file in the bundle by its path in the image, sorted by
+    // path.
+    fn files(&self) -> SeavanResult<Vec<(String, PathBuf)>> {
+        let mut files = Vec::new();
+        for input in &self.inputs {
+            let name = input_name(input)?;
+            if Path::new(MANIFEST_PATH).starts_with(name) {
+                return Err(SeavanError::DuplicateBundlePath(name.into()));
+            }
+            match input.is_dir() {
+                true => walk(input, input, name, &mut files)?,
+                false if input.is_file() => files.push((name.into(), input.clone())),
+                false => return Err(SeavanError::NotAFile(input.clone())),
+            }
+        }
+        files.sort();
+        if let Some(pair) = files.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(SeavanError::DuplicateBundlePath(pair[0].0.clone()));
+        }
+        Ok(files)
+    }
+
+    // Lists the directories in the bundle's directory inputs which hold no
+    // files, by their paths in the image, sorted by path so each comes after
+    // its parent. These are only copied when metadata is preserved.
+    fn empty_directories(&self, entries: &[BundleEntry]) -> SeavanResult<Vec<(String, PathBuf)>> {
+        let mut directories = Vec::new();
+        if self.file_metadata == FileMetadata::Normalize {
+            return Ok(directories);
+        }
+        for input in self.inputs.iter().filter(|input| input.is_dir()) {
+            walk_directories(input, input_name(input)?, &mut directories)?;
+        }
+        directories.retain(|(path, _)| {
+            !entries
+                .iter()
+                .any(|entry| Path::new(&entry.path).starts_with(path))
+        });
+        directories.sort();
+        Ok(directories)
+    }
+
+    // Generates the Dockerfile, copying each input in its own layer, or each
+    // top-level subdirectory of a directory input in its own layer if
+    // `layer_per_subdirectory` is set.
+    pub(crate) fn dockerfile(&self, entries: &[BundleEntry], hash: &str) -> SeavanResult<String> {
+        let empty = self.empty_directories(entries)?;
+        let mut dockerfile = String::from("FROM scratch\n");
+        for input in &self.inputs {
+            let name = input_name(input)?;
+            let layers = match (input.is_dir(), self.layer_per_subdirectory) {
+                (true, true) => subdirectory_layers(name, entries, &empty),
+                (true, false) => vec![(vec![name.to_string()], format!("/{}/", name))],
+                (false, _) => vec![(vec![name.to_string()], format!("/{}", name))],
+            };
+            for (sources, target) in layers {
+                let arguments = sources
+                    .iter()
+                    .chain([&target])
+                    .map(serde_json::to_string)
+                    .collect::<Result<Vec<_>, _>>()?;
+                dockerfile.push_str(&format!("COPY [{}]\n", arguments.join(", ")));
+            }
+        }
+        dockerfile.push_str(&format!(
+            "COPY [{}, {}]\n",
+            serde_json::to_string(MANIFEST_PATH)?,
+            serde_json::to_string(&format!("/{}", MANIFEST_PATH))?
+        ));
+        for (key, value) in self.labels(entries, hash)? {
+            dockerfile.push_str(&format!(
+                "LABEL {}={}\n",
+                labels::dockerfile_quote(key),
+                labels::dockerfile_quote(&value)
+            ));
+        }
+        Ok(dockerfile)
+    }
+
+    // Returns the keys of the labels of a bundle's image.
+    fn label_keys() -> [&'static str; 4] {
+        [
+            labels::FORMAT_VERSION,
+            labels::CONTENT_DIGEST,
+            labels::BUNDLE,
+            labels::PAYLOAD_SIZE,
+        ]
+    }
+
+    // Returns the labels of the image of a bundle with the given entries
+    // and content hash, in the order of `label_keys`.
+    fn labels(
+        &self,
+        entries: &[BundleEntry],
+        hash: &str,
+    ) -> SeavanResult<Vec<(&'static str, String)>> {
+        let [format_version, content_digest, bundle, payload_size] = Self::label_keys();
+        Ok(vec![
+            (format_version, labels::CURRENT_FORMAT_VERSION.to_string()),
+            (content_digest, hash::digest(Scheme::default(), hash)),
+            (bundle, serde_json::to_string(entries)?),
+            (
+                payload_size,
+                entries
+                    .iter()
+                    .map(|entry| entry.size)
+                    .sum::<u64>()
+                    .to_string(),
+            ),
+        ])
+    }
+
+    // Writes a tar build context holding every file at its path in the
+    // image, hashing each file as it's written, followed by the listing of
+    // them all and the Dockerfile. Returns the content hash and the entries
+    // of the files.
+    pub(crate) fn write_context<W: Write>(
+        &self,
+        writer: W,
+    ) -> SeavanResult<(String, Vec<BundleEntry>)> {
+        let mode = match self.file_metadata {
+            FileMetadata::Preserve => HeaderMode::Complete,
+            FileMetadata::Normalize => HeaderMode::Deterministic,
+        };
+        let mut builder = Builder::new(writer);
+        let mut entries = Vec::new();
+        let mut directories = HashSet::new();
+        for (path, file) in self.files()? {
+            // Add the directories leading to the file first, so Docker
+            // copies their metadata rather than creating them.
+            let mut parents: Vec<_> = Path::new(&path)
+                .ancestors()
+                .zip(file.ancestors())
+                .skip(1)
+                .filter(|(parent, _)| !parent.as_os_str().is_empty())
+                .collect();
+            parents.reverse();
+            for (parent, source) in parents {
+                if directories.insert(parent.to_path_buf()) {
+                    self.append_directory(&mut builder, parent, source, mode)?;
+                }
+            }
+
+            if self.file_metadata == FileMetadata::Preserve {
+                append_xattrs(&mut builder, &file)?;
+            }
+            let file = File::open(file)?;
+            let size = file.metadata()?.len();
+            let hash = context::append_hashed(&mut builder, &path, file, Scheme::default(), mode)?;
+            entries.push(BundleEntry { path, hash, size });
+        }
+        for (path, source) in self.empty_directories(&entries)? {
+            self.append_directory(&mut builder, Path::new(&path), &source, mode)?;
+        }
+        let listing = listing(&entries);
+        let mut header = Header::new_gnu();
+        header.set_size(listing.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, MANIFEST_PATH, listing.as_bytes())?;
+
+        let hash = Self::hash(&entries);
+        context::append_dockerfile(builder, &self.dockerfile(&entries, &hash)?)?;
+        Ok((hash, entries))
+    }
+
+    // Appends the directory `source` to a build context at `path`, with its
+    // metadata recorded in `mode`.
+    fn append_directory<W: Write>(
+        &self,
+        builder: &mut Builder<W>,
+        path: &Path,
+        source: &Path,
+        mode: HeaderMode,
+    ) -> SeavanResult<()> {
+        if self.file_metadata == FileMetadata::Preserve {
+            append_xattrs(builder, source)?;
+        }
+        let mut header = Header::new_gnu();
+        header.set_metadata_in_mode(&std::fs::metadata(source)?, mode);
+        builder.append_data(&mut header, path, io::empty())?;
+        Ok(())
+    }
+}
+
+// Appends a PAX header holding the extended attributes of `source` to a
+// build context, so they apply to the entry appended next. Filesystems
+// without extended attributes are treated as having none.
+#[cfg(unix)]
+fn append_xattrs<W: Write>(builder: &mut Builder<W>, source: &Path) -> SeavanResult<()> {
+    let names = match xattr::list_deref(source) {
+        Ok(names) => names,
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut records = Vec::new();
+    for name in names {
+        // PAX records are keyed by UTF-8 strings, so other names are left out.
+        if let (Some(key), Some(value)) = (name.to_str(), xattr::get_deref(source, &name)?) {
+            records.push((format!("SCHILY.xattr.{}", key), value));
+        }
+    }
+    builder.append_pax_extensions(
+        records
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice())),
+    )?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn append_xattrs<W: Write>(_builder: &mut Builder<W>, _source: &Path) -> SeavanResult<()> {
+    Ok(())
+}
+
+// Lists the files in a bundle in the format of `sha256sum`, which its
+// content hash is the hash of.
+fn listing(entries: &[BundleEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{}  {}\n", entry.hash, entry.path))
+        .collect()
+}
+
+// Returns the content hash and entries of the bundle whose files were copied
+// into `directory`, from every file beneath it. The listing embedded in
+// bundles is left out.
+pub(crate) fn directory_entries(directory: &Path) -> SeavanResult<(String, Vec<BundleEntry>)> {
+    let root = directory.canonicalize()?;
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| SeavanError::FailedStrConversion)?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() && Path::new(MANIFEST_PATH).starts_with(&name) {
+            continue;
+        } else if file_type.is_dir() {
+            walk(&root, &path, &name, &mut files)?;
+        } else if file_type.is_file() || (file_type.is_symlink() && links_within(&path, &root)) {
+            files.push((name, path));
+        }
+    }
+    files.sort();
+    let entries = hash_files(files)?;
+    Ok((SeavanBundle::hash(&entries), entries))
+}
+
+// Hashes each file, keeping its path in the bundle.
+fn hash_files(files: Vec<(String, PathBuf)>) -> SeavanResult<Vec<BundleEntry>> {
+    files
+        .into_iter()
+        .map(|(path, file)| {
+            Ok(BundleEntry {
+                path,
+                hash: hash::hash_file(&file, Scheme::default())?,
+                size: std::fs::metadata(&file)?.len(),
+            })
+        })
+        .collect()
+}
+
+// Returns the name an input is copied into the image under.
+fn input_name(input: &Path) -> SeavanResult<&str> {
+    input
+        .file_name()
+        .ok_or_else(|| SeavanError::NoFileName(input.into()))?
+        .to_str()
+        .ok_or(SeavanError::FailedStrConversion)
+}
+
+// Splits the directory input `name` into the layers it's copied in with, as
+// the sources and target of each `COPY`: one for the files directly inside
+// it, if there are any, then one for each top-level subdirectory holding
+// files or one of the `empty` directories. An input which is itself empty
+// is copied whole.
+fn subdirectory_layers(
+    name: &str,
+    entries: &[BundleEntry],
+    empty: &[(String, PathBuf)],
+) -> Vec<(Vec<String>, String)> {
+    let prefix = format!("{}/", name);
+    let mut files = Vec::new();
+    let mut subdirectories: Vec<(Vec<String>, String)> = Vec::new();
+    let mut add_subdirectory = |subdirectory: &str| {
+        let source = format!("{}{}", prefix, subdirectory);
+        if !subdirectories
+            .iter()
+            .any(|(sources, _)| sources[0] == source)
+        {
+            let target = format!("/{}/", source);
+            subdirectories.push((vec![source], target));
+        }
+    };
+    for entry in entries {
+        let path = match entry.path.strip_prefix(&prefix) {
+            Some(path) => path,
+            None => continue,
+        };
+        match path.split_once('/') {
+            None => files.push(entry.path.clone()),
+            Some((subdirectory, _)) => add_subdirectory(subdirectory),
+        }
+    }
+    for (directory, _) in empty {
+        if let Some(path) = directory.strip_prefix(&prefix) {
+            add_subdirectory(path.split('/').next().unwrap_or(path));
+        }
+    }
+    let mut layers = Vec::new();
+    if !files.is_empty() {
+        layers.push((files, format!("/{}/", name)));
+    }
+    layers.extend(subdirectories);
+    if layers.is_empty() && empty.iter().any(|(directory, _)| directory == name) {
+        layers.push((vec![name.to_string()], format!("/{}/", name)));
+    }
+    layers
+}
+
+// Returns whether the symbolic link at `path` points to a file beneath
+// `root`, which is canonical. Links leaving it, such as `key ->
+// ~/.ssh/id_rsa`, are left out of bundles, so they can't copy files from
+// elsewhere on the host into an image.
+fn links_within(path: &Path, root: &Path) -> bool {
+    std::fs::canonicalize(path).is_ok_and(|target| target.is_file() && target.starts_with(root))
+}
+
+// Adds `directory` and every directory beneath it to `directories`, under
+// `prefix`. Symbolic links to directories aren't followed, as in `walk`.
+fn walk_directories(
+    directory: &Path,
+    prefix: &str,
+    directories: &mut Vec<(String, PathBuf)>,
+) -> SeavanResult<()> {
+    directories.push((prefix.into(), directory.into()));
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|_| SeavanError::FailedStrConversion)?;
+            walk_directories(&entry.path(), &format!("{}/{}", prefix, name), directories)?;
+        }
+    }
+    Ok(())
+}
+
+// Adds every file beneath `directory` to `files`, under `prefix`. Symbolic
+// links to directories aren't followed, so the walk can't loop or leave the
+// directory, and symbolic links to files are only followed if the file is
+// beneath `root`, the canonical path of the input being walked.
+fn walk(
+    root: &Path,
+    directory: &Path,
+    prefix: &str,
+    files: &mut Vec<(String, PathBuf)>,
+) -> SeavanResult<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| SeavanError::FailedStrConversion)?;
+        let path = entry.path();
+        let prefixed = format!("{}/{}", prefix, name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(root, &path, &prefixed, files)?;
+        } else if file_type.is_file() || (file_type.is_symlink() && links_within(&path, root)) {
+            files.push((prefixed, path));
+        } else {
+            debug!("Leaving {} out of the bundle", path.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Recording wrapped artifacts in an artifact catalog.
+
+use crate::error::SeavanResult;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The metadata of a wrapped artifact, recorded when its image is created
+/// or pushed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    /// The repository name and tag of the image.
+    pub reference: String,
+    /// The digest of the wrapped file's content, as in the
+    /// `seavan.content-digest` label.
+    pub digest: String,
+    /// The name of the wrapped file, unless it is hidden by the naming
+    /// strategy or a policy.
+    pub filename: Option<String>,
+    /// The size of the wrapped file in bytes, if known.
+    pub size: Option<u64>,
+    /// The labels of the image.
+    pub labels: BTreeMap<String, String>,
+    /// When the entry was recorded, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// A destination for catalog entries, such as an organization's artifact
+/// catalog or CMDB.
+///
+/// This is implemented for closures taking a `&CatalogEntry`, so a callback
+/// can be used directly.
+pub trait CatalogSink: Send + Sync {
+    /// Records a catalog entry.
+    fn record(&self, entry: &CatalogEntry) -> SeavanResult<()>;
+}
+
+impl<F> CatalogSink for F
+where
+    F: Fn(&CatalogEntry) -> SeavanResult<()> + Send + Sync,
+{
+    fn record(&self, entry: &CatalogEntry) -> SeavanResult<()> {
+        self(entry)
+    }
+}
+
+/// A catalog which appends each entry to a file as a line of JSON.
+#[derive(Debug)]
+pub struct JsonlCatalog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonlCatalog {
+    /// Creates a new `JsonlCatalog`. The file is created when the first
+    /// entry is recorded, and entries are always appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the catalog file.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{catalog::JsonlCatalog, Seavan};
+    /// use std::sync::Arc;
+    /// let wrap = Seavan::new("README.md")?
+    ///     .with_catalog(Arc::new(JsonlCatalog::new("catalog.jsonl")))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl CatalogSink for JsonlCatalog {
+    fn record(&self, entry: &CatalogEntry) -> SeavanResult<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        // Serialise writers within this process so lines never interleave.
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+// Returns the current time in seconds since the Unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// A shareable catalog sink.
+#[derive(Clone)]
+pub(crate) struct Catalog(pub(crate) Arc<dyn CatalogSink>);
+
+impl fmt::Debug for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Catalog")
+    }
+}
@@ -0,0 +1,313 @@
+//! Content-defined chunking of wrapped files.
+//!
+//! Chunked images hold a large file as a series of chunks, each in its own
+//! layer, rather than as a single layer. Chunk boundaries are chosen by the
+//! content using FastCDC, so an edit to the file only changes the chunks
+//! around it; successive versions of the file share most of their layers,
+//! and pushes only transfer the chunks which changed. Alternatively, files
+//! can be split into chunks of a fixed size, to keep every layer under a
+//! registry's size limit.
+//!
+//! Chunks are stored at `/.seavan/chunks/<sha256>`, and listed in order in
+//! the `seavan.chunks` label. The list is also written to
+//! `/.seavan/chunks.json`, so the file can be rebuilt from the image's
+//! content alone. Use `reassemble` to rebuild the file from an extracted
+//! image.
+
+use crate::error::{SeavanError, SeavanResult};
+use crate::HASH_LENGTH;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Directory holding the chunks in a chunked image.
+pub const CHUNK_DIRECTORY: &str = ".seavan/chunks";
+
+/// Path of the chunk index in a chunked image.
+pub const CHUNK_INDEX: &str = ".seavan/chunks.json";
+
+// Smallest and largest average chunk sizes allowed.
+const MIN_AVERAGE_SIZE: usize = 1024;
+const MAX_AVERAGE_SIZE: usize = 1 << 30;
+
+// Most chunks in an image; Docker's storage drivers limit the number of
+// layers in an image to around 127.
+pub(crate) const MAX_CHUNKS: usize = 100;
+
+// Generates the table of random values used by the gear hash, with
+// splitmix64 so it's the same on every platform and release.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+// Returns a mask of the top `bits` bits. The gear hash shifts left, so its
+// top bits depend on the most bytes.
+fn mask(bits: u32) -> u64 {
+    !(u64::MAX >> bits)
+}
+
+/// The sizes of the chunks a file is split into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSizes {
+    average: usize,
+    fixed: bool,
+}
+
+impl ChunkSizes {
+    /// Creates new `ChunkSizes` with the given average chunk size. Chunks are
+    /// between a quarter of and four times the average size.
+    ///
+    /// Each chunk is stored in its own layer, and images can only hold around
+    /// 100 chunks, so large files need large chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `average`: The average chunk size in bytes. This must be a power of
+    ///   two between 1 KiB and 1 GiB.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{chunk::ChunkSizes, Seavan};
+    /// let sizes = ChunkSizes::new(16 * 1024 * 1024)?;
+    /// let wrap = Seavan::new("README.md")?.with_chunking(Some(sizes))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(average: usize) -> SeavanResult<Self> {
+        let valid =
+            average.is_power_of_two() && (MIN_AVERAGE_SIZE..=MAX_AVERAGE_SIZE).contains(&average);
+        match valid {
+            true => Ok(Self {
+                average,
+                fixed: false,
+            }),
+            false => Err(SeavanError::InvalidChunkSize(average)),
+        }
+    }
+
+    /// Creates new `ChunkSizes` which split files into chunks of exactly the
+    /// given size, apart from the last. Files no larger than the size aren't
+    /// chunked at all. Use this to keep layers under a registry's size limit;
+    /// unlike content-defined chunks, an edit to the file changes every chunk
+    /// after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `size`: The chunk size in bytes, between 1 KiB and 1 GiB.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{chunk::ChunkSizes, Seavan};
+    /// let sizes = ChunkSizes::fixed(500 * 1024 * 1024)?;
+    /// let wrap = Seavan::new("README.md")?.with_chunking(Some(sizes))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fixed(size: usize) -> SeavanResult<Self> {
+        match (MIN_AVERAGE_SIZE..=MAX_AVERAGE_SIZE).contains(&size) {
+            true => Ok(Self {
+                average: size,
+                fixed: true,
+            }),
+            false => Err(SeavanError::InvalidChunkLimit(size)),
+        }
+    }
+
+    /// Returns the average chunk size in bytes. For fixed-size chunks, this
+    /// is the size of every chunk but the last.
+    pub fn average(&self) -> usize {
+        self.average
+    }
+
+    fn min(&self) -> usize {
+        match self.fixed {
+            true => self.average,
+            false => self.average / 4,
+        }
+    }
+
+    fn max(&self) -> usize {
+        match self.fixed {
+            true => self.average,
+            false => self.average * 4,
+        }
+    }
+
+    // Returns whether a file of `size` bytes is chunked. Files which fit in a
+    // single fixed-size chunk are stored whole.
+    pub(crate) fn applies_to(&self, size: u64) -> bool {
+        !self.fixed || size > self.average as u64
+    }
+
+    // Returns the value of the `CHUNKING` label describing these sizes.
+    pub(crate) fn label(&self) -> String {
+        match self.fixed {
+            true => format!("fixed;size={}", self.average),
+            false => format!("fastcdc;average-size={}", self.average),
+        }
+    }
+
+    // Returns the length of the first chunk of `data`. `data` must hold at
+    // least `max` bytes unless it's the end of the file.
+    //
+    // This uses FastCDC's normalized chunking: cut points are harder to find
+    // before the average size and easier after it, which keeps chunk sizes
+    // close to the average.
+    fn cut(&self, data: &[u8]) -> usize {
+        if self.fixed || data.len() <= self.min() {
+            return data.len().min(self.max());
+        }
+        let end = data.len().min(self.max());
+        let normal = end.min(self.average);
+        let bits = self.average.trailing_zeros();
+        let (strict, loose) = (mask(bits + 1), mask(bits - 1));
+
+        let mut hash: u64 = 0;
+        for (i, &byte) in data.iter().enumerate().take(end).skip(self.min()) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            let mask = if i < normal { strict } else { loose };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    // Splits the content read from `reader` into chunks, calling `on_chunk`
+    // with each in turn.
+    pub(crate) fn split<R, F>(&self, mut reader: R, mut on_chunk: F) -> SeavanResult<()>
+    where
+        R: Read,
+        F: FnMut(&[u8]) -> SeavanResult<()>,
+    {
+        let mut buffer = Vec::with_capacity(self.max() * 2);
+        let mut eof = false;
+        loop {
+            // Keep at least one maximum-sized chunk buffered.
+            while !eof && buffer.len() < self.max() {
+                let filled = buffer.len();
+                buffer.resize(self.max() * 2, 0);
+                let read = reader.read(&mut buffer[filled..])?;
+                buffer.truncate(filled + read);
+                eof = read == 0;
+            }
+            if buffer.is_empty() {
+                return Ok(());
+            }
+            let length = self.cut(&buffer);
+            on_chunk(&buffer[..length])?;
+            let _ = buffer.drain(..length);
+        }
+    }
+}
+
+/// The index of the chunks in a chunked image, stored in the `CHUNKS` label
+/// and at `CHUNK_INDEX`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkIndex {
+    /// The SHA-256 hash of each chunk, in order. Each chunk is stored under
+    /// its hash in `CHUNK_DIRECTORY`.
+    pub chunks: Vec<String>,
+}
+
+// Splits the content read from `reader` into chunks stored under their hash
+// in `directory`, returning the index of the chunks. Chunk files have a fixed
+// modification time, so identical chunks produce identical layers.
+pub(crate) fn stage<R: Read>(
+    reader: R,
+    sizes: &ChunkSizes,
+    directory: &Path,
+) -> SeavanResult<ChunkIndex> {
+    let mut index = ChunkIndex::default();
+    sizes.split(reader, |chunk| {
+        let hash = format!("{:x}", Sha256::digest(chunk));
+        if !index.chunks.contains(&hash) {
+            let mut file = File::create(directory.join(&hash))?;
+            file.write_all(chunk)?;
+            file.set_modified(std::time::UNIX_EPOCH)?;
+        }
+        index.chunks.push(hash);
+        Ok(())
+    })?;
+    Ok(index)
+}
+
+// Returns the path of the chunk named `hash`, relative to the root of its
+// image. Chunks are named by their hash, so names which aren't hashes, such
+// as `../../etc/passwd`, give `None` rather than a path outside
+// `CHUNK_DIRECTORY`.
+pub(crate) fn chunk_path(hash: &str) -> Option<PathBuf> {
+    let named_by_hash = hash.len() == HASH_LENGTH
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    named_by_hash.then(|| Path::new(CHUNK_DIRECTORY).join(hash))
+}
+
+/// Rebuilds a chunked file from the filesystem of its image, such as one
+/// extracted with `docker export`, checking the hash of every chunk. The
+/// chunks are read in the order listed at `CHUNK_INDEX`; use
+/// `reassemble_with_index` when the `CHUNKS` label is available. Returns the
+/// number of bytes written.
+///
+/// # Arguments
+///
+/// * `root`: The root of the image's filesystem.
+/// * `output`: Where to write the file's content.
+pub fn reassemble<W: Write + ?Sized>(root: &Path, output: &mut W) -> SeavanResult<u64> {
+    let index_path = root.join(CHUNK_INDEX);
+    let index: ChunkIndex = serde_json::from_reader(io::BufReader::new(File::open(index_path)?))?;
+    reassemble_with_index(root, &index, output)
+}
+
+/// Rebuilds a chunked file from the filesystem of its image, reading the
+/// chunks in the order given by `index`, such as one parsed from the image's
+/// `CHUNKS` label. Checks the hash of every chunk, and returns the number of
+/// bytes written. Chunks named by anything but a hash are refused with
+/// `SeavanError::UnsafeEntry` before they're read.
+///
+/// # Arguments
+///
+/// * `root`: The root of the image's filesystem.
+/// * `index`: The chunks of the file, in order.
+/// * `output`: Where to write the file's content.
+pub fn reassemble_with_index<W: Write + ?Sized>(
+    root: &Path,
+    index: &ChunkIndex,
+    output: &mut W,
+) -> SeavanResult<u64> {
+    let mut written = 0;
+    for hash in &index.chunks {
+        let path = chunk_path(hash).ok_or_else(|| {
+            SeavanError::UnsafeEntry(
+                root.display().to_string(),
+                format!("/{}/{}", CHUNK_DIRECTORY, hash),
+            )
+        })?;
+        let chunk = std::fs::read(root.join(path))?;
+        let actual = format!("{:x}", Sha256::digest(&chunk));
+        if actual != *hash {
+            return Err(SeavanError::ChunkMismatch(hash.clone(), actual));
+        }
+        output.write_all(&chunk)?;
+        written += chunk.len() as u64;
+    }
+    Ok(written)
+}
@@ -0,0 +1,65 @@
+//! Detection of CI environments building images.
+
+use crate::labels;
+
+// The labels which may describe the CI run.
+pub(crate) const LABELS: [&str; 3] = [
+    labels::CI_PROVIDER,
+    labels::CI_RUN_URL,
+    labels::CI_PIPELINE_ID,
+];
+
+// Returns labels describing the CI run, looking up environment variables with
+// `var`. No labels are returned outside of a recognised CI environment.
+pub(crate) fn labels_from<F>(var: F) -> Vec<(String, String)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let (provider, run_url, pipeline_id) = if var("GITHUB_ACTIONS").as_deref() == Some("true") {
+        let run_url = match (
+            var("GITHUB_SERVER_URL"),
+            var("GITHUB_REPOSITORY"),
+            var("GITHUB_RUN_ID"),
+        ) {
+            (Some(server), Some(repository), Some(run)) => {
+                Some(format!("{}/{}/actions/runs/{}", server, repository, run))
+            }
+            _ => None,
+        };
+        ("github-actions", run_url, var("GITHUB_RUN_ID"))
+    } else if var("GITLAB_CI").as_deref() == Some("true") {
+        ("gitlab-ci", var("CI_PIPELINE_URL"), var("CI_PIPELINE_ID"))
+    } else if var("TF_BUILD")
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+    {
+        let run_url = match (
+            var("SYSTEM_COLLECTIONURI"),
+            var("SYSTEM_TEAMPROJECT"),
+            var("BUILD_BUILDID"),
+        ) {
+            (Some(collection), Some(project), Some(build)) => Some(format!(
+                "{}{}/_build/results?buildId={}",
+                collection, project, build
+            )),
+            _ => None,
+        };
+        ("azure-pipelines", run_url, var("BUILD_BUILDID"))
+    } else {
+        return Vec::new();
+    };
+
+    let mut labels = vec![(labels::CI_PROVIDER.to_string(), provider.to_string())];
+    if let Some(run_url) = run_url {
+        labels.push((labels::CI_RUN_URL.into(), run_url));
+    }
+    if let Some(pipeline_id) = pipeline_id {
+        labels.push((labels::CI_PIPELINE_ID.into(), pipeline_id));
+    }
+    labels
+}
+
+// Returns labels describing the CI run from the process environment.
+pub(crate) fn labels() -> Vec<(String, String)> {
+    labels_from(|name| std::env::var(name).ok())
+}
@@ -0,0 +1,501 @@
+//! Operations on images which aren't tied to a single wrapped file, such as
+//! extracting, verifying, listing and removing them.
+//!
+//! The static functions on `Seavan` and the free functions in other modules
+//! use a `Client` configured from the environment. Create a `Client` to run
+//! them with a particular container engine, command runner, docker
+//! environment or registry credentials, or use `Seavan::client` to share a
+//! `Seavan`'s settings.
+
+#[cfg(feature = "async")]
+use crate::nonblocking;
+use crate::{
+    archive, compose,
+    compose::{Ancestor, ComposedInput},
+    credentials::{self, Auth},
+    docker::Docker,
+    engine::{ContainerEngine, Engine},
+    error::{SeavanError, SeavanResult},
+    extract, labels, naming,
+    prefetch::{self, PrefetchEvent},
+    registry::{self, Registry},
+    report::VerificationReport,
+    retention::{self, RemoteFilter},
+    runner::CommandRunner,
+    ENGINE_ENV_VAR, PACKAGE_ROOT,
+};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// How strictly `Client::extract_from_reference` checks the files it copies
+/// out of an image. The wrapped file itself must always be a regular file,
+/// and symlinks which resolve outside the copied files are always refused,
+/// so that a crafted image can't read or write files outside the
+/// destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtractPolicy {
+    /// Fail with `SeavanError::UnsafeEntry` if anything copied out of the
+    /// image is a symlink or special file. This is the default.
+    #[default]
+    Strict,
+
+    /// Allow symlinks which resolve within the copied files, and leave out
+    /// special files such as devices and FIFOs, for images with extra
+    /// entries alongside the wrapped file.
+    Lenient,
+}
+
+/// Runs docker commands on images built by seavan, with the configured
+/// container engine, command runner, environment and registry credentials.
+#[derive(Debug, Clone, Default)]
+pub struct Client {
+    pub(crate) docker: Docker,
+    extract_policy: ExtractPolicy,
+    package_root: Option<String>,
+}
+
+impl Client {
+    /// Creates a new `Client` using the container engine named by the
+    /// `SEAVAN_ENGINE` environment variable, if it is set, as `Seavan::new`
+    /// does.
+    ///
+    /// Fails with `SeavanError::InvalidEngine` if `SEAVAN_ENGINE` doesn't name
+    /// a container engine.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::client::Client;
+    /// let client = Client::new()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new() -> SeavanResult<Self> {
+        let mut client = Self::default();
+        if let Some(engine) = std::env::var_os(ENGINE_ENV_VAR) {
+            let engine = engine.to_str().ok_or(SeavanError::FailedStrConversion)?;
+            if !engine.is_empty() {
+                client = client.with_engine(engine.parse::<Engine>()?);
+            }
+        }
+        Ok(client)
+    }
+
+    // Creates a `Client` which runs docker with the given settings, for
+    // images under `package_root`.
+    pub(crate) fn from_docker(docker: Docker, package_root: &str) -> Self {
+        Self {
+            docker,
+            package_root: Some(package_root.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Adds an environment variable to pass to every docker command, as
+    /// `Seavan::with_docker_env` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The name of the environment variable.
+    /// * `value`: The value of the environment variable.
+    pub fn with_docker_env(mut self, key: &str, value: &str) -> Self {
+        self.docker.add_env(key, value);
+        self
+    }
+
+    /// Sets the runner used to execute docker commands, as
+    /// `Seavan::with_command_runner` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `runner`: The runner to execute docker commands with.
+    pub fn with_command_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.docker.set_runner(runner);
+        self
+    }
+
+    /// Sets the container engine used in place of Docker, as
+    /// `Seavan::with_engine` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine`: The container engine to use.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{client::Client, engine::Engine};
+    /// let client = Client::new()?.with_engine(Engine::Podman);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_engine<E: ContainerEngine + 'static>(mut self, engine: E) -> Self {
+        self.docker.set_engine(Arc::new(engine));
+        self
+    }
+
+    /// Authenticates to `registry` with a username and a password or access
+    /// token, as `Seavan::with_credentials` does. The credentials are only
+    /// used for images in `registry`, so credentials for several registries
+    /// can be given; giving them again for the same registry replaces them.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry`: The registry host, e.g. `registry.example.com`.
+    /// * `username`: The user to authenticate as.
+    /// * `password`: The user's password or access token.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::client::Client;
+    /// let path = Client::new()?
+    ///     .with_credentials("registry.example.com", "ci", &std::env::var("REGISTRY_TOKEN")?)?
+    ///     .extract_from_reference("registry.example.com/seavanpkg/abcd--readme-md:latest", "extracted")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_credentials(
+        mut self,
+        registry: &str,
+        username: &str,
+        password: &str,
+    ) -> SeavanResult<Self> {
+        let auth = Auth::Basic(username.into(), password.into());
+        self.docker
+            .set_registry_config(credentials::write_config(registry, &auth)?);
+        Ok(self)
+    }
+
+    /// Authenticates to `registry` with a docker credential helper, as
+    /// `Seavan::with_credential_helper` does. The helper is only used for
+    /// images in `registry`.
+    ///
+    /// Fails with `SeavanError::InvalidCredentialHelper` if `helper` isn't a
+    /// valid program name suffix.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry`: The registry host, e.g. `registry.example.com`.
+    /// * `helper`: The name of the credential helper, e.g. `ecr-login`.
+    pub fn with_credential_helper(mut self, registry: &str, helper: &str) -> SeavanResult<Self> {
+        credentials::validate_helper(helper)?;
+        let auth = Auth::Helper(helper.into());
+        self.docker
+            .set_registry_config(credentials::write_config(registry, &auth)?);
+        Ok(self)
+    }
+
+    /// Pulls images in `registry` through the pull-through mirror `mirror`,
+    /// as `Seavan::with_registry_mirror` does. Mirrors are used by
+    /// `extract_from_reference` and `prefetch`, and are tried in the order
+    /// they were added before falling back to `registry` itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry`: The registry host, e.g. `registry.example.com`, or
+    ///   `docker.io` for Docker Hub.
+    /// * `mirror`: The mirror's host and optional path prefix, e.g.
+    ///   `mirror.internal:5000/hub`.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::client::Client;
+    /// let client = Client::new()?.with_mirror("docker.io", "mirror.internal:5000");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_mirror(mut self, registry: &str, mirror: &str) -> Self {
+        self.docker.add_mirror(registry, mirror);
+        self
+    }
+
+    /// Sets how strictly `extract_from_reference` checks the files it copies
+    /// out of an image. Defaults to `ExtractPolicy::Strict`.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy`: The policy to check copied files with.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::client::{Client, ExtractPolicy};
+    /// let client = Client::new()?.with_extract_policy(ExtractPolicy::Lenient);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_extract_policy(mut self, policy: ExtractPolicy) -> Self {
+        self.extract_policy = policy;
+        self
+    }
+
+    /// Specifies the repository path prefix which images are stored under,
+    /// as `Seavan::with_package_root` does, so `prune_remote` and
+    /// `prune_keep_latest_remote` look for images under it. Defaults to
+    /// `seavanpkg`.
+    ///
+    /// Fails with `SeavanError::InvalidPackageRoot` if `package_root` isn't a
+    /// valid repository path.
+    ///
+    /// # Arguments
+    ///
+    /// * `package_root`: The repository path prefix images are stored under.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::client::Client;
+    /// let client = Client::new()?.with_package_root("data/seavanpkg")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_package_root(mut self, package_root: &str) -> SeavanResult<Self> {
+        naming::validate_package_root(package_root)?;
+        self.package_root = Some(package_root.into());
+        Ok(self)
+    }
+
+    // Returns the repository path prefix which images are stored under.
+    fn package_root(&self) -> &str {
+        self.package_root.as_deref().unwrap_or(PACKAGE_ROOT)
+    }
+
+    /// Extracts the file wrapped in the image `reference` into `destination`,
+    /// as `Seavan::extract_from_reference` does. Files copied out of the
+    /// image are checked according to the policy set with
+    /// `with_extract_policy`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: The image to extract the file from.
+    /// * `destination`: The directory to extract the file into. It's created
+    ///   if it doesn't exist.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{client::Client, engine::Engine};
+    /// let path = Client::new()?
+    ///     .with_engine(Engine::Podman)
+    ///     .extract_from_reference("acr.azurecr.io/seavanpkg/abcd--readme-md:latest", "extracted")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn extract_from_reference<P: AsRef<Path>>(
+        &self,
+        reference: &str,
+        destination: P,
+    ) -> SeavanResult<PathBuf> {
+        extract::extract(
+            &self.docker,
+            reference,
+            destination.as_ref(),
+            self.extract_policy,
+        )
+    }
+
+    /// Extracts the file wrapped in the image `reference` into `destination`,
+    /// as `extract_from_reference` does, without blocking the calling task.
+    /// The image is pulled with `tokio::process`; see the `nonblocking`
+    /// module.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: The image to extract the file from.
+    /// * `destination`: The directory to extract the file into. It's created
+    ///   if it doesn't exist.
+    #[cfg(feature = "async")]
+    pub async fn extract_from_reference_async<P: AsRef<Path>>(
+        &self,
+        reference: &str,
+        destination: P,
+    ) -> SeavanResult<PathBuf> {
+        extract::check_reference(reference)?;
+        let (docker, owned) = (self.docker.clone(), reference.to_string());
+        let local = nonblocking::blocking(move || Ok(docker.image_id(&owned).is_ok())).await?;
+        if !local {
+            self.docker.pull_async(reference).await?;
+        }
+
+        let (docker, reference) = (self.docker.clone(), reference.to_string());
+        let (destination, policy) = (destination.as_ref().to_path_buf(), self.extract_policy);
+        nonblocking::blocking(move || extract::extract(&docker, &reference, &destination, policy))
+            .await
+    }
+
+    /// Checks that a file, or a directory holding a bundle's files, holds the
+    /// content wrapped in the image `reference`, as `Seavan::verify` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: The image the file should match.
+    /// * `path`: The file or directory to check.
+    pub fn verify<P: AsRef<Path>>(
+        &self,
+        reference: &str,
+        path: P,
+    ) -> SeavanResult<VerificationReport> {
+        extract::verify(&self.docker, reference, path.as_ref())
+    }
+
+    /// Returns the references of every local image built by seavan.
+    pub fn list_images(&self) -> SeavanResult<Vec<String>> {
+        self.docker
+            .local_references_with_label(labels::CONTENT_DIGEST, None)
+    }
+
+    /// Removes the local image `reference`. Only images built by seavan are
+    /// removed; other images fail with `SeavanError::NotWrapped`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: The image to remove.
+    pub fn remove_image(&self, reference: &str) -> SeavanResult<()> {
+        naming::validate_reference(reference)?;
+        if self
+            .docker
+            .local_label_value(reference, labels::CONTENT_DIGEST)
+            .is_none()
+        {
+            return Err(SeavanError::NotWrapped(reference.into()));
+        }
+        self.docker.remove_image(reference)
+    }
+
+    /// Loads an image saved by `Seavan::save_to_tar` into the local image
+    /// store, as `Seavan::load_from_tar` does.
+    ///
+    /// Returns the repository name and tag of the loaded image.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The archive to load.
+    pub fn load_from_tar<P: AsRef<Path>>(&self, path: P) -> SeavanResult<String> {
+        archive::load(&self.docker, path.as_ref())
+    }
+
+    /// Pulls images ahead of time, as `prefetch::prefetch` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `references`: The images to pull.
+    /// * `parallelism`: The most images to pull at once.
+    /// * `on_event`: Called with progress events.
+    pub fn prefetch<F>(
+        &self,
+        references: &[&str],
+        parallelism: usize,
+        on_event: F,
+    ) -> Vec<SeavanResult<()>>
+    where
+        F: Fn(PrefetchEvent<'_>) + Sync,
+    {
+        prefetch::prefetch_with(&self.docker, references, parallelism, on_event)
+    }
+
+    /// Removes older content versions of a wrapped file from the local image
+    /// store, as `retention::prune_keep_latest` does.
+    ///
+    /// Returns the references which were removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename`: The name of the wrapped file, without any directory.
+    /// * `keep`: The number of content versions to keep.
+    pub fn prune_keep_latest<S: AsRef<OsStr> + ?Sized>(
+        &self,
+        filename: &S,
+        keep: usize,
+    ) -> SeavanResult<Vec<String>> {
+        retention::prune_generations(&self.docker, filename.as_ref(), keep, |_| Ok(()))
+    }
+
+    /// Removes older content versions of a wrapped file from a registry, as
+    /// `retention::prune_keep_latest_remote` does, under the package root set
+    /// with `with_package_root`. Registry API requests use the credentials
+    /// given for the registry with `with_credentials`, if any.
+    ///
+    /// Returns the references which were removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry`: The registry to remove images from.
+    /// * `filename`: The name of the wrapped file, without any directory.
+    /// * `keep`: The number of content versions to keep.
+    pub fn prune_keep_latest_remote<S: AsRef<OsStr> + ?Sized>(
+        &self,
+        registry: &Registry,
+        filename: &S,
+        keep: usize,
+    ) -> SeavanResult<Vec<String>> {
+        retention::prune_remote_generations(
+            &self.docker,
+            registry,
+            self.package_root(),
+            filename.as_ref(),
+            keep,
+            |_| Ok(()),
+        )
+    }
+
+    /// Removes seavan images matching `filter` from a registry, as
+    /// `retention::prune_remote` does, under the package root set with
+    /// `with_package_root`. Registry API requests use the credentials given
+    /// for the registry with `with_credentials`, if any.
+    ///
+    /// Returns the references which were removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry`: The registry to remove images from.
+    /// * `filter`: Selects the images to remove.
+    pub fn prune_remote(
+        &self,
+        registry: &Registry,
+        filter: &RemoteFilter,
+    ) -> SeavanResult<Vec<String>> {
+        retention::prune_remote_with(&self.docker, registry, self.package_root(), filter)
+    }
+
+    /// Reads the size of the file wrapped in a remote image, as
+    /// `labels::payload_size` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: The image reference to inspect.
+    pub fn payload_size(&self, reference: &str) -> SeavanResult<Option<u64>> {
+        labels::remote_payload_size(&self.docker, reference)
+    }
+
+    /// Reads the inputs of an aggregate image, as `compose::inputs_of` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate_ref`: The reference of the aggregate image.
+    pub fn inputs_of(&self, aggregate_ref: &str) -> SeavanResult<Vec<ComposedInput>> {
+        compose::read_inputs(&self.docker, aggregate_ref)
+    }
+
+    /// Reads every image an aggregate image was built from, as
+    /// `compose::lineage` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate_ref`: The reference of the aggregate image.
+    pub fn lineage(&self, aggregate_ref: &str) -> SeavanResult<Vec<Ancestor>> {
+        compose::read_lineage(&self.docker, aggregate_ref)
+    }
+
+    /// Checks whether the image `reference` can be fetched without
+    /// credentials, as `registry::check_public_access` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: The image reference to check.
+    pub fn check_public_access(&self, reference: &str) -> SeavanResult<bool> {
+        registry::public_access(&self.docker, reference)
+    }
+}
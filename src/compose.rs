@@ -0,0 +1,317 @@
+//! Composing wrapped files into aggregate images.
+//!
+//! An aggregate image holds the files of several seavan images in a single
+//! image. Each input is resolved to its image ID when the aggregate is
+//! built, and copied from a temporary tag of that ID, so a tag moved while
+//! the aggregate is building can't change what's copied. The inputs are
+//! recorded in the aggregate's
+//! `labels::COMPOSED_INPUTS` label, so the exact input set can be recovered
+//! later with `inputs_of` for audits and rebuilds. Aggregates can themselves
+//! be composed, and `lineage` walks the inputs of each in turn to recover
+//! every image an aggregate was built from.
+
+use crate::{
+    client::Client,
+    docker::{self, Docker},
+    engine::ContainerEngine,
+    error::{SeavanError, SeavanResult},
+    labels, naming,
+    runner::CommandRunner,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{Seek, Write};
+use std::sync::Arc;
+use tempfile::tempfile;
+
+/// An input of an aggregate image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComposedInput {
+    /// The repository name and tag of the input image.
+    pub reference: String,
+    /// The ID of the input image when the aggregate was built. This is the
+    /// digest of the image's configuration, so it identifies the image
+    /// exactly wherever it's pulled from.
+    pub digest: String,
+}
+
+/// An image an aggregate was built from, directly or through other
+/// aggregates, as returned by `lineage`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ancestor {
+    /// The input, as recorded when it was composed.
+    pub input: ComposedInput,
+    /// The reference of the aggregate image the input was composed into.
+    pub composed_into: String,
+    /// How many compositions the input is away from the aggregate whose
+    /// lineage was read. The aggregate's own inputs are at depth 1.
+    pub depth: usize,
+}
+
+/// A set of seavan images which are composed into an aggregate image.
+#[derive(Debug, Default)]
+pub struct Composition {
+    inputs: Vec<String>,
+    docker: Docker,
+}
+
+impl Composition {
+    /// Creates a new `Composition` of the given images. Files in later
+    /// images replace files of the same name in earlier ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs`: The references of the images to compose.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::compose::Composition;
+    /// let inputs = Composition::new(&[
+    ///     "seavanpkg/abcd--readme-md:latest",
+    ///     "seavanpkg/ef01--data-csv:latest",
+    /// ])
+    /// .create_image("example/bundle:latest")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(inputs: &[&str]) -> Self {
+        Self {
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            docker: Docker::default(),
+        }
+    }
+
+    /// Sets the runner used to execute docker commands.
+    ///
+    /// # Arguments
+    ///
+    /// * `runner`: The runner to execute docker commands with.
+    pub fn with_command_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.docker.set_runner(runner);
+        self
+    }
+
+    /// Sets the container engine used in place of Docker, as
+    /// `Seavan::with_engine` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine`: The container engine to use.
+    pub fn with_engine<E: ContainerEngine + 'static>(mut self, engine: E) -> Self {
+        self.docker.set_engine(Arc::new(engine));
+        self
+    }
+
+    /// Builds the aggregate image and tags it as `reference`. Returns the
+    /// resolved inputs, as recorded in the image's `labels::COMPOSED_INPUTS`
+    /// label.
+    ///
+    /// The input images must exist locally. Fails with
+    /// `SeavanError::InvalidReference` if the reference of the aggregate or
+    /// of any input isn't valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: The repository name and tag of the aggregate image.
+    pub fn create_image(&self, reference: &str) -> SeavanResult<Vec<ComposedInput>> {
+        naming::validate_reference(reference)?;
+        for input in &self.inputs {
+            naming::validate_reference(input)?;
+        }
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                Ok(ComposedInput {
+                    reference: input.clone(),
+                    digest: self.docker.image_id(input)?,
+                })
+            })
+            .collect::<SeavanResult<Vec<_>>>()?;
+
+        // Each input is copied from a temporary tag of the image it resolved
+        // to, which is dropped once the aggregate is built.
+        let mut pinned = Vec::new();
+        let built = self.build(&inputs, &mut pinned, reference);
+        for temporary in pinned {
+            if let Err(e) = self.docker.remove_image(&temporary) {
+                debug!("Failed to remove {}: {}", temporary, e);
+            }
+        }
+        built.map(|()| inputs)
+    }
+
+    // Tags each input's image under a temporary reference, adding it to
+    // `pinned`, then builds the aggregate from them as `reference`.
+    fn build(
+        &self,
+        inputs: &[ComposedInput],
+        pinned: &mut Vec<String>,
+        reference: &str,
+    ) -> SeavanResult<()> {
+        for input in inputs {
+            let temporary = docker::temporary_reference();
+            self.docker.tag(&input.digest, &temporary)?;
+            pinned.push(temporary);
+        }
+
+        let mut dockerfile = tempfile()?;
+        dockerfile.write_all(Self::dockerfile(inputs, pinned)?.as_bytes())?;
+        dockerfile.rewind()?;
+
+        // The Dockerfile is the whole build context, as nothing is copied
+        // from the host.
+        let temporary_reference = docker::temporary_reference();
+        debug!(
+            "Composing under temporary reference {}",
+            temporary_reference
+        );
+        let output = self.docker.run(
+            self.docker
+                .command()
+                .args(["build", "-t", &temporary_reference, "-"])
+                .env("DOCKER_BUILDKIT", "1"),
+            Some(dockerfile),
+        )?;
+        if !output.status.success() {
+            return Err(docker::build_failure(output));
+        }
+        docker::log_output(&output);
+
+        self.docker.promote(&temporary_reference, reference)
+    }
+
+    // Generates the Dockerfile copying every input into one image, from the
+    // references each is pinned under.
+    fn dockerfile(inputs: &[ComposedInput], pinned: &[String]) -> SeavanResult<String> {
+        let mut dockerfile = String::from("FROM scratch\n");
+        for reference in pinned {
+            dockerfile.push_str(&format!("COPY --from={} / /\n", reference));
+        }
+        dockerfile.push_str(&format!(
+            "LABEL {}={}\n",
+            labels::dockerfile_quote(labels::FORMAT_VERSION),
+            labels::dockerfile_quote(&labels::CURRENT_FORMAT_VERSION.to_string())
+        ));
+        dockerfile.push_str(&format!(
+            "LABEL {}={}\n",
+            labels::dockerfile_quote(labels::COMPOSED_INPUTS),
+            labels::dockerfile_quote(&serde_json::to_string(inputs)?)
+        ));
+        Ok(dockerfile)
+    }
+}
+
+/// Returns the inputs an aggregate image was composed from, as recorded
+/// when it was built. The local image is inspected if it exists; otherwise
+/// only the image configuration is fetched from the registry.
+///
+/// Fails with `SeavanError::NotComposed` if the image can't be inspected or
+/// isn't an aggregate image, or `SeavanError::UnsupportedFormatVersion` if it
+/// was built by a later version of seavan.
+///
+/// # Arguments
+///
+/// * `aggregate_ref`: The reference of the aggregate image.
+pub fn inputs_of(aggregate_ref: &str) -> SeavanResult<Vec<ComposedInput>> {
+    Client::new()?.inputs_of(aggregate_ref)
+}
+
+/// Returns every image an aggregate image was built from: its inputs, then
+/// the inputs of any of them which are aggregates themselves, and so on,
+/// depth first in the order they were composed. Inputs which aren't
+/// aggregates end their branch of the lineage.
+///
+/// Each input is read through its recorded reference, as `inputs_of` reads
+/// the aggregate, so an input whose tag has since been moved is read as the
+/// image the tag now points to. Images already in the lineage aren't walked
+/// again.
+///
+/// Fails as `inputs_of` does if `aggregate_ref` isn't an aggregate image.
+///
+/// # Arguments
+///
+/// * `aggregate_ref`: The reference of the aggregate image.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::compose::lineage;
+/// for ancestor in lineage("example/bundle:latest")? {
+///     println!("{}{}", "  ".repeat(ancestor.depth), ancestor.input.reference);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn lineage(aggregate_ref: &str) -> SeavanResult<Vec<Ancestor>> {
+    Client::new()?.lineage(aggregate_ref)
+}
+
+// Reads the lineage of an aggregate image using the given docker settings.
+pub(crate) fn read_lineage(docker: &Docker, aggregate_ref: &str) -> SeavanResult<Vec<Ancestor>> {
+    let mut lineage = Vec::new();
+    let mut seen = HashSet::new();
+    walk_lineage(
+        docker,
+        aggregate_ref,
+        read_inputs(docker, aggregate_ref)?,
+        1,
+        &mut seen,
+        &mut lineage,
+    )?;
+    Ok(lineage)
+}
+
+// Adds `inputs`, composed into `aggregate_ref`, and their own inputs to
+// `lineage`, skipping images whose ID is already in `seen`.
+fn walk_lineage(
+    docker: &Docker,
+    aggregate_ref: &str,
+    inputs: Vec<ComposedInput>,
+    depth: usize,
+    seen: &mut HashSet<String>,
+    lineage: &mut Vec<Ancestor>,
+) -> SeavanResult<()> {
+    for input in inputs {
+        if !seen.insert(input.digest.clone()) {
+            continue;
+        }
+        let parents = match read_inputs(docker, &input.reference) {
+            Ok(parents) => parents,
+            Err(SeavanError::NotComposed(_)) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let reference = input.reference.clone();
+        lineage.push(Ancestor {
+            input,
+            composed_into: aggregate_ref.into(),
+            depth,
+        });
+        walk_lineage(docker, &reference, parents, depth + 1, seen, lineage)?;
+    }
+    Ok(())
+}
+
+// Reads the inputs of an aggregate image using the given docker settings.
+pub(crate) fn read_inputs(
+    docker: &Docker,
+    aggregate_ref: &str,
+) -> SeavanResult<Vec<ComposedInput>> {
+    let (value, version) = match docker.local_label_value(aggregate_ref, labels::COMPOSED_INPUTS) {
+        Some(value) => (
+            value,
+            docker.local_label_value(aggregate_ref, labels::FORMAT_VERSION),
+        ),
+        None => (
+            docker
+                .remote_label_value(aggregate_ref, labels::COMPOSED_INPUTS)
+                .ok_or_else(|| SeavanError::NotComposed(aggregate_ref.into()))?,
+            docker.remote_label_value(aggregate_ref, labels::FORMAT_VERSION),
+        ),
+    };
+    let _ = labels::check_format_version(aggregate_ref, version.as_deref())?;
+    serde_json::from_str(&value)
+        .map_err(|_| SeavanError::InvalidLabel(labels::COMPOSED_INPUTS.into(), value))
+}
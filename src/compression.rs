@@ -0,0 +1,126 @@
+//! Compression of wrapped files.
+//!
+//! Compressed images hold the wrapped file compressed, with the algorithm's
+//! extension added to its name, e.g. `/data.csv.gz`. The content hash, and so
+//! the image's name, is still that of the uncompressed file, and
+//! `Seavan::extract` decompresses the file before verifying it.
+//!
+//! Files are compressed and decompressed with the `gzip` and `zstd` command
+//! line tools, which must be installed wherever images are built or
+//! extracted.
+
+use crate::error::{SeavanError, SeavanResult};
+use std::fs::File;
+use std::path::Path;
+use std::process::Command;
+
+/// The algorithms wrapped files can be compressed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip, which is installed almost everywhere.
+    Gzip {
+        /// The compression level, from 1 (fastest) to 9 (smallest).
+        level: u32,
+    },
+
+    /// Zstandard, which compresses better and decompresses much faster than
+    /// gzip.
+    Zstd {
+        /// The compression level, from 1 (fastest) to 19 (smallest).
+        level: u32,
+    },
+}
+
+impl Compression {
+    // Checks that the compression level is in range for the algorithm.
+    pub(crate) fn validate(&self) -> SeavanResult<()> {
+        let (level, range) = match *self {
+            Compression::Gzip { level } => (level, 1..=9),
+            Compression::Zstd { level } => (level, 1..=19),
+        };
+        match range.contains(&level) {
+            true => Ok(()),
+            false => Err(SeavanError::InvalidCompressionLevel(level)),
+        }
+    }
+
+    /// Returns the extension added to the names of compressed files, e.g.
+    /// `.gz`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip { .. } => ".gz",
+            Compression::Zstd { .. } => ".zst",
+        }
+    }
+
+    // Returns the value of the `COMPRESSION` label describing this
+    // compression, e.g. `gzip;level=6`.
+    pub(crate) fn label(&self) -> String {
+        match *self {
+            Compression::Gzip { level } => format!("gzip;level={}", level),
+            Compression::Zstd { level } => format!("zstd;level={}", level),
+        }
+    }
+
+    // Parses the value of a `COMPRESSION` label. Only the algorithm is
+    // needed to decompress a file, so the level is optional.
+    pub(crate) fn from_label(label: &str) -> Option<Self> {
+        let mut parameters = label.split(';');
+        let algorithm = parameters.next()?;
+        let level = match parameters.next() {
+            Some(level) => level.strip_prefix("level=")?.parse().ok()?,
+            None => 0,
+        };
+        match algorithm {
+            "gzip" => Some(Compression::Gzip { level }),
+            "zstd" => Some(Compression::Zstd { level }),
+            _ => None,
+        }
+    }
+
+    // Returns the command line tool for the algorithm.
+    fn program(&self) -> &'static str {
+        match self {
+            Compression::Gzip { .. } => "gzip",
+            Compression::Zstd { .. } => "zstd",
+        }
+    }
+
+    // Runs the command line tool with `args`, reading from `source` and
+    // writing to `target`.
+    fn run(&self, args: &[&str], source: File, target: File) -> SeavanResult<()> {
+        let output = Command::new(self.program())
+            .args(args)
+            .stdin(source)
+            .stdout(target)
+            .output()
+            .map_err(|e| SeavanError::CompressionFailure(format!("{}: {}", self.program(), e)))?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(SeavanError::CompressionFailure(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            )),
+        }
+    }
+
+    // Compresses `source` into `target`.
+    pub(crate) fn compress(&self, source: File, target: File) -> SeavanResult<()> {
+        match *self {
+            Compression::Gzip { level } => {
+                self.run(&["-c", "-n", &format!("-{}", level)], source, target)
+            }
+            Compression::Zstd { level } => {
+                self.run(&["-q", "-c", &format!("-{}", level)], source, target)
+            }
+        }
+    }
+
+    // Decompresses the file at `source` into a new file at `target`.
+    pub(crate) fn decompress(&self, source: &Path, target: &Path) -> SeavanResult<()> {
+        self.run(
+            &["-d", "-q", "-c"],
+            File::open(source)?,
+            File::create(target)?,
+        )
+    }
+}
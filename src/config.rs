@@ -0,0 +1,140 @@
+//! Configuration files holding shared defaults.
+//!
+//! Defaults are read from a global configuration file at
+//! `~/.config/seavan/config.toml` (or `$XDG_CONFIG_HOME/seavan/config.toml`)
+//! and from the nearest project-local `.seavan.toml`, found by searching from
+//! a directory upwards. Project settings take precedence over global ones.
+//!
+//! ```toml
+//! registry = "acr.azurecr.io"
+//! package_root = "seavanpkg"
+//! tag_policy = "short-hash"
+//!
+//! [labels]
+//! "com.example.team" = "data"
+//!
+//! [[policies]]
+//! extensions = ["key"]
+//! action = "reject"
+//!
+//! [rules]
+//! allowed_registries = ["acr.azurecr.io"]
+//! ```
+//!
+//! See the `policy` and `rules` modules for the policy and rule settings.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    naming::TagPolicy,
+    policy::Policy,
+    rules::Rules,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the project-local configuration file.
+pub const PROJECT_CONFIG_FILE: &str = ".seavan.toml";
+
+/// Default settings for `Seavan`s, as read from configuration files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+    /// The registry to store images in.
+    pub registry: Option<String>,
+
+    /// The repository path prefix which all images are stored under.
+    pub package_root: Option<String>,
+
+    /// The policy for tagging images which aren't given a tag.
+    pub tag_policy: Option<TagPolicy>,
+
+    /// Additional labels to add to every image.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
+    /// Policies to apply to every wrapped file.
+    #[serde(default)]
+    pub policies: Vec<Policy>,
+
+    /// Rules which every wrapped file must follow.
+    #[serde(default)]
+    pub rules: Rules,
+}
+
+impl Config {
+    /// Loads a configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the TOML configuration file.
+    pub fn load<P: AsRef<Path>>(path: P) -> SeavanResult<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| SeavanError::InvalidConfig(path.to_path_buf(), e))
+    }
+
+    /// Loads and merges the global configuration file and the nearest
+    /// project-local configuration file at or above `directory`. Missing files
+    /// are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory`: The directory to search for a project-local
+    ///   configuration file from.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::config::Config;
+    /// let config = Config::discover(std::env::current_dir()?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn discover<P: AsRef<Path>>(directory: P) -> SeavanResult<Self> {
+        let mut config = match global_config_path() {
+            Some(path) if path.is_file() => Config::load(path)?,
+            _ => Config::default(),
+        };
+
+        let project = directory
+            .as_ref()
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+            .find(|path| path.is_file());
+        if let Some(path) = project {
+            config = config.merge(Config::load(path)?);
+        }
+        Ok(config)
+    }
+
+    /// Merges two configurations. Settings in `overrides` take precedence,
+    /// and labels, policies and rules from both are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `overrides`: The configuration to merge over this one.
+    pub fn merge(mut self, overrides: Config) -> Self {
+        if overrides.registry.is_some() {
+            self.registry = overrides.registry;
+        }
+        if overrides.package_root.is_some() {
+            self.package_root = overrides.package_root;
+        }
+        if overrides.tag_policy.is_some() {
+            self.tag_policy = overrides.tag_policy;
+        }
+        self.labels.extend(overrides.labels);
+        self.policies.extend(overrides.policies);
+        self.rules = self.rules.merge(overrides.rules);
+        self
+    }
+}
+
+// Returns the path of the global configuration file.
+fn global_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("seavan").join("config.toml"))
+}
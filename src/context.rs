@@ -0,0 +1,90 @@
+//! Build contexts streamed to Docker.
+
+use crate::{
+    error::SeavanResult,
+    hash::{ContentHasher, Scheme},
+    Seavan,
+};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use tar::{Builder, Header, HeaderMode};
+
+/// Path of the Dockerfile within a streamed context. Wrapped files are always
+/// at the top level, so this can't clash with them.
+pub(crate) const DOCKERFILE_PATH: &str = ".seavan/Dockerfile";
+
+// Reader which hashes and counts everything read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: ContentHasher,
+    count: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+// Writes a tar build context holding the wrapped file followed by its
+// Dockerfile, returning the content hash. The Dockerfile depends on the hash,
+// so it comes last; Docker reads the whole context before building.
+pub(crate) fn write<W: Write>(writer: W, wrap: &Seavan) -> SeavanResult<String> {
+    let mut builder = Builder::new(writer);
+    let hash = append_hashed(
+        &mut builder,
+        &wrap.filename_lossy()?,
+        wrap.open()?,
+        wrap.hash_scheme,
+        HeaderMode::Complete,
+    )?;
+
+    let dockerfile = wrap.dockerfile(&hash)?;
+    append_dockerfile(builder, &dockerfile)?;
+    Ok(hash)
+}
+
+// Appends `file` to a build context at `path`, with its metadata recorded in
+// `mode`, hashing it as it's written, and returns its hash.
+pub(crate) fn append_hashed<W: Write>(
+    builder: &mut Builder<W>,
+    path: &str,
+    file: File,
+    scheme: Scheme,
+    mode: HeaderMode,
+) -> SeavanResult<String> {
+    let metadata = file.metadata()?;
+    let mut reader = HashingReader {
+        inner: file.take(metadata.len()),
+        hasher: ContentHasher::new(scheme),
+        count: 0,
+    };
+
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(&metadata, mode);
+    builder.append_data(&mut header, path, &mut reader)?;
+    if reader.count != metadata.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "file was truncated while it was being streamed",
+        )
+        .into());
+    }
+    Ok(reader.hasher.finalize())
+}
+
+// Appends the Dockerfile to a build context and finishes it.
+pub(crate) fn append_dockerfile<W: Write>(
+    mut builder: Builder<W>,
+    dockerfile: &str,
+) -> SeavanResult<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(dockerfile.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, DOCKERFILE_PATH, dockerfile.as_bytes())?;
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! Registry credentials, given to docker through an isolated configuration
+//! directory so the user's own docker configuration is never changed.
+
+use crate::{
+    docker,
+    error::{SeavanError, SeavanResult},
+};
+use serde_json::json;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+// Key docker uses for Docker Hub's credentials.
+const DOCKER_HUB_KEY: &str = "https://index.docker.io/v1/";
+
+// Hostnames which refer to Docker Hub.
+const DOCKER_HUB_HOSTS: &[&str] = &["docker.io", "index.docker.io", "registry-1.docker.io"];
+
+// Alphabet of standard base64.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How to authenticate to a registry.
+#[derive(Clone)]
+pub(crate) enum Auth {
+    /// A username with a password or access token.
+    Basic(String, String),
+
+    /// A docker credential helper, e.g. `ecr-login` for
+    /// `docker-credential-ecr-login`.
+    Helper(String),
+}
+
+// Hides the password from debug output.
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::Basic(username, _) => f
+                .debug_tuple("Basic")
+                .field(username)
+                .field(&"<redacted>")
+                .finish(),
+            Auth::Helper(helper) => f.debug_tuple("Helper").field(helper).finish(),
+        }
+    }
+}
+
+// Checks that `helper` names a credential helper program suffix.
+pub(crate) fn validate_helper(helper: &str) -> SeavanResult<()> {
+    let valid = !helper.is_empty()
+        && helper
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+    match valid {
+        true => Ok(()),
+        false => Err(SeavanError::InvalidCredentialHelper(helper.into())),
+    }
+}
+
+// A docker configuration directory holding the credentials for a single
+// registry.
+#[derive(Debug)]
+pub(crate) struct RegistryConfig {
+    key: String,
+    auth: Auth,
+    directory: TempDir,
+}
+
+impl RegistryConfig {
+    // Returns the configuration directory.
+    pub(crate) fn path(&self) -> &Path {
+        self.directory.path()
+    }
+
+    // Returns the key the credentials are stored under, which identifies
+    // their registry.
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+
+    // Returns whether the credentials are for the registry holding the image
+    // `reference`. References without a registry host are on Docker Hub.
+    pub(crate) fn applies_to(&self, reference: &str) -> bool {
+        let (registry, _) = docker::split_registry(reference);
+        registry_key(registry) == self.key
+    }
+
+    // Returns the username and password to authenticate with, asking the
+    // credential helper for them if there is one.
+    pub(crate) fn basic(&self) -> SeavanResult<(String, String)> {
+        let helper = match &self.auth {
+            Auth::Basic(username, password) => return Ok((username.clone(), password.clone())),
+            Auth::Helper(helper) => helper,
+        };
+        let program = format!("docker-credential-{}", helper);
+        let mut child = std::process::Command::new(&program)
+            .arg("get")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            std::io::Write::write_all(&mut stdin, self.key.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(SeavanError::RegistryAuthFailure(format!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stdout).trim()
+            )));
+        }
+        let credentials: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        match (
+            credentials["Username"].as_str(),
+            credentials["Secret"].as_str(),
+        ) {
+            (Some(username), Some(secret)) => Ok((username.into(), secret.into())),
+            _ => Err(SeavanError::RegistryAuthFailure(format!(
+                "{} returned no credentials",
+                program
+            ))),
+        }
+    }
+}
+
+// Returns the key docker stores credentials for `registry` under: its host,
+// or Docker Hub's legacy URL.
+fn registry_key(registry: &str) -> &str {
+    let host = registry.split('/').next().unwrap_or(registry);
+    match DOCKER_HUB_HOSTS.contains(&host) {
+        true => DOCKER_HUB_KEY,
+        false => host,
+    }
+}
+
+// Returns the directory holding the user's docker configuration.
+fn user_config_dir() -> Option<PathBuf> {
+    match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => Some(dir.into()),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker")),
+    }
+}
+
+// Encodes `data` as standard base64, with padding.
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(BASE64[(bits >> (18 - 6 * i)) as usize & 63] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+// Writes a docker configuration directory holding only the credentials for
+// `registry`. CLI plugins installed for the user, such as buildx, remain
+// available.
+pub(crate) fn write_config(registry: &str, auth: &Auth) -> SeavanResult<RegistryConfig> {
+    let key = registry_key(registry);
+    let mut config = match auth {
+        Auth::Basic(username, password) => json!({
+            "auths": { key: { "auth": base64(format!("{}:{}", username, password).as_bytes()) } }
+        }),
+        Auth::Helper(helper) => json!({ "credHelpers": { key: helper } }),
+    };
+    if let Some(dir) = user_config_dir() {
+        config["cliPluginsExtraDirs"] = json!([dir.join("cli-plugins")]);
+    }
+
+    let directory = tempfile::Builder::new().prefix("seavan").tempdir()?;
+    serde_json::to_writer(File::create(directory.path().join("config.json"))?, &config)?;
+    Ok(RegistryConfig {
+        key: key.into(),
+        auth: auth.clone(),
+        directory,
+    })
+}
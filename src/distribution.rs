@@ -0,0 +1,661 @@
+//! Requests to registries over the OCI distribution API, to push natively
+//! built images without a container runtime, to read pushed images back to
+//! verify them, and to prune images.
+//!
+//! Requests are made with the `curl` CLI. Credentials are those given to
+//! `with_credentials` or `with_credential_helper` on `Seavan` or
+//! `client::Client`, and are passed to curl on its standard input rather than
+//! its command line, so they aren't visible to other processes. They are only
+//! sent to the registry itself and its token service, not to upload
+//! locations elsewhere. Registries which require token authentication are
+//! supported: a bearer token is requested from the registry's token service
+//! the first time a request for each scope is refused, and again whenever
+//! the registry refuses it, as it does once the token expires.
+//!
+//! Requests go through the proxy set with `Registry::with_proxy`, or the
+//! proxies named in the environment. They trust the CA certificates set with
+//! `Registry::with_ca_certificates`, and present the client certificate set
+//! with `Registry::with_client_certificate` to registries which require
+//! mutual TLS.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    naming,
+    registry::Transport,
+    utils::curl_quote,
+};
+use log::debug;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+#[cfg(feature = "oci-native")]
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+// Longest time a single request which neither uploads nor downloads a blob
+// may take.
+const REQUEST_TIMEOUT_SECS: &str = "300";
+
+// Uploads and downloads may take as long as they need, but are abandoned if
+// they transfer less than `STALL_BYTES_PER_SEC` for `STALL_TIMEOUT_SECS`.
+const STALL_BYTES_PER_SEC: &str = "1";
+const STALL_TIMEOUT_SECS: &str = "60";
+
+#[cfg(feature = "oci-native")]
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+// A response from the registry.
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    // Returns the first value of the header `name`.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// A token issued by a registry's token service.
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+// Media types accepted when fetching manifests.
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json, \
+    application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json";
+
+// A client for the API of a registry. Bearer tokens are held for each scope
+// they were issued for, so a token for one repository isn't sent with
+// requests for another.
+pub(crate) struct Client<'a> {
+    origin: String,
+    basic: Option<(String, String)>,
+    transport: &'a Transport,
+    tokens: BTreeMap<String, String>,
+}
+
+impl<'a> Client<'a> {
+    // Creates a client for the registry at `host`, authenticating with the
+    // given username and password if the registry asks for them.
+    pub(crate) fn new(
+        host: &str,
+        basic: Option<(String, String)>,
+        transport: &'a Transport,
+    ) -> Self {
+        Self {
+            origin: format!("{}://{}", transport.scheme(host), host),
+            basic,
+            transport,
+            tokens: BTreeMap::new(),
+        }
+    }
+
+    // Makes a request to `url`, which is relative to the registry's `/v2/`
+    // API root unless it's absolute, authenticating if the registry asks for
+    // it.
+    pub(crate) fn request(
+        &mut self,
+        method: &str,
+        url: &str,
+        content: Option<(&str, &Path)>,
+    ) -> SeavanResult<Response> {
+        self.exchange(method, url, content, None, None)
+    }
+
+    // Returns the URL of the next page of a paginated list, from the
+    // `rel="next"` link in the `Link` header of `response`, if there is one.
+    // The link may be relative to the registry.
+    pub(crate) fn next_page(&self, response: &Response) -> Option<String> {
+        let link = response
+            .header("Link")?
+            .split(',')
+            .find(|link| link.contains("rel=\"next\"") || link.contains("rel=next"))?;
+        let url = link.split('<').nth(1)?.split('>').next()?;
+        match url.starts_with('/') {
+            true => Some(format!("{}{}", self.origin, url)),
+            false => Some(url.to_string()),
+        }
+    }
+
+    // Fetches the manifest `reference`, which is a tag or digest, from
+    // `repository`.
+    pub(crate) fn manifest(&mut self, repository: &str, reference: &str) -> SeavanResult<Response> {
+        let url = format!("{}/manifests/{}", repository, reference);
+        self.exchange("GET", &url, None, Some(MANIFEST_ACCEPT), None)
+    }
+
+    // Fetches the blob with the given digest from `repository` into the file
+    // at `destination`, rather than into the response's body.
+    fn download(
+        &mut self,
+        repository: &str,
+        digest: &str,
+        destination: &Path,
+    ) -> SeavanResult<Response> {
+        let url = format!("{}/blobs/{}", repository, digest);
+        self.exchange("GET", &url, None, None, Some(destination))
+    }
+
+    // Makes a request as `request` does, accepting the given media types and
+    // writing the body of the response to `destination` if it's given.
+    fn exchange(
+        &mut self,
+        method: &str,
+        url: &str,
+        content: Option<(&str, &Path)>,
+        accept: Option<&str>,
+        destination: Option<&Path>,
+    ) -> SeavanResult<Response> {
+        let url = match url.starts_with("http://") || url.starts_with("https://") {
+            true => url.to_string(),
+            false => format!("{}/v2/{}", self.origin, url),
+        };
+        let scope = default_scope(method, &url);
+        let held = self.tokens.get(&scope);
+        let response = self.send(method, &url, content, accept, destination, held)?;
+        if response.status != 401 {
+            return Ok(response);
+        }
+        // A token held for the scope may have expired, e.g. during a long
+        // push, so a fresh one is fetched and the request is made again.
+        match response.header("WWW-Authenticate") {
+            Some(challenge) if challenge.to_ascii_lowercase().starts_with("bearer ") => {
+                if held.is_some() {
+                    debug!("Refreshing the token for {}", scope);
+                }
+                let token = self.fetch_token(&challenge[7..], &scope)?;
+                let response =
+                    self.send(method, &url, content, accept, destination, Some(&token))?;
+                let _ = self.tokens.insert(scope, token);
+                Ok(response)
+            }
+            _ => Ok(response),
+        }
+    }
+
+    // Sends a single request with curl, with the given bearer token.
+    fn send(
+        &self,
+        method: &str,
+        url: &str,
+        content: Option<(&str, &Path)>,
+        accept: Option<&str>,
+        destination: Option<&Path>,
+        token: Option<&String>,
+    ) -> SeavanResult<Response> {
+        debug!("{} {}", method, url);
+        let staging = tempfile::tempdir()?;
+        let headers = staging.path().join("headers");
+        let body = destination.map_or_else(|| staging.path().join("body"), Path::to_path_buf);
+
+        let mut command = curl_command(
+            method,
+            url,
+            content,
+            accept,
+            &headers,
+            &body,
+            destination.is_some(),
+        );
+        let mut child = command.spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(self.curl_config(url, token).as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(SeavanError::RegistryApiFailure(
+                String::from_utf8_lossy(&output.stderr).trim().into(),
+            ));
+        }
+        let status = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .map_err(|_| SeavanError::RegistryApiFailure(format!("no response from {}", url)))?;
+
+        // Headers of interim responses, such as `100 Continue`, and of
+        // redirects come first.
+        let headers = fs::read_to_string(headers)?;
+        let headers = headers
+            .split("\r\n\r\n")
+            .filter(|block| !block.trim().is_empty())
+            .last()
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().into(), value.trim().into()))
+            .collect();
+        Ok(Response {
+            status,
+            headers,
+            body: match destination {
+                Some(_) => Vec::new(),
+                None => fs::read(body).unwrap_or_default(),
+            },
+        })
+    }
+
+    // Returns the curl configuration for a request to `url`. Credentials are
+    // only sent to the registry's own origin; without any, those in
+    // `~/.netrc` are used if it has some for the registry.
+    fn curl_config(&self, url: &str, token: Option<&String>) -> String {
+        let mut config = self.transport.curl_config(url);
+        if !origin(url).eq_ignore_ascii_case(&self.origin) {
+            return config;
+        }
+        config += &match (token, &self.basic) {
+            (Some(token), _) => {
+                format!("header = \"Authorization: Bearer {}\"\n", curl_quote(token))
+            }
+            (None, Some((username, password))) => {
+                format!(
+                    "user = \"{}:{}\"\n",
+                    curl_quote(username),
+                    curl_quote(password)
+                )
+            }
+            (None, None) => "netrc-optional\n".into(),
+        };
+        config
+    }
+
+    // Requests a token from the token service described by a bearer
+    // challenge, e.g. `realm="https://auth.example.com/token",service="..."`,
+    // for `scope` unless the challenge names one.
+    fn fetch_token(&self, challenge: &str, scope: &str) -> SeavanResult<String> {
+        let parameters = challenge_parameters(challenge);
+        let realm = parameters
+            .iter()
+            .find(|(key, _)| key == "realm")
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| SeavanError::RegistryAuthFailure(challenge.into()))?;
+        let mut query: Vec<String> = parameters
+            .iter()
+            .filter(|(key, _)| key == "service" || key == "scope")
+            .map(|(key, value)| format!("{}={}", key, percent_encode(value)))
+            .collect();
+        if !parameters.iter().any(|(key, _)| key == "scope") {
+            query.push(format!("scope={}", percent_encode(scope)));
+        }
+        let separator = match realm.contains('?') {
+            true => '&',
+            false => '?',
+        };
+        let url = format!("{}{}{}", realm, separator, query.join("&"));
+
+        // The token service is trusted with the registry's credentials.
+        let service = Client {
+            origin: origin(&realm).into(),
+            basic: self.basic.clone(),
+            transport: self.transport,
+            tokens: BTreeMap::new(),
+        };
+        let response = service.send("GET", &url, None, None, None, None)?;
+        if response.status != 200 {
+            return Err(SeavanError::RegistryAuthFailure(format!(
+                "token request returned {}",
+                response.status
+            )));
+        }
+        let token: TokenResponse = serde_json::from_slice(&response.body)?;
+        token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| SeavanError::RegistryAuthFailure("no token issued".into()))
+    }
+
+    // Uploads the blob in `blobs` with the given digest to `repository`,
+    // unless the repository already has it.
+    //
+    // The blob is sent in a single monolithic `PUT`, so the registry holds
+    // none of it until the whole blob is accepted. If the `PUT` is refused
+    // because the token expired, the whole blob is sent again with a fresh
+    // token, from its first byte: there's no partial upload whose `Range` it
+    // could resume from. Very large blobs on registries with short-lived
+    // tokens may need a chunked upload, which isn't supported.
+    #[cfg(feature = "oci-native")]
+    fn upload_blob(&mut self, repository: &str, blobs: &Path, digest: &str) -> SeavanResult<()> {
+        let url = format!("{}/blobs/{}", repository, digest);
+        let response = self.request("HEAD", &url, None)?;
+        if response.status == 200 {
+            debug!("Blob {} already exists", digest);
+            return Ok(());
+        }
+        let response = self.request("POST", &format!("{}/blobs/uploads/", repository), None)?;
+        expect(&response, 202, "starting an upload")?;
+        let location = response
+            .header("Location")
+            .ok_or_else(|| SeavanError::RegistryApiFailure("no upload location".into()))?;
+        let separator = match location.contains('?') {
+            true => '&',
+            false => '?',
+        };
+        // Locations are either absolute or relative to the registry's root.
+        let url = match location.starts_with('/') {
+            true => format!(
+                "{}{}{}digest={}",
+                self.origin,
+                location,
+                separator,
+                percent_encode(digest)
+            ),
+            false => format!("{}{}digest={}", location, separator, percent_encode(digest)),
+        };
+        let path = blobs.join(digest.trim_start_matches("sha256:"));
+        let response = self.request(
+            "PUT",
+            &url,
+            Some(("application/octet-stream", path.as_path())),
+        )?;
+        expect(&response, 201, "uploading a blob")
+    }
+
+    // Checks that every manifest and blob `manifest` refers to in
+    // `repository` can be fetched and matches its digest.
+    fn verify_manifest(&mut self, repository: &str, manifest: &[u8]) -> SeavanResult<()> {
+        let manifest: Value = serde_json::from_slice(manifest)?;
+        // Indexes refer to the manifest of each platform's image.
+        for descriptor in manifest["manifests"].as_array().into_iter().flatten() {
+            let digest = descriptor["digest"].as_str().unwrap_or_default();
+            let response = self.manifest(repository, digest)?;
+            expect(&response, 200, &format!("fetching manifest {}", digest))?;
+            let actual = format!("sha256:{:x}", Sha256::digest(&response.body));
+            check_digest(repository, digest, &actual)?;
+            self.verify_manifest(repository, &response.body)?;
+        }
+
+        let staging = tempfile::tempdir()?;
+        let path = staging.path().join("blob");
+        let digests = std::iter::once(&manifest["config"])
+            .chain(manifest["layers"].as_array().into_iter().flatten())
+            .filter_map(|descriptor| descriptor["digest"].as_str());
+        for digest in digests {
+            let response = self.download(repository, digest, &path)?;
+            expect(&response, 200, &format!("fetching blob {}", digest))?;
+            let mut hasher = Sha256::new();
+            let _ = io::copy(&mut File::open(&path)?, &mut hasher)?;
+            check_digest(
+                repository,
+                digest,
+                &format!("sha256:{:x}", hasher.finalize()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Creates the curl command for a request, which reads its configuration
+// from standard input and writes the response's headers to `headers` and its
+// body to `body`. Set `download` if the body is a blob, which may be large.
+pub(crate) fn curl_command(
+    method: &str,
+    url: &str,
+    content: Option<(&str, &Path)>,
+    accept: Option<&str>,
+    headers: &Path,
+    body: &Path,
+    download: bool,
+) -> Command {
+    let mut command = Command::new("curl");
+    let _ = command
+        .args(["--silent", "--show-error", "--config", "-"])
+        .args(["--write-out", "%{http_code}"])
+        .arg("--dump-header")
+        .arg(headers)
+        .arg("--output")
+        .arg(body);
+    let _ = match method {
+        "HEAD" => command.arg("--head"),
+        // Registries may redirect downloads to other storage.
+        "GET" => command.arg("--location"),
+        method => command.args(["--request", method]),
+    };
+    if let Some(accept) = accept {
+        let _ = command.args(["--header", &format!("Accept: {}", accept)]);
+    }
+    // Content is streamed from its file rather than read into memory, and
+    // downloads straight into theirs, so layers of any size can be
+    // transferred.
+    let _ = match content {
+        Some((content_type, path)) => command
+            .args(["--header", &format!("Content-Type: {}", content_type)])
+            .args(["--speed-limit", STALL_BYTES_PER_SEC])
+            .args(["--speed-time", STALL_TIMEOUT_SECS])
+            .arg("--upload-file")
+            .arg(path),
+        None if download => command
+            .args(["--speed-limit", STALL_BYTES_PER_SEC])
+            .args(["--speed-time", STALL_TIMEOUT_SECS]),
+        None => command.args(["--max-time", REQUEST_TIMEOUT_SECS]),
+    };
+    let _ = command
+        .args(["--url", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+// Checks content fetched from `repository` has the digest it was fetched
+// by. Content is hashed with SHA-256, so other digests never match.
+fn check_digest(repository: &str, expected: &str, actual: &str) -> SeavanResult<()> {
+    match expected == actual {
+        true => Ok(()),
+        false => Err(SeavanError::ContentMismatch(
+            format!("{}@{}", repository, expected),
+            expected.into(),
+            actual.into(),
+        )),
+    }
+}
+
+// Splits `reference` into the registry host, the repository on it, and the
+// tag.
+fn split_reference(reference: &str) -> SeavanResult<(&str, &str, &str)> {
+    let repository = naming::repository_of(reference);
+    let tag = reference[repository.len()..].trim_start_matches(':');
+    let (host, repository) = repository
+        .split_once('/')
+        .ok_or_else(|| SeavanError::NoRegistry(reference.into()))?;
+    Ok((host, repository, tag))
+}
+
+// Reads the pushed image `reference` back from its registry, checking that
+// its manifest has the given digest, if it's known, and that everything the
+// manifest refers to matches its digest.
+pub(crate) fn verify_pushed(
+    reference: &str,
+    digest: Option<&str>,
+    basic: Option<(String, String)>,
+    transport: &Transport,
+) -> SeavanResult<()> {
+    let (host, repository, tag) = split_reference(reference)?;
+    let mut client = Client::new(host, basic, transport);
+    let response = client.manifest(repository, tag)?;
+    expect(
+        &response,
+        200,
+        &format!("fetching the manifest of {}", reference),
+    )?;
+    let actual = format!("sha256:{:x}", Sha256::digest(&response.body));
+    if let Some(expected) = digest {
+        check_digest(repository, expected, &actual)?;
+    }
+    client.verify_manifest(repository, &response.body)
+}
+
+// Returns the scope of the token needed for a request to `url` on a
+// registry's API, used when the registry's challenge doesn't name one: the
+// catalog, or the repository the request is for.
+fn default_scope(method: &str, url: &str) -> String {
+    let path = url
+        .split_once("/v2/")
+        .map_or("", |(_, path)| path)
+        .split('?')
+        .next()
+        .unwrap_or_default();
+    if path == "_catalog" {
+        return "registry:catalog:*".into();
+    }
+    let repository = ["/blobs/", "/manifests/", "/tags/"]
+        .iter()
+        .filter_map(|separator| path.find(separator))
+        .min()
+        .map_or(path, |end| &path[..end]);
+    let actions = match method {
+        "GET" | "HEAD" => "pull",
+        "DELETE" => "delete",
+        _ => "pull,push",
+    };
+    format!("repository:{}:{}", repository, actions)
+}
+
+// Returns the scheme and host of `url`.
+fn origin(url: &str) -> &str {
+    let after_scheme = url.find("://").map_or(0, |index| index + 3);
+    let end = url[after_scheme..]
+        .find(['/', '?'])
+        .map_or(url.len(), |index| after_scheme + index);
+    &url[..end]
+}
+
+// Checks a response has the expected status.
+pub(crate) fn expect(response: &Response, status: u16, action: &str) -> SeavanResult<()> {
+    match response.status {
+        actual if actual == status => Ok(()),
+        401 | 403 => Err(SeavanError::RegistryAuthFailure(format!(
+            "{} returned {}",
+            action, response.status
+        ))),
+        actual => Err(SeavanError::RegistryApiFailure(format!(
+            "{} returned {}: {}",
+            action,
+            actual,
+            String::from_utf8_lossy(&response.body).trim()
+        ))),
+    }
+}
+
+// Splits the parameters of an authentication challenge, e.g.
+// `realm="https://auth.example.com/token",service="registry"`.
+fn challenge_parameters(challenge: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut rest = challenge.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => match value.find(',') {
+                Some(end) => (&value[..end], &value[end..]),
+                None => (value, ""),
+            },
+        };
+        parameters.push((key, value.to_string()));
+        rest = remainder;
+    }
+    parameters
+}
+
+// Percent-encodes a query parameter value.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Reads the manifest of the image `reference` in the OCI image layout at
+// `layout`, returning its path and content.
+#[cfg(feature = "oci-native")]
+fn read_manifest(layout: &Path, reference: &str) -> SeavanResult<(PathBuf, Value)> {
+    let index: Value = serde_json::from_slice(&fs::read(layout.join("index.json"))?)?;
+    let descriptor = index["manifests"]
+        .as_array()
+        .and_then(|manifests| {
+            manifests
+                .iter()
+                .find(|m| m["annotations"]["org.opencontainers.image.ref.name"] == reference)
+        })
+        .ok_or_else(|| {
+            SeavanError::RegistryApiFailure(format!("{} isn't in the layout", reference))
+        })?;
+    let manifest_digest = descriptor["digest"].as_str().unwrap_or_default();
+    let manifest_path = blob_path(layout, manifest_digest);
+    let manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+    Ok((manifest_path, manifest))
+}
+
+// Returns the path of the blob with the given digest in the OCI image layout
+// at `layout`.
+#[cfg(feature = "oci-native")]
+fn blob_path(layout: &Path, digest: &str) -> PathBuf {
+    layout
+        .join("blobs")
+        .join("sha256")
+        .join(digest.trim_start_matches("sha256:"))
+}
+
+// Returns the labels of the image `reference` in the OCI image layout at
+// `layout`.
+#[cfg(feature = "oci-native")]
+pub(crate) fn layout_labels(
+    layout: &Path,
+    reference: &str,
+) -> SeavanResult<BTreeMap<String, String>> {
+    let (_, manifest) = read_manifest(layout, reference)?;
+    let config_digest = manifest["config"]["digest"].as_str().unwrap_or_default();
+    let config: Value = serde_json::from_slice(&fs::read(blob_path(layout, config_digest))?)?;
+    Ok(serde_json::from_value(config["config"]["Labels"].clone()).unwrap_or_default())
+}
+
+// Pushes the image `reference` from the OCI image layout at `layout`,
+// returning the digest of its manifest.
+#[cfg(feature = "oci-native")]
+pub(crate) fn push_layout(
+    layout: &Path,
+    reference: &str,
+    basic: Option<(String, String)>,
+    transport: &Transport,
+) -> SeavanResult<String> {
+    let (host, repository, tag) = split_reference(reference)?;
+    let (manifest_path, manifest) = read_manifest(layout, reference)?;
+    let blobs = layout.join("blobs").join("sha256");
+
+    let mut client = Client::new(host, basic, transport);
+    let digests = std::iter::once(&manifest["config"])
+        .chain(manifest["layers"].as_array().into_iter().flatten())
+        .filter_map(|descriptor| descriptor["digest"].as_str());
+    for digest in digests {
+        client.upload_blob(repository, &blobs, digest)?;
+    }
+
+    let response = client.request(
+        "PUT",
+        &format!("{}/manifests/{}", repository, tag),
+        Some((MANIFEST_MEDIA_TYPE, manifest_path.as_path())),
+    )?;
+    expect(&response, 201, "uploading the manifest")?;
+    let digest = format!("sha256:{:x}", Sha256::digest(fs::read(&manifest_path)?));
+    Ok(digest)
+}
@@ -0,0 +1,1016 @@
+//! Helpers for invoking Docker commands.
+
+#[cfg(feature = "async")]
+use crate::nonblocking;
+use crate::{
+    archive::ArchiveFormat,
+    credentials::RegistryConfig,
+    engine::{ContainerEngine, Engine},
+    error::{SeavanError, SeavanResult},
+    runner::{CommandRunner, LocalRunner},
+};
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Repository used for images while they are being built; images only gain
+/// their final name once the build has succeeded.
+const TEMPORARY_REPOSITORY: &str = "seavantmp";
+
+// Registry of images whose references don't name one.
+const DOCKER_HUB_HOST: &str = "docker.io";
+
+// Counter to keep temporary references unique within a process.
+static TEMPORARY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Generates a unique reference to build an image under before retagging.
+pub(crate) fn temporary_reference() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    let count = TEMPORARY_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!(
+        "{}/{}-{}-{}:build",
+        TEMPORARY_REPOSITORY,
+        std::process::id(),
+        nanos,
+        count
+    )
+}
+
+// How often a command is checked for completion while a deadline is set.
+const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Converts docker's stderr into a String for error reporting.
+pub(crate) fn stderr_string(output: Output) -> String {
+    String::from_utf8(output.stderr).unwrap_or_else(|_| "No Docker stderr".to_string())
+}
+
+// Best effort debug logging of docker's stdout and stderr.
+pub(crate) fn log_output(output: &Output) {
+    if let Ok(stdout) = std::str::from_utf8(&output.stdout) {
+        debug!("Docker output: {}", stdout);
+    }
+    // Buildkit prints out to stderr rather than stdout.
+    if let Ok(stderr) = std::str::from_utf8(&output.stderr) {
+        debug!("Docker stderr: {}", stderr);
+    }
+}
+
+/// Settings applied to every docker command run on behalf of a `Seavan`.
+#[derive(Clone)]
+pub(crate) struct Docker {
+    env: Vec<(String, String)>,
+    runner: Arc<dyn CommandRunner>,
+    custom_runner: bool,
+    engine: Arc<dyn ContainerEngine>,
+    deadline: Option<Instant>,
+    registry_configs: Vec<Arc<RegistryConfig>>,
+    mirrors: Vec<(String, String)>,
+    insecure_registries: Vec<String>,
+}
+
+impl Default for Docker {
+    fn default() -> Self {
+        Self {
+            env: Vec::new(),
+            runner: Arc::new(LocalRunner),
+            custom_runner: false,
+            engine: Arc::new(Engine::default()),
+            deadline: None,
+            registry_configs: Vec::new(),
+            mirrors: Vec::new(),
+            insecure_registries: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Docker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Docker")
+            .field("env", &self.env)
+            .field("custom_runner", &self.custom_runner)
+            .field("engine", &self.engine)
+            .field("deadline", &self.deadline)
+            .field(
+                "registry_configs",
+                &self
+                    .registry_configs
+                    .iter()
+                    .map(|config| config.path())
+                    .collect::<Vec<_>>(),
+            )
+            .field("mirrors", &self.mirrors)
+            .field("insecure_registries", &self.insecure_registries)
+            .finish()
+    }
+}
+
+// Reads a child's output pipe to the end on another thread, so the child
+// never blocks on a full pipe.
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+// Sends each line of a child's output pipe to `sender` from another thread,
+// tagged with whether it came from stderr.
+fn forward<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    is_stderr: bool,
+    sender: mpsc::Sender<(bool, String)>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        if let Some(pipe) = pipe {
+            let mut reader = BufReader::new(pipe);
+            let mut line = Vec::new();
+            while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                let text = String::from_utf8_lossy(&line).into_owned();
+                if sender.send((is_stderr, text)).is_err() {
+                    break;
+                }
+                line.clear();
+            }
+        }
+    })
+}
+
+// Splits the image `reference` into its registry host and the rest, following
+// docker's rule that the first component names a registry if it looks like a
+// host. Other references are on Docker Hub.
+pub(crate) fn split_registry(reference: &str) -> (&str, &str) {
+    match reference.split_once('/') {
+        Some((first, rest)) if first.contains(['.', ':']) || first == "localhost" => (first, rest),
+        _ => (DOCKER_HUB_HOST, reference),
+    }
+}
+
+// Returns whether docker's `stderr` reports that a remote image has no
+// manifest, e.g. `manifest unknown`, or `<reference>: not found` from buildx.
+fn reports_missing_manifest(stderr: &str) -> bool {
+    let lowercase = stderr.to_lowercase();
+    lowercase.contains("no such manifest")
+        || lowercase.contains("manifest unknown")
+        || lowercase
+            .lines()
+            .any(|line| line.trim_end().ends_with(": not found"))
+}
+
+// Returns whether docker's `stderr` reports that its daemon couldn't be
+// reached, e.g. because it isn't running.
+fn reports_daemon_unreachable(stderr: &str) -> bool {
+    let lowercase = stderr.to_lowercase();
+    lowercase.contains("cannot connect to the docker daemon")
+        || lowercase.contains("is the docker daemon running")
+        || lowercase.contains("cannot connect to podman")
+        || lowercase.contains("error during connect")
+}
+
+// Returns whether docker's `stderr` reports that the registry refused its
+// credentials, or that it had none, e.g. `unauthorized: authentication
+// required` or `denied: requested access to the resource is denied`.
+fn reports_auth_failure(stderr: &str) -> bool {
+    let lowercase = stderr.to_lowercase();
+    lowercase.contains("unauthorized:")
+        || lowercase.contains("denied:")
+        || lowercase.contains("authentication required")
+        || lowercase.contains("no basic auth credentials")
+}
+
+// Returns the error for docker's daemon being unreachable, as reported in
+// `stderr`.
+fn daemon_unreachable(stderr: String) -> SeavanError {
+    SeavanError::DockerUnavailable(io::Error::new(io::ErrorKind::NotConnected, stderr))
+}
+
+// Returns the error for a failed `docker build` from its output.
+pub(crate) fn build_failure(output: Output) -> SeavanError {
+    let stderr = stderr_string(output);
+    match reports_daemon_unreachable(&stderr) {
+        true => daemon_unreachable(stderr),
+        false => SeavanError::DockerBuildFailure(stderr),
+    }
+}
+
+// Returns the error for a failed `docker push` from its output.
+fn push_failure(output: Output) -> SeavanError {
+    let stderr = stderr_string(output);
+    if reports_daemon_unreachable(&stderr) {
+        daemon_unreachable(stderr)
+    } else if reports_auth_failure(&stderr) {
+        SeavanError::RegistryAuthFailure(stderr)
+    } else {
+        SeavanError::DockerPushFailure(stderr)
+    }
+}
+
+// Checks the output of `docker pull`.
+fn pulled(output: Output) -> SeavanResult<()> {
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(SeavanError::DockerPullFailure(stderr_string(output))),
+    }
+}
+
+// Checks the output of `docker push`, returning the digest of the pushed
+// manifest if docker reported it.
+pub(crate) fn pushed_digest(output: Output) -> SeavanResult<Option<String>> {
+    if !output.status.success() {
+        return Err(push_failure(output));
+    }
+    log_output(&output);
+
+    // Docker reports the digest as its last line of output, e.g.
+    // `latest: digest: sha256:<hex> size: 528`.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().rev().find_map(|line| {
+        let digest = line.split("digest: ").nth(1)?.split_whitespace().next()?;
+        Some(digest.to_string())
+    }))
+}
+
+// Creates the error for a deadline passing; the caller fills in the
+// progress made.
+fn deadline_exceeded() -> SeavanError {
+    SeavanError::DeadlineExceeded(Default::default())
+}
+
+impl Docker {
+    // Adds an environment variable to pass to docker.
+    pub(crate) fn add_env(&mut self, key: &str, value: &str) {
+        self.env.push((key.into(), value.into()));
+    }
+
+    // Sets the runner used to execute docker commands.
+    pub(crate) fn set_runner(&mut self, runner: Arc<dyn CommandRunner>) {
+        self.runner = runner;
+        self.custom_runner = true;
+    }
+
+    // Sets the container engine which is run in place of docker.
+    pub(crate) fn set_engine(&mut self, engine: Arc<dyn ContainerEngine>) {
+        self.engine = engine;
+    }
+
+    // Sets the time after which docker commands are killed.
+    pub(crate) fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    // Sets the docker configuration used by commands which talk to the
+    // registry it holds credentials for, replacing any earlier configuration
+    // for that registry.
+    pub(crate) fn set_registry_config(&mut self, config: RegistryConfig) {
+        self.registry_configs
+            .retain(|existing| existing.key() != config.key());
+        self.registry_configs.push(Arc::new(config));
+    }
+
+    // Adds a mirror which images in `registry` are pulled through, tried in
+    // the order mirrors were added.
+    pub(crate) fn add_mirror(&mut self, registry: &str, mirror: &str) {
+        self.mirrors.push((
+            registry.trim_end_matches('/').into(),
+            mirror.trim_end_matches('/').into(),
+        ));
+    }
+
+    // Returns the references `reference` can be pulled as through the
+    // mirrors of its registry. References without a registry host are on
+    // Docker Hub, where single-component names are official images under
+    // `library/`.
+    fn mirrored(&self, reference: &str) -> Vec<String> {
+        let (registry, path) = split_registry(reference);
+        let path = match (registry, path.contains('/')) {
+            (DOCKER_HUB_HOST, false) => format!("library/{}", path),
+            _ => path.to_string(),
+        };
+        self.mirrors
+            .iter()
+            .filter(|(mirrored, _)| mirrored.eq_ignore_ascii_case(registry))
+            .map(|(_, mirror)| format!("{}/{}", mirror, path))
+            .collect()
+    }
+
+    // Marks the registry at `host` as insecure, or not. The docker commands
+    // which talk to insecure registries themselves, rather than through the
+    // Docker daemon, are told to skip TLS verification and fall back to
+    // plain HTTP.
+    pub(crate) fn set_insecure_registry(&mut self, host: &str, insecure: bool) {
+        self.insecure_registries
+            .retain(|registry| !registry.eq_ignore_ascii_case(host));
+        if insecure {
+            self.insecure_registries.push(host.into());
+        }
+    }
+
+    // Returns whether the image `reference` is in an insecure registry.
+    fn insecure(&self, reference: &str) -> bool {
+        let (registry, _) = split_registry(reference);
+        self.insecure_registries
+            .iter()
+            .any(|insecure| insecure.eq_ignore_ascii_case(registry))
+    }
+
+    // Returns the arguments of a `docker manifest inspect` of the image
+    // `reference`.
+    pub(crate) fn manifest_inspect_args<'a>(&self, reference: &'a str) -> Vec<&'a str> {
+        let mut args = vec!["manifest", "inspect"];
+        if self.insecure(reference) {
+            args.push("--insecure");
+        }
+        args.push(reference);
+        args
+    }
+
+    // Returns the name of a buildx builder configured to reach the insecure
+    // registries, creating it if it doesn't exist yet. `buildx imagetools`
+    // has no option to reach a registry insecurely; it uses the registry
+    // configuration of the builder it's given instead. The builder is never
+    // started, and its name depends on the registries, so it's shared by
+    // every process configured with the same ones.
+    fn insecure_builder(&self) -> SeavanResult<String> {
+        let mut registries = self.insecure_registries.clone();
+        registries.sort();
+        let mut config = String::new();
+        for registry in &registries {
+            config += &format!(
+                "[registry.{:?}]\n  http = true\n  insecure = true\n",
+                registry
+            );
+        }
+        let name = format!(
+            "seavan-insecure-{}",
+            &format!("{:x}", Sha256::digest(&config))[..12]
+        );
+
+        let inspected = self.run(self.command().args(["buildx", "inspect", &name]), None)?;
+        if inspected.status.success() {
+            return Ok(name);
+        }
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(config.as_bytes())?;
+        let output = self.run(
+            self.command()
+                .args(["buildx", "create", "--name", &name])
+                .args(["--driver", "docker-container", "--buildkitd-config"])
+                .arg(file.path()),
+            None,
+        )?;
+        match output.status.success() {
+            true => Ok(name),
+            false => Err(SeavanError::DockerInspectFailure(stderr_string(output))),
+        }
+    }
+
+    // Creates a docker command with this instance's settings applied.
+    pub(crate) fn command(&self) -> Command {
+        let mut command = Command::new(self.engine.program());
+        let _ = command.envs(self.env.iter().map(|(k, v)| (k, v)));
+        command
+    }
+
+    // Returns the registry configuration holding credentials for the
+    // registry holding the image `reference`, if there is one.
+    pub(crate) fn registry_config(&self, reference: &str) -> Option<&RegistryConfig> {
+        self.registry_configs
+            .iter()
+            .map(|config| config.as_ref())
+            .find(|config| config.applies_to(reference))
+    }
+
+    // Creates a docker command which talks to the registry holding the
+    // image `reference`, using the registry configuration holding
+    // credentials for that registry. Other registries use the user's own
+    // docker configuration.
+    // Fails with `SeavanError::RemoteCredentials` if the configuration would
+    // be needed by a runner which runs docker on another host, where it
+    // doesn't exist.
+    pub(crate) fn registry_command(&self, reference: &str) -> SeavanResult<Command> {
+        let mut command = self.command();
+        if let Some(config) = self.registry_config(reference) {
+            if !self.runner.is_local() {
+                return Err(SeavanError::RemoteCredentials(config.key().into()));
+            }
+            let _ = command.env("DOCKER_CONFIG", config.path());
+        }
+        Ok(command)
+    }
+
+    // Runs a docker command to completion using this instance's runner.
+    // Failing to start docker at all is reported as
+    // `SeavanError::DockerUnavailable`. If a deadline is set, the command is
+    // killed when it passes.
+    pub(crate) fn run(&self, command: &mut Command, stdin: Option<File>) -> SeavanResult<Output> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => {
+                return self
+                    .runner
+                    .run(command, stdin)
+                    .map_err(SeavanError::DockerUnavailable)
+            }
+        };
+        if Instant::now() >= deadline {
+            return Err(deadline_exceeded());
+        }
+
+        match self.runner.spawn(command) {
+            Ok(child) => self.wait(child, stdin),
+            // Runners which can't spawn commands can't have them killed.
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => self
+                .runner
+                .run(command, stdin)
+                .map_err(SeavanError::DockerUnavailable),
+            Err(e) => Err(SeavanError::DockerUnavailable(e)),
+        }
+    }
+
+    // Runs a docker command as `run` does, without blocking the calling task.
+    // Commands for the default runner are spawned with tokio, and killed if
+    // the deadline passes; other runners are called on the blocking thread
+    // pool.
+    #[cfg(feature = "async")]
+    pub(crate) async fn run_async(
+        &self,
+        mut command: Command,
+        stdin: Option<File>,
+    ) -> SeavanResult<Output> {
+        if self.custom_runner {
+            let docker = self.clone();
+            return nonblocking::blocking(move || docker.run(&mut command, stdin)).await;
+        }
+        let mut command = tokio::process::Command::from(command);
+        let _ = command.kill_on_drop(true);
+        if let Some(stdin) = stdin {
+            let _ = command.stdin(stdin);
+        }
+        let output = match self.deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), command.output())
+                .await
+                .map_err(|_| deadline_exceeded())?,
+            None => command.output().await,
+        };
+        output.map_err(SeavanError::DockerUnavailable)
+    }
+
+    // Waits for a spawned docker command to finish, writing `stdin` to it if
+    // given. If a deadline is set, the command is killed when it passes.
+    pub(crate) fn wait(&self, mut child: Child, stdin: Option<File>) -> SeavanResult<Output> {
+        let writer = match (child.stdin.take(), stdin) {
+            (Some(mut pipe), Some(mut file)) => Some(thread::spawn(move || {
+                let _ = io::copy(&mut file, &mut pipe);
+            })),
+            _ => None,
+        };
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return Ok(child.wait_with_output()?),
+        };
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                debug!("Deadline passed, killing docker");
+                let _ = child.kill();
+                let _ = child.wait()?;
+                return Err(deadline_exceeded());
+            }
+            thread::sleep(DEADLINE_POLL_INTERVAL);
+        };
+
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+
+    // Runs a docker command as `run` does, passing each line of its stdout
+    // and stderr to `on_line` as it's written. The output of runners which
+    // can't spawn commands is passed on once the command finishes.
+    pub(crate) fn run_watched(
+        &self,
+        command: &mut Command,
+        stdin: Option<File>,
+        on_line: &mut dyn FnMut(&str),
+    ) -> SeavanResult<Output> {
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(deadline_exceeded());
+        }
+        match self.runner.spawn(command) {
+            Ok(child) => self.watch(child, stdin, on_line),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                let output = self.run(command, stdin)?;
+                for pipe in [&output.stdout, &output.stderr] {
+                    String::from_utf8_lossy(pipe)
+                        .lines()
+                        .for_each(&mut *on_line);
+                }
+                Ok(output)
+            }
+            Err(e) => Err(SeavanError::DockerUnavailable(e)),
+        }
+    }
+
+    // Waits for a spawned docker command as `wait` does, passing each line
+    // of its stdout and stderr to `on_line` as it's written.
+    pub(crate) fn watch(
+        &self,
+        mut child: Child,
+        stdin: Option<File>,
+        on_line: &mut dyn FnMut(&str),
+    ) -> SeavanResult<Output> {
+        let writer = match (child.stdin.take(), stdin) {
+            (Some(mut pipe), Some(mut file)) => Some(thread::spawn(move || {
+                let _ = io::copy(&mut file, &mut pipe);
+            })),
+            _ => None,
+        };
+        let (sender, receiver) = mpsc::channel();
+        let readers = [
+            forward(child.stdout.take(), false, sender.clone()),
+            forward(child.stderr.take(), true, sender),
+        ];
+
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        loop {
+            match receiver.recv_timeout(DEADLINE_POLL_INTERVAL) {
+                Ok((is_stderr, line)) => {
+                    on_line(line.trim_end_matches(['\r', '\n']));
+                    match is_stderr {
+                        true => stderr.extend_from_slice(line.as_bytes()),
+                        false => stdout.extend_from_slice(line.as_bytes()),
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                debug!("Deadline passed, killing docker");
+                let _ = child.kill();
+                let _ = child.wait()?;
+                return Err(deadline_exceeded());
+            }
+        }
+        for reader in readers {
+            let _ = reader.join();
+        }
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+        Ok(Output {
+            status: child.wait()?,
+            stdout,
+            stderr,
+        })
+    }
+
+    // Starts a docker command with piped stdio using this instance's runner.
+    pub(crate) fn spawn(&self, command: &mut Command) -> SeavanResult<Child> {
+        self.runner
+            .spawn(command)
+            .map_err(SeavanError::DockerUnavailable)
+    }
+
+    // Adds an additional tag `target` to the image `source`.
+    pub(crate) fn tag(&self, source: &str, target: &str) -> SeavanResult<()> {
+        let output = self.run(self.command().args(["tag", source, target]), None)?;
+
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(SeavanError::DockerTagFailure(stderr_string(output))),
+        }
+    }
+
+    // Pulls the image `reference` from its registry.
+    pub(crate) fn pull(&self, reference: &str) -> SeavanResult<()> {
+        // Images pulled through a mirror are tagged with their own name, as
+        // if they'd been pulled from their registry.
+        for mirrored in self.mirrored(reference) {
+            match self.pull_from(&mirrored) {
+                Ok(()) => {
+                    self.tag(&mirrored, reference)?;
+                    if let Err(e) = self.remove_image(&mirrored) {
+                        debug!("Couldn't untag {}: {}", mirrored, e);
+                    }
+                    return Ok(());
+                }
+                Err(e) => debug!("Couldn't pull {} through a mirror: {}", reference, e),
+            }
+        }
+        self.pull_from(reference)
+    }
+
+    // Pulls the image `reference` from its registry, as `pull` does, without
+    // blocking the calling task.
+    #[cfg(feature = "async")]
+    pub(crate) async fn pull_async(&self, reference: &str) -> SeavanResult<()> {
+        for mirrored in self.mirrored(reference) {
+            let output = self.run_async(self.pull_command(&mirrored)?, None).await?;
+            match pulled(output) {
+                Ok(()) => {
+                    let (docker, reference) = (self.clone(), reference.to_string());
+                    return nonblocking::blocking(move || {
+                        docker.tag(&mirrored, &reference)?;
+                        if let Err(e) = docker.remove_image(&mirrored) {
+                            debug!("Couldn't untag {}: {}", mirrored, e);
+                        }
+                        Ok(())
+                    })
+                    .await;
+                }
+                Err(e) => debug!("Couldn't pull {} through a mirror: {}", reference, e),
+            }
+        }
+        pulled(self.run_async(self.pull_command(reference)?, None).await?)
+    }
+
+    // Pulls the image `reference` from its registry.
+    fn pull_from(&self, reference: &str) -> SeavanResult<()> {
+        pulled(self.run(&mut self.pull_command(reference)?, None)?)
+    }
+
+    // Returns the command pulling the image `reference` from its registry.
+    fn pull_command(&self, reference: &str) -> SeavanResult<Command> {
+        let mut command = self.registry_command(reference)?;
+        let _ = command.args(["pull", reference]);
+        Ok(command)
+    }
+
+    // Pushes the image `reference` to its registry, returning the digest of
+    // the pushed manifest if docker reported it.
+    pub(crate) fn push(&self, reference: &str) -> SeavanResult<Option<String>> {
+        pushed_digest(self.run(&mut self.push_command(reference)?, None)?)
+    }
+
+    // Pushes the image `reference` as `push` does, passing each line of
+    // docker's output to `on_line` as it's written.
+    pub(crate) fn push_watched(
+        &self,
+        reference: &str,
+        on_line: &mut dyn FnMut(&str),
+    ) -> SeavanResult<Option<String>> {
+        pushed_digest(self.run_watched(&mut self.push_command(reference)?, None, on_line)?)
+    }
+
+    // Returns the command pushing the image `reference` to its registry.
+    pub(crate) fn push_command(&self, reference: &str) -> SeavanResult<Command> {
+        let mut command = self.registry_command(reference)?;
+        let _ = command.args(["push", reference]);
+        Ok(command)
+    }
+
+    // Saves the image `reference` to an archive at `path`. Podman writes
+    // Docker archives unless told otherwise.
+    pub(crate) fn save(
+        &self,
+        reference: &str,
+        path: &Path,
+        format: ArchiveFormat,
+    ) -> SeavanResult<()> {
+        let mut command = self.command();
+        let _ = command.args(["save", "-o"]).arg(path);
+        if Path::new(self.engine.program()).file_stem() == Some("podman".as_ref()) {
+            let _ = command.args(match format {
+                ArchiveFormat::Docker => ["--format", "docker-archive"],
+                ArchiveFormat::Oci => ["--format", "oci-archive"],
+            });
+        }
+        let output = self.run(command.arg(reference), None)?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(SeavanError::DockerSaveFailure(stderr_string(output))),
+        }
+    }
+
+    // Loads the images in the archive at `path`, returning their references.
+    pub(crate) fn load(&self, path: &Path) -> SeavanResult<Vec<String>> {
+        let output = self.run(self.command().arg("load").arg("-i").arg(path), None)?;
+        if !output.status.success() {
+            return Err(SeavanError::DockerLoadFailure(stderr_string(output)));
+        }
+
+        // Docker reports e.g. `Loaded image: <reference>` for each image;
+        // Podman reports `Loaded image(s): <reference>,<reference>`.
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .lines()
+            .filter_map(|line| {
+                line.strip_prefix("Loaded image: ")
+                    .or_else(|| line.strip_prefix("Loaded image(s): "))
+            })
+            .flat_map(|references| references.split(','))
+            .map(|reference| reference.trim().to_string())
+            .filter(|reference| !reference.is_empty())
+            .collect())
+    }
+
+    // Creates (without starting) a container of the image `reference`,
+    // returning its ID. Images built from scratch have no command, so a
+    // placeholder is given.
+    pub(crate) fn create_container(&self, reference: &str) -> SeavanResult<String> {
+        let output = self.run(self.command().args(["create", reference, "seavan"]), None)?;
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+            false => Err(SeavanError::DockerContainerFailure(stderr_string(output))),
+        }
+    }
+
+    // Copies `source` out of the container `id` to `destination`.
+    pub(crate) fn copy_from_container(
+        &self,
+        id: &str,
+        source: &str,
+        destination: &Path,
+    ) -> SeavanResult<()> {
+        let output = self.run(
+            self.command()
+                .arg("cp")
+                .arg(format!("{}:{}", id, source))
+                .arg(destination),
+            None,
+        )?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(SeavanError::DockerContainerFailure(stderr_string(output))),
+        }
+    }
+
+    // Removes the container `id`.
+    pub(crate) fn remove_container(&self, id: &str) -> SeavanResult<()> {
+        let output = self.run(self.command().args(["rm", id]), None)?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(SeavanError::DockerContainerFailure(stderr_string(output))),
+        }
+    }
+
+    // Removes the reference `image` from the local image store. The underlying
+    // image is only deleted if no other tags point to it.
+    pub(crate) fn remove_image(&self, image: &str) -> SeavanResult<()> {
+        let output = self.run(self.command().args(["rmi", image]), None)?;
+
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(SeavanError::DockerRemoveFailure(stderr_string(output))),
+        }
+    }
+
+    // Tags the image built under `temporary` as `target`, then drops the
+    // temporary reference.
+    pub(crate) fn promote(&self, temporary: &str, target: &str) -> SeavanResult<()> {
+        let retagged = self.tag(temporary, target);
+        if let Err(e) = self.remove_image(temporary) {
+            debug!("Failed to remove {}: {}", temporary, e);
+        }
+        retagged
+    }
+
+    // Returns the size in bytes of the local image `reference`.
+    pub(crate) fn image_size(&self, reference: &str) -> SeavanResult<u64> {
+        let output = self.run(
+            self.command()
+                .args(["image", "inspect", "--format", "{{.Size}}", reference]),
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(SeavanError::DockerInspectFailure(stderr_string(output)));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.trim().parse().map_err(|_| {
+            SeavanError::DockerInspectFailure(format!("Unexpected image size {:?}", stdout))
+        })
+    }
+
+    // Returns the ID of the local image `reference`, which is the digest of
+    // its configuration.
+    pub(crate) fn image_id(&self, reference: &str) -> SeavanResult<String> {
+        let output = self.run(
+            self.command()
+                .args(["image", "inspect", "--format", "{{.Id}}", reference]),
+            None,
+        )?;
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+            false => Err(SeavanError::DockerInspectFailure(stderr_string(output))),
+        }
+    }
+
+    // Returns the value of `label` on the local image `reference`, if it
+    // exists and has the label.
+    pub(crate) fn local_label_value(&self, reference: &str, label: &str) -> Option<String> {
+        let output = self
+            .run(
+                self.command()
+                    .args(["image", "inspect", "--format"])
+                    .arg(format!("{{{{index .Config.Labels {:?}}}}}", label))
+                    .arg(reference),
+                None,
+            )
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        match value.is_empty() || value == "<no value>" {
+            true => None,
+            false => Some(value),
+        }
+    }
+
+    // Returns every label on the local image `reference`.
+    pub(crate) fn local_labels(&self, reference: &str) -> SeavanResult<BTreeMap<String, String>> {
+        let output = self.run(
+            self.command().args([
+                "image",
+                "inspect",
+                "--format",
+                "{{json .Config.Labels}}",
+                reference,
+            ]),
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(SeavanError::DockerInspectFailure(stderr_string(output)));
+        }
+        // Images without labels have `null` labels.
+        let labels: Option<BTreeMap<String, String>> = serde_json::from_slice(&output.stdout)?;
+        Ok(labels.unwrap_or_default())
+    }
+
+    // Returns the references of every tagged local image with `label` set to
+    // `value`, or with `label` set at all if `value` is `None`.
+    pub(crate) fn local_references_with_label(
+        &self,
+        label: &str,
+        value: Option<&str>,
+    ) -> SeavanResult<Vec<String>> {
+        let filter = match value {
+            Some(value) => format!("label={}={}", label, value),
+            None => format!("label={}", label),
+        };
+        let output = self.run(
+            self.command()
+                .args(["images", "--format", "{{.Repository}}:{{.Tag}}", "--filter"])
+                .arg(filter),
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(SeavanError::DockerInspectFailure(stderr_string(output)));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|reference| !reference.is_empty() && !reference.contains("<none>"))
+            .map(String::from)
+            .collect())
+    }
+
+    // Returns the values of `label` on every local image in `repository`. Images
+    // without the label are skipped.
+    pub(crate) fn local_label_values(
+        &self,
+        repository: &str,
+        label: &str,
+    ) -> SeavanResult<Vec<String>> {
+        let output = self.run(
+            self.command()
+                .args(["images", "-q", "--no-trunc", "--filter"])
+                .arg(format!("reference={}", repository)),
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(SeavanError::DockerInspectFailure(stderr_string(output)));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let ids: Vec<&str> = stdout.lines().filter(|id| !id.is_empty()).collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let output = self.run(
+            self.command()
+                .args(["image", "inspect", "--format"])
+                .arg(format!("{{{{index .Config.Labels {:?}}}}}", label))
+                .args(ids),
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(SeavanError::DockerInspectFailure(stderr_string(output)));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|value| !value.is_empty() && *value != "<no value>")
+            .map(String::from)
+            .collect())
+    }
+
+    // Checks whether the image `reference` exists in its registry by fetching
+    // its manifest, without pulling it.
+    pub(crate) fn remote_exists(&self, reference: &str) -> SeavanResult<bool> {
+        let output = self.run(
+            self.registry_command(reference)?
+                .args(self.manifest_inspect_args(reference)),
+            None,
+        )?;
+        if output.status.success() {
+            return Ok(true);
+        }
+        let stderr = stderr_string(output);
+        match reports_missing_manifest(&stderr) {
+            true => Ok(false),
+            false => Err(SeavanError::DockerInspectFailure(stderr)),
+        }
+    }
+
+    // Returns the value of `label` on the remote image `reference`, if it exists
+    // and can be inspected.
+    pub(crate) fn remote_label_value(&self, reference: &str, label: &str) -> Option<String> {
+        match self.remote_label(reference, label) {
+            Ok(value) => value,
+            Err(e) => {
+                debug!("Could not inspect remote image {}: {}", reference, e);
+                None
+            }
+        }
+    }
+
+    // Returns the value of `label` on the remote image `reference`, as
+    // `remote_label` does, or `None` if the registry reports that the image
+    // doesn't exist. Other failures, such as refused or failed requests, are
+    // still errors.
+    pub(crate) fn remote_label_if_exists(
+        &self,
+        reference: &str,
+        label: &str,
+    ) -> SeavanResult<Option<String>> {
+        match self.remote_label(reference, label) {
+            Err(SeavanError::DockerInspectFailure(stderr)) if reports_missing_manifest(&stderr) => {
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    // Returns the value of `label` on the remote image `reference`, if it
+    // has the label. Fails if the image can't be inspected.
+    pub(crate) fn remote_label(
+        &self,
+        reference: &str,
+        label: &str,
+    ) -> SeavanResult<Option<String>> {
+        let mut command = self.registry_command(reference)?;
+        let _ = command.args(["buildx", "imagetools", "inspect"]);
+        if self.insecure(reference) {
+            let _ = command.args(["--builder", &self.insecure_builder()?]);
+        }
+        let output = self.run(
+            command
+                .args([reference, "--format"])
+                .arg(format!("{{{{index .Image.Config.Labels {:?}}}}}", label)),
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(SeavanError::DockerInspectFailure(stderr_string(output)));
+        }
+
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        match value.is_empty() || value == "<no value>" {
+            true => Ok(None),
+            false => Ok(Some(value)),
+        }
+    }
+}
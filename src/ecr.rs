@@ -0,0 +1,162 @@
+//! Helpers for storing images in AWS Elastic Container Registry.
+//!
+//! ECR requires repositories to exist before images can be pushed to them,
+//! and uses short-lived tokens for authentication. These helpers use the
+//! `aws` CLI, which must be installed and configured with credentials.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    registry::Registry,
+};
+use log::debug;
+use std::process::{Command, Output};
+
+// Runs an aws CLI command, returning its output on success.
+fn aws(args: &[&str]) -> SeavanResult<Output> {
+    let output = Command::new("aws").args(args).output()?;
+    match output.status.success() {
+        true => Ok(output),
+        false => Err(SeavanError::AwsCliFailure(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )),
+    }
+}
+
+// Splits an image reference into its ECR region and repository name.
+fn parse_reference(reference: &str) -> SeavanResult<(&str, &str)> {
+    let invalid = || SeavanError::NotAnEcrReference(reference.into());
+
+    let (host, repository) = reference.split_once('/').ok_or_else(invalid)?;
+    let region = region_of(host).ok_or_else(invalid)?;
+    Ok((region, crate::naming::repository_of(repository)))
+}
+
+// Returns the region of an ECR registry host, e.g. `eu-west-1` for
+// `123456789012.dkr.ecr.eu-west-1.amazonaws.com`.
+fn region_of(host: &str) -> Option<&str> {
+    let parts: Vec<&str> = host.split('.').collect();
+    match parts.as_slice() {
+        [_account, "dkr", "ecr", region, "amazonaws", "com"] => Some(region),
+        _ => None,
+    }
+}
+
+/// Returns a `Registry` for the ECR registry of the given AWS account and
+/// region.
+///
+/// # Arguments
+///
+/// * `account_id`: The 12 digit AWS account ID.
+/// * `region`: The AWS region, e.g. `eu-west-1`.
+///
+/// # Examples
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{ecr, Seavan};
+/// let wrap = Seavan::new("README.md")?.with_registry(ecr::registry("123456789012", "eu-west-1")?)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn registry(account_id: &str, region: &str) -> SeavanResult<Registry> {
+    let valid_account = account_id.len() == 12 && account_id.chars().all(|c| c.is_ascii_digit());
+    if !valid_account {
+        return Err(SeavanError::InvalidRegistryComponent(account_id.into()));
+    }
+    let valid_region = !region.is_empty()
+        && region
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !valid_region {
+        return Err(SeavanError::InvalidRegistryComponent(region.into()));
+    }
+
+    Ok(Registry::new(&format!(
+        "{}.dkr.ecr.{}.amazonaws.com",
+        account_id, region
+    )))
+}
+
+/// Makes sure the ECR repository for an image reference exists, creating it
+/// if necessary.
+///
+/// # Arguments
+///
+/// * `reference`: An image reference in an ECR registry, such as one
+///   returned by `Seavan::repository_name_and_tag`.
+pub fn ensure_repository(reference: &str) -> SeavanResult<()> {
+    let (region, repository) = parse_reference(reference)?;
+
+    let described = Command::new("aws")
+        .args([
+            "ecr",
+            "describe-repositories",
+            "--region",
+            region,
+            "--repository-names",
+            repository,
+        ])
+        .output()?;
+    if described.status.success() {
+        debug!("ECR repository {} already exists", repository);
+        return Ok(());
+    }
+
+    // Only create the repository if it genuinely doesn't exist, so that
+    // other failures such as missing credentials are reported as-is.
+    let stderr = String::from_utf8_lossy(&described.stderr);
+    if !stderr.contains("RepositoryNotFoundException") {
+        return Err(SeavanError::AwsCliFailure(stderr.into_owned()));
+    }
+
+    debug!("Creating ECR repository {}", repository);
+    let _ = aws(&[
+        "ecr",
+        "create-repository",
+        "--region",
+        region,
+        "--repository-name",
+        repository,
+    ])?;
+    Ok(())
+}
+
+/// Fetches a short-lived authentication token for ECR registries in the
+/// given region. The token is used as the password for the `AWS` user.
+///
+/// # Arguments
+///
+/// * `region`: The AWS region, e.g. `eu-west-1`.
+pub fn login_password(region: &str) -> SeavanResult<String> {
+    let output = aws(&["ecr", "get-login-password", "--region", region])?;
+    let password =
+        String::from_utf8(output.stdout).map_err(|_| SeavanError::FailedStrConversion)?;
+    Ok(password.trim().to_string())
+}
+
+/// Returns the username and a freshly fetched token to authenticate to an
+/// ECR registry with. Pass them to `Seavan::with_credentials` or
+/// `client::Client::with_credentials`, which give them to docker without
+/// changing the user's own docker configuration.
+///
+/// # Arguments
+///
+/// * `registry`: The ECR registry, as returned by `registry`.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{ecr, Seavan};
+/// let registry = ecr::registry("123456789012", "eu-west-1")?;
+/// let (username, password) = ecr::credentials(&registry)?;
+/// let pushed = Seavan::new("README.md")?
+///     .with_registry(registry)?
+///     .with_credentials(&username, &password)?
+///     .create_and_push()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn credentials(registry: &Registry) -> SeavanResult<(String, String)> {
+    let host = registry.name();
+    let region = region_of(host).ok_or_else(|| SeavanError::NotAnEcrReference(host.into()))?;
+    Ok(("AWS".into(), login_password(region)?))
+}
@@ -0,0 +1,95 @@
+//! Container engines which seavan can drive.
+//!
+//! Every command seavan runs is a `docker` command line. Podman and nerdctl
+//! accept the same command lines, so they can be used in its place on hosts
+//! without Docker.
+
+use crate::error::{SeavanError, SeavanResult};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// A container engine with a Docker-compatible command line.
+///
+/// Implement this to run a different program, for example a wrapper script
+/// or an engine installed outside `PATH`.
+pub trait ContainerEngine: fmt::Debug + Send + Sync {
+    /// Returns the program to run, e.g. `docker`.
+    fn program(&self) -> &str;
+}
+
+/// The container engines seavan knows about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    /// Whichever of Docker, Podman and nerdctl is found first on `PATH`,
+    /// falling back to Docker. This is the default.
+    #[default]
+    Auto,
+
+    /// Docker, run as `docker`.
+    Docker,
+
+    /// Podman, run as `podman`.
+    Podman,
+
+    /// nerdctl, the containerd command line, run as `nerdctl`.
+    Nerdctl,
+}
+
+impl Engine {
+    /// Returns the first of Docker, Podman and nerdctl which is installed on
+    /// `PATH`, or Docker if none are. The result is cached for the life of
+    /// the process.
+    pub fn detect() -> Engine {
+        static DETECTED: OnceLock<Engine> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            [Engine::Docker, Engine::Podman, Engine::Nerdctl]
+                .iter()
+                .copied()
+                .find(|engine| {
+                    std::env::split_paths(&path).any(|dir| installed(&dir, engine.executable()))
+                })
+                .unwrap_or(Engine::Docker)
+        })
+    }
+
+    // Returns the program run for this engine.
+    fn executable(self) -> &'static str {
+        match self {
+            Engine::Auto => Engine::detect().executable(),
+            Engine::Docker => "docker",
+            Engine::Podman => "podman",
+            Engine::Nerdctl => "nerdctl",
+        }
+    }
+}
+
+// Checks whether `program` is in the directory `dir`.
+fn installed(dir: &Path, program: &str) -> bool {
+    dir.join(format!("{}{}", program, std::env::consts::EXE_SUFFIX))
+        .is_file()
+}
+
+impl ContainerEngine for Engine {
+    fn program(&self) -> &str {
+        self.executable()
+    }
+}
+
+impl FromStr for Engine {
+    type Err = SeavanError;
+
+    fn from_str(engine: &str) -> SeavanResult<Self> {
+        match engine {
+            "auto" => Ok(Engine::Auto),
+            "docker" => Ok(Engine::Docker),
+            "podman" => Ok(Engine::Podman),
+            "nerdctl" => Ok(Engine::Nerdctl),
+            _ => Err(SeavanError::InvalidEngine(engine.into())),
+        }
+    }
+}
@@ -1,7 +1,29 @@
 //! Error types for seavan
 
+use crate::{report::PartialResult, rules::Violation};
 use std::path::PathBuf;
 
+/// Exit code for errors without a more specific category.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Exit code for invalid arguments or configuration.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Exit code for files which could not be found.
+pub const EXIT_FILE_NOT_FOUND: i32 = 3;
+
+/// Exit code for when Docker could not be run.
+pub const EXIT_DOCKER_UNAVAILABLE: i32 = 4;
+
+/// Exit code for failures while building images.
+pub const EXIT_BUILD_FAILED: i32 = 5;
+
+/// Exit code for failures to authenticate with a registry.
+pub const EXIT_AUTH_FAILED: i32 = 6;
+
+/// Exit code for content which didn't match its expected digest.
+pub const EXIT_VERIFICATION_MISMATCH: i32 = 7;
+
 /// Types of error for seavan
 #[derive(thiserror::Error, Debug)]
 pub enum SeavanError {
@@ -13,26 +35,408 @@ pub enum SeavanError {
     #[error("{0:?} has no directory")]
     NoDirectory(PathBuf),
 
+    /// A component of the given path is longer than the platform allows.
+    /// Holds the path, the offending component and the limit in bytes.
+    #[error("{0:?} has component {1:?} longer than {2} bytes")]
+    PathComponentTooLong(PathBuf, String, usize),
+
+    /// The given path is longer than the platform allows. Holds the path and
+    /// the limit in bytes.
+    #[error("{0:?} is longer than {1} bytes")]
+    PathTooLong(PathBuf, usize),
+
+    /// The given file handle is not a regular file.
+    #[error("{0:?} is not a regular file")]
+    NotAFile(PathBuf),
+
     /// A string conversion operation failed.
     #[error("Failed string conversion")]
     FailedStrConversion,
 
+    /// The given string contains characters which are not supported in image
+    /// references, and strict sanitization was requested.
+    #[error("{0:?} contains unsupported characters")]
+    UnsupportedCharacters(String),
+
+    /// An image label has a value which could not be parsed.
+    #[error("Label {0} has invalid value {1:?}")]
+    InvalidLabel(String, String),
+
+    /// The requested hash length is out of range.
+    #[error("Hash length {0} must be between 8 and 64")]
+    InvalidHashLength(usize),
+
+    /// The requested tree hash segment size is zero.
+    #[error("Hash segment size {0} must be greater than zero")]
+    InvalidSegmentSize(u64),
+
+    /// The requested average chunk size is out of range.
+    #[error("Chunk size {0} must be a power of two between 1 KiB and 1 GiB")]
+    InvalidChunkSize(usize),
+
+    /// The given fixed chunk size is out of range.
+    #[error("Fixed chunk size {0} must be between 1 KiB and 1 GiB")]
+    InvalidChunkLimit(usize),
+
+    /// The file splits into more chunks than an image can hold. Use a larger
+    /// chunk size.
+    #[error("{0:?} splits into {1} chunks, which is too many for one image")]
+    TooManyChunks(PathBuf, usize),
+
+    /// Content extracted from an image doesn't match the digest it was
+    /// wrapped with. Holds the image reference, and the expected and actual
+    /// digests.
+    #[error("Content of {0} has digest {2}, expected {1}")]
+    ContentMismatch(String, String, String),
+
+    /// An image holds a link, special file or path which extracting could
+    /// use to read or replace files outside the destination, such as a
+    /// symlink in place of the wrapped file. Holds the image reference and
+    /// the path in the image.
+    #[error("{0} holds {1:?}, which can't be safely extracted")]
+    UnsafeEntry(String, String),
+
+    /// A chunk of a chunked file doesn't match its hash. Holds the expected
+    /// and actual hashes.
+    #[error("Chunk {0} has hash {1}")]
+    ChunkMismatch(String, String),
+
+    /// An existing repository has the same (truncated) name as the image
+    /// being built, but holds different content.
+    #[error("Repository {0} already holds different content ({1})")]
+    HashCollision(String, String),
+
     /// Banned registry prefix: docker.io
     #[error("Banned registry prefix: docker.io")]
     BannedRegistryPrefix,
 
+    /// The given image reference breaks the registry's naming rules.
+    #[error("Invalid reference {0:?}: {1}")]
+    InvalidReference(String, String),
+
+    /// The file is rejected by a policy.
+    #[error("{0:?} is rejected by policy")]
+    PolicyRejected(PathBuf),
+
+    /// The file or its image breaks a rule.
+    #[error("{0:?} breaks a rule: {1}")]
+    RuleViolation(PathBuf, Violation),
+
+    /// A scanner refused to wrap or push the file.
+    #[error("{0:?} was rejected by a scanner: {1}")]
+    ScanRejected(PathBuf, String),
+
+    /// An image was requested from a reference-only `Seavan`, which has no
+    /// file on disk to build from.
+    #[error("{0:?} is reference-only and can't be built")]
+    ReferenceOnly(PathBuf),
+
+    /// The given platform is not of the form `os/architecture[/variant]`.
+    #[error("Invalid platform {0:?}")]
+    InvalidPlatform(String),
+
+    /// The given label key is empty, contains characters which aren't
+    /// allowed, or is in the `seavan.` namespace reserved for seavan's own
+    /// labels.
+    #[error("Invalid label key {0:?}")]
+    InvalidLabelKey(String),
+
+    /// The value of the label with the given key contains control
+    /// characters, such as newlines, which can't be written to a Dockerfile.
+    #[error("Invalid value for label {0:?}")]
+    InvalidLabelValue(String),
+
+    /// The given destination is not an absolute path to a file, or contains
+    /// characters which aren't allowed.
+    #[error("Invalid destination {0:?}")]
+    InvalidDestination(String),
+
+    /// The given compression level is out of range for its algorithm.
+    #[error("Invalid compression level {0}")]
+    InvalidCompressionLevel(u32),
+
+    /// There was a failure while compressing or decompressing a file.
+    #[error("Compression failure: {0}")]
+    CompressionFailure(String),
+
+    /// A destination was set for a chunked image, which holds chunks rather
+    /// than the file itself.
+    #[error("Chunked images can't have a destination")]
+    ChunkedDestination,
+
+    /// The given tag policy is not one of `latest`, `short-hash` or
+    /// `timestamp`.
+    #[error("Invalid tag policy {0:?}")]
+    InvalidTagPolicy(String),
+
+    /// The given container engine is not one of `auto`, `docker`, `podman`
+    /// or `nerdctl`.
+    #[error("Invalid container engine {0:?}")]
+    InvalidEngine(String),
+
+    /// The given package root is not a valid repository path.
+    #[error("Invalid package root {0:?}")]
+    InvalidPackageRoot(String),
+
+    /// A configuration file could not be parsed.
+    #[error("Invalid configuration file {0:?}: {1}")]
+    InvalidConfig(PathBuf, #[source] toml::de::Error),
+
+    /// A lockfile could not be parsed.
+    #[error("Invalid lockfile {0:?}: {1}")]
+    InvalidLockfile(PathBuf, #[source] toml::de::Error),
+
+    /// The image was built by a later version of seavan with an incompatible
+    /// layout. Holds the image reference and its format version.
+    #[error("{0} has format version {1}, which this version of seavan can't read")]
+    UnsupportedFormatVersion(String, u32),
+
+    /// The given image wasn't built by seavan, so has no wrapped file, or it
+    /// was renamed so its repository name no longer holds the content hash.
+    #[error("{0} is not a seavan image")]
+    NotWrapped(String),
+
+    /// The given image isn't an aggregate image, or couldn't be inspected.
+    #[error("{0} is not a composed image")]
+    NotComposed(String),
+
+    /// The named artifact isn't pinned in the lockfile.
+    #[error("Artifact {0:?} is not locked")]
+    NotLocked(String),
+
+    /// An artifact no longer matches its pin in the lockfile. Holds the
+    /// artifact name, the pinned value and the current value.
+    #[error("Artifact {0:?} doesn't match the lockfile: expected {1}, found {2}")]
+    LockMismatch(String, String, String),
+
+    /// A component of a registry name, such as an owner or project, is not
+    /// valid.
+    #[error("Invalid registry component {0:?}")]
+    InvalidRegistryComponent(String),
+
+    /// The requested option is not supported by this kind of registry.
+    #[error("Registry does not support option: {0}")]
+    UnsupportedRegistryOption(String),
+
+    /// The requested option is not supported when building images natively
+    /// with `Seavan::with_oci_output`.
+    #[error("Native OCI builds do not support option: {0}")]
+    UnsupportedNativeOption(String),
+
     /// There was a failure while calling Docker to build the image.
     #[error("Docker build failure: {0}")]
     DockerBuildFailure(String),
 
+    /// There was a failure while calling Docker to tag the image.
+    #[error("Docker tag failure: {0}")]
+    DockerTagFailure(String),
+
+    /// There was a failure while calling Docker to remove an image.
+    #[error("Docker remove failure: {0}")]
+    DockerRemoveFailure(String),
+
+    /// There was a failure while calling Docker to inspect images.
+    #[error("Docker inspect failure: {0}")]
+    DockerInspectFailure(String),
+
+    /// There was a failure while calling Docker to pull an image.
+    #[error("Docker pull failure: {0}")]
+    DockerPullFailure(String),
+
+    /// An image was pushed without a registry to push it to. Configure one
+    /// with `Seavan::with_registry` or the `SEAVAN_REGISTRY` environment
+    /// variable.
+    #[error("No registry is configured for {0:?}")]
+    NoRegistry(PathBuf),
+
+    /// Registry credentials given to seavan, e.g. with
+    /// `Seavan::with_credentials`, are needed by a docker command run on
+    /// another host by a `runner::CommandRunner` such as
+    /// `runner::SshRunner`. The credentials are only written on this host,
+    /// so log in to the registry on the other host instead.
+    #[error("Credentials for {0} can't be used by docker commands run on another host")]
+    RemoteCredentials(String),
+
+    /// Two inputs of a `bundle::SeavanBundle` hold a file at the same path.
+    #[error("More than one file in the bundle has the path {0:?}")]
+    DuplicateBundlePath(String),
+
+    /// The given image holds a `bundle::SeavanBundle`, which can't be
+    /// extracted as a single file.
+    #[error("{0} is a bundle of several files, so can't be extracted as one file")]
+    BundleImage(String),
+
+    /// There was a failure while calling Docker to save an image to an
+    /// archive.
+    #[error("Docker save failure: {0}")]
+    DockerSaveFailure(String),
+
+    /// There was a failure while calling Docker to load an image from an
+    /// archive.
+    #[error("Docker load failure: {0}")]
+    DockerLoadFailure(String),
+
+    /// There was a failure while calling Docker to push an image.
+    #[error("Docker push failure: {0}")]
+    DockerPushFailure(String),
+
+    /// The given credential helper name isn't the suffix of a
+    /// `docker-credential-` program.
+    #[error("Invalid credential helper {0:?}")]
+    InvalidCredentialHelper(String),
+
+    /// A pushed image couldn't be signed with the signer configured by
+    /// `Seavan::with_signer`. The image has been pushed, unsigned.
+    #[error("Failed to sign {0}: {1}")]
+    SigningFailure(String, String),
+
+    /// The registry project (or repository key) images are pushed to doesn't
+    /// exist. Registries such as Harbor require projects to be created
+    /// before pushing.
+    #[error("Registry project {0:?} does not exist")]
+    ProjectNotFound(String),
+
+    /// Pushing an image would take a registry over its storage quota or the
+    /// configured storage budget.
+    #[error("Pushing {1} bytes to {0} would exceed its storage limit ({2} bytes available)")]
+    QuotaExceeded(String, u64, u64),
+
+    /// The operation's deadline passed before it finished. Holds the progress
+    /// made before the remaining phases were skipped or cancelled.
+    #[error("Deadline exceeded: {0}")]
+    DeadlineExceeded(Box<PartialResult>),
+
+    /// A webhook could not be notified about a pushed image. The image was
+    /// pushed successfully.
+    #[error("Webhook failure: {0}")]
+    WebhookFailure(String),
+
+    /// A request to a registry's API failed.
+    #[error("Registry API failure: {0}")]
+    RegistryApiFailure(String),
+
+    /// A registry refused the credentials given to it, or none were given,
+    /// or its token service didn't issue a token. Returned for pushes which
+    /// Docker reports as unauthorized or denied.
+    #[error("Registry authentication failure: {0}")]
+    RegistryAuthFailure(String),
+
+    /// There was a failure while calling Docker to create, copy from or
+    /// remove a container.
+    #[error("Docker container failure: {0}")]
+    DockerContainerFailure(String),
+
+    /// There was a failure while calling the az CLI. Only returned with the
+    /// `azure` feature.
+    #[error("az CLI failure: {0}")]
+    AzCliFailure(String),
+
+    /// There was a failure while calling the aws CLI. Only returned with the
+    /// `aws` feature; the variant exists without it, so matches on
+    /// `SeavanError` don't depend on the features enabled.
+    #[error("aws CLI failure: {0}")]
+    AwsCliFailure(String),
+
+    /// The given image reference or registry is not in an ECR registry. Only
+    /// returned with the `aws` feature.
+    #[error("{0:?} is not an ECR reference")]
+    NotAnEcrReference(String),
+
+    /// Docker could not be run, or its daemon couldn't be reached. Check that
+    /// Docker is installed, on the `PATH`, and running.
+    #[error("Docker unavailable")]
+    DockerUnavailable(#[source] std::io::Error),
+
     /// Standard io error.
     #[error("io error")]
     IoError(#[from] std::io::Error),
 
+    /// Error serializing or deserializing JSON.
+    #[error("json error")]
+    JsonError(#[from] serde_json::Error),
+
+    /// Error serializing TOML.
+    #[error("toml error")]
+    TomlError(#[from] toml::ser::Error),
+
     /// Error with safe string replacement
     #[error("regex error")]
     RegexError(#[from] regex::Error),
 }
 
+impl SeavanError {
+    /// Returns the process exit code for this error, so that scripts can
+    /// distinguish categories of failure:
+    ///
+    /// | Code | Constant                     | Category                       |
+    /// |------|------------------------------|--------------------------------|
+    /// | 1    | `EXIT_FAILURE`               | Other failure                  |
+    /// | 2    | `EXIT_USAGE`                 | Invalid arguments              |
+    /// | 3    | `EXIT_FILE_NOT_FOUND`        | File not found                 |
+    /// | 4    | `EXIT_DOCKER_UNAVAILABLE`    | Docker could not be run        |
+    /// | 5    | `EXIT_BUILD_FAILED`          | Image build failed             |
+    /// | 6    | `EXIT_AUTH_FAILED`           | Registry authentication failed |
+    /// | 7    | `EXIT_VERIFICATION_MISMATCH` | Content didn't match digest    |
+    ///
+    /// Only I/O errors reporting a missing file or directory are
+    /// `EXIT_FILE_NOT_FOUND`; other I/O errors, such as permission errors,
+    /// are `EXIT_FAILURE`. The cause is the error's `source()`, which the
+    /// command line interface prints after the error, e.g.
+    /// `io error: No such file or directory (os error 2)`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SeavanError::NoFileName(_)
+            | SeavanError::NoDirectory(_)
+            | SeavanError::NotAFile(_)
+            | SeavanError::PathComponentTooLong(_, _, _)
+            | SeavanError::PathTooLong(_, _)
+            | SeavanError::UnsupportedCharacters(_)
+            | SeavanError::InvalidHashLength(_)
+            | SeavanError::InvalidSegmentSize(_)
+            | SeavanError::InvalidChunkSize(_)
+            | SeavanError::InvalidChunkLimit(_)
+            | SeavanError::TooManyChunks(_, _)
+            | SeavanError::InvalidReference(_, _)
+            | SeavanError::InvalidRegistryComponent(_)
+            | SeavanError::InvalidPackageRoot(_)
+            | SeavanError::InvalidTagPolicy(_)
+            | SeavanError::InvalidLabelKey(_)
+            | SeavanError::InvalidLabelValue(_)
+            | SeavanError::InvalidDestination(_)
+            | SeavanError::InvalidCompressionLevel(_)
+            | SeavanError::InvalidCredentialHelper(_)
+            | SeavanError::ChunkedDestination
+            | SeavanError::InvalidEngine(_)
+            | SeavanError::InvalidPlatform(_)
+            | SeavanError::InvalidConfig(_, _)
+            | SeavanError::InvalidLockfile(_, _)
+            | SeavanError::NotLocked(_)
+            | SeavanError::UnsupportedRegistryOption(_)
+            | SeavanError::UnsupportedNativeOption(_)
+            | SeavanError::ReferenceOnly(_)
+            | SeavanError::NoRegistry(_)
+            | SeavanError::RemoteCredentials(_)
+            | SeavanError::DuplicateBundlePath(_)
+            | SeavanError::BundleImage(_)
+            | SeavanError::PolicyRejected(_)
+            | SeavanError::ScanRejected(_, _)
+            | SeavanError::RuleViolation(_, _)
+            | SeavanError::BannedRegistryPrefix => EXIT_USAGE,
+            SeavanError::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                EXIT_FILE_NOT_FOUND
+            }
+            SeavanError::DockerUnavailable(_) => EXIT_DOCKER_UNAVAILABLE,
+            SeavanError::DockerBuildFailure(_) | SeavanError::DockerTagFailure(_) => {
+                EXIT_BUILD_FAILED
+            }
+            SeavanError::RegistryAuthFailure(_) => EXIT_AUTH_FAILED,
+            SeavanError::LockMismatch(_, _, _)
+            | SeavanError::ChunkMismatch(_, _)
+            | SeavanError::ContentMismatch(_, _, _) => EXIT_VERIFICATION_MISMATCH,
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
 /// Result wrapper for `SeavanError`
 pub type SeavanResult<T> = Result<T, SeavanError>;
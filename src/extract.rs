@@ -0,0 +1,468 @@
+//! Extraction of wrapped files from their images.
+
+use crate::{
+    bundle::{self, BundleEntry},
+    chunk,
+    client::ExtractPolicy,
+    compression::Compression,
+    docker::Docker,
+    error::{SeavanError, SeavanResult},
+    hash::{self, HashAlgorithm},
+    labels, naming,
+    report::VerificationReport,
+    utils, HASH_LENGTH, MIN_HASH_LENGTH,
+};
+use log::debug;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Files and directories docker adds to the root of every container. These
+// aren't part of the image, so aren't checked.
+const CONTAINER_FILES: &[&str] = &[".dockerenv", "dev", "etc", "proc", "sys"];
+
+// Checks that a file can be extracted from the image `reference` before
+// it's pulled.
+pub(crate) fn check_reference(reference: &str) -> SeavanResult<()> {
+    naming::validate_reference(reference)?;
+    // The content is checked against the hash in the repository name, which
+    // images renamed after they were built no longer have.
+    match named_hash(reference).1.is_empty() {
+        true => Err(SeavanError::NotWrapped(reference.into())),
+        false => Ok(()),
+    }
+}
+
+// Extracts the file wrapped in the image `reference` into `destination`
+// using the given docker settings, returning the path of the extracted file.
+// Everything copied out of the image is checked according to `policy`.
+pub(crate) fn extract(
+    docker: &Docker,
+    reference: &str,
+    destination: &Path,
+    policy: ExtractPolicy,
+) -> SeavanResult<PathBuf> {
+    check_reference(reference)?;
+    let (named_algorithm, named_hash) = named_hash(reference);
+    if docker.image_id(reference).is_err() {
+        debug!("Pulling {}", reference);
+        docker.pull(reference)?;
+    }
+
+    let label = |key: &str| docker.local_label_value(reference, key);
+    let _ = labels::check_format_version(reference, label(labels::FORMAT_VERSION).as_deref())?;
+    if label(labels::BUNDLE).is_some() {
+        return Err(SeavanError::BundleImage(reference.into()));
+    }
+    let expected =
+        label(labels::CONTENT_DIGEST).ok_or_else(|| SeavanError::NotWrapped(reference.into()))?;
+    let scheme = match label(labels::HASH_SCHEME) {
+        Some(scheme) => hash::parse_scheme(&scheme)
+            .ok_or_else(|| SeavanError::InvalidLabel(labels::HASH_SCHEME.into(), scheme))?,
+        None => hash::Scheme::default(),
+    };
+    let expected_hash = expected
+        .strip_prefix(&hash::digest(scheme, ""))
+        .ok_or_else(|| {
+            SeavanError::InvalidLabel(labels::CONTENT_DIGEST.into(), expected.clone())
+        })?;
+    let filename = match label(labels::ORIGINAL_FILENAME) {
+        Some(encoded) => Some(bare_filename(&encoded)?),
+        None => None,
+    };
+    let location = match label(labels::DESTINATION) {
+        Some(location) => {
+            utils::validate_destination(&location).map_err(|_| {
+                SeavanError::InvalidLabel(labels::DESTINATION.into(), location.clone())
+            })?;
+            Some(location)
+        }
+        None => None,
+    };
+    let compression = match label(labels::COMPRESSION) {
+        Some(value) => Some(
+            Compression::from_label(&value)
+                .ok_or_else(|| SeavanError::InvalidLabel(labels::COMPRESSION.into(), value))?,
+        ),
+        None => None,
+    };
+    let chunks = match label(labels::CHUNKS) {
+        Some(value) => Some(
+            serde_json::from_str::<chunk::ChunkIndex>(&value)
+                .map_err(|_| SeavanError::InvalidLabel(labels::CHUNKS.into(), value))?,
+        ),
+        None => None,
+    };
+    let extension = compression.as_ref().map_or("", Compression::extension);
+
+    // Stage the file next to its destination, so it can be moved into place
+    // once it's verified.
+    fs::create_dir_all(destination)?;
+    let staging = tempfile::Builder::new()
+        .prefix(".seavan")
+        .tempdir_in(destination)?;
+    let container = docker.create_container(reference)?;
+    let copy = Copy {
+        docker,
+        reference,
+        container: &container,
+        staging: staging.path(),
+        policy,
+    };
+    let copied = match label(labels::CHUNKING) {
+        Some(_) => copy.chunked(chunks.as_ref()).map(|staged| {
+            let name = filename.unwrap_or_else(|| expected_hash.into());
+            (staged, name)
+        }),
+        None => match location {
+            Some(location) => copy.located(&location, filename),
+            None => copy.file(filename, extension),
+        },
+    };
+    if let Err(e) = docker.remove_container(&container) {
+        debug!("Failed to remove container {}: {}", container, e);
+    }
+    let (staged, name) = copied?;
+    let staged = match compression {
+        Some(compression) => {
+            let decompressed = staging.path().join("decompressed");
+            compression.decompress(&staged, &decompressed)?;
+            decompressed
+        }
+        None => staged,
+    };
+
+    let actual = hash::digest(scheme, &hash::hash_file(&staged, scheme)?);
+    if actual != expected {
+        return Err(SeavanError::ContentMismatch(
+            reference.into(),
+            expected,
+            actual,
+        ));
+    }
+    // The repository name holds the hash, possibly truncated. Hashes other
+    // than SHA-256 are named with their algorithm, which must match the one
+    // the image was hashed with.
+    if named_algorithm.is_some_and(|algorithm| algorithm != scheme.algorithm)
+        || !expected_hash.starts_with(named_hash)
+    {
+        return Err(SeavanError::ContentMismatch(
+            reference.into(),
+            named_hash.into(),
+            expected_hash.into(),
+        ));
+    }
+
+    let target = destination.join(name);
+    fs::rename(&staged, &target)?;
+    Ok(target)
+}
+
+// Checks the file at `path` against the hash in the repository name of
+// `reference`. The hash scheme recorded on the image is used when the image
+// is available locally, so tree hashes can be checked too. A directory is
+// checked against the bundle in `reference`.
+pub(crate) fn verify(
+    docker: &Docker,
+    reference: &str,
+    path: &Path,
+) -> SeavanResult<VerificationReport> {
+    naming::validate_reference(reference)?;
+    let (named_algorithm, expected) = named_hash(reference);
+    if expected.is_empty() {
+        return Err(SeavanError::NotWrapped(reference.into()));
+    }
+    let named_algorithm = named_algorithm.unwrap_or_default();
+    let scheme = match docker.local_label_value(reference, labels::HASH_SCHEME) {
+        Some(scheme) => hash::parse_scheme(&scheme)
+            .ok_or_else(|| SeavanError::InvalidLabel(labels::HASH_SCHEME.into(), scheme))?,
+        None => hash::Scheme {
+            algorithm: named_algorithm,
+            segment_size: None,
+        },
+    };
+
+    if path.is_dir() {
+        return verify_bundle(docker, reference, path, expected);
+    }
+
+    let actual = hash::hash_file(path, scheme)?;
+    Ok(VerificationReport {
+        reference: reference.into(),
+        path: path.to_path_buf(),
+        algorithm: scheme.algorithm,
+        verified: scheme.algorithm == named_algorithm && actual.starts_with(expected),
+        expected: expected.into(),
+        actual,
+        mismatched: Vec::new(),
+    })
+}
+
+// Checks the files in the directory at `path` against the bundle in
+// `reference`, whose repository name holds the hash `expected`. Each file is
+// compared with the bundle's listing when the image is available locally.
+fn verify_bundle(
+    docker: &Docker,
+    reference: &str,
+    path: &Path,
+    expected: &str,
+) -> SeavanResult<VerificationReport> {
+    let (actual, entries) = bundle::directory_entries(path)?;
+    let mut mismatched = Vec::new();
+    if let Some(listing) = docker.local_label_value(reference, labels::BUNDLE) {
+        let listed: Vec<BundleEntry> = serde_json::from_str(&listing)
+            .map_err(|_| SeavanError::InvalidLabel(labels::BUNDLE.into(), listing.clone()))?;
+        mismatched = listed
+            .iter()
+            .filter(|entry| !entries.contains(entry))
+            .chain(entries.iter().filter(|entry| !listed.contains(entry)))
+            .map(|entry| entry.path.clone())
+            .collect();
+        mismatched.sort();
+        mismatched.dedup();
+    }
+    Ok(VerificationReport {
+        reference: reference.into(),
+        path: path.to_path_buf(),
+        algorithm: HashAlgorithm::Sha256,
+        verified: actual.starts_with(expected) && mismatched.is_empty(),
+        expected: expected.into(),
+        actual,
+        mismatched,
+    })
+}
+
+// Decodes an `ORIGINAL_FILENAME` label, checking that it's a bare filename so
+// it can't be used to write outside the destination directory.
+fn bare_filename(encoded: &str) -> SeavanResult<OsString> {
+    let filename = labels::decode_filename(encoded)?;
+    match Path::new(&filename).file_name() == Some(filename.as_os_str()) {
+        true => Ok(filename),
+        false => Err(SeavanError::InvalidLabel(
+            labels::ORIGINAL_FILENAME.into(),
+            encoded.into(),
+        )),
+    }
+}
+
+// Copies files out of the container of the image `reference` into a
+// staging directory. Docker copies links and special files as they are, so
+// everything copied is checked to be a regular file before it's read, and
+// other entries copied alongside it are checked according to `policy`.
+struct Copy<'a> {
+    docker: &'a Docker,
+    reference: &'a str,
+    container: &'a str,
+    staging: &'a Path,
+    policy: ExtractPolicy,
+}
+
+impl Copy<'_> {
+    // Copies `source` out of the container to `destination`.
+    fn copy(&self, source: &str, destination: &Path) -> SeavanResult<()> {
+        self.docker
+            .copy_from_container(self.container, source, destination)
+    }
+
+    // Checks that `staged`, copied from `source` in the image, is a regular
+    // file. Hashing a symlink would follow it on the host, and moving it into
+    // place would replace the destination with the link.
+    fn check_regular_file(&self, staged: &Path, source: &str) -> SeavanResult<()> {
+        match fs::symlink_metadata(staged)?.is_file() {
+            true => Ok(()),
+            false => Err(self.unsafe_entry(source)),
+        }
+    }
+
+    // Creates the error for an unsafe entry at `source` in the image.
+    fn unsafe_entry(&self, source: &str) -> SeavanError {
+        SeavanError::UnsafeEntry(self.reference.into(), source.into())
+    }
+
+    // Checks the entries under `staged`, a directory copied from `source` in
+    // the image, apart from the `skipped` names directly inside it. Symlinks
+    // must resolve within `root`, the top of what was copied, and are refused
+    // altogether by `ExtractPolicy::Strict`. Special files, such as devices
+    // and FIFOs, are refused by `ExtractPolicy::Strict` and removed by
+    // `ExtractPolicy::Lenient`.
+    fn check_tree(
+        &self,
+        root: &Path,
+        staged: &Path,
+        source: &str,
+        skipped: &[&str],
+    ) -> SeavanResult<()> {
+        for entry in fs::read_dir(staged)? {
+            let entry = entry?;
+            if skipped
+                .iter()
+                .any(|name| OsStr::new(name) == entry.file_name())
+            {
+                continue;
+            }
+            let path = entry.path();
+            let source = format!(
+                "{}/{}",
+                source.trim_end_matches('/'),
+                entry.file_name().to_string_lossy()
+            );
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.check_tree(root, &path, &source, &[])?;
+            } else if file_type.is_symlink() {
+                let contained = fs::canonicalize(&path)
+                    .ok()
+                    .zip(fs::canonicalize(root).ok())
+                    .is_some_and(|(target, root)| target.starts_with(root));
+                if !contained || self.policy == ExtractPolicy::Strict {
+                    return Err(self.unsafe_entry(&source));
+                }
+            } else if !file_type.is_file() {
+                if self.policy == ExtractPolicy::Strict {
+                    return Err(self.unsafe_entry(&source));
+                }
+                debug!("Leaving out special file {}", source);
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Copies the file out of the container, returning its path and name.
+    // Files with hidden names are found as the only file in the root.
+    // Compressed files are named with the compression's `extension`, which
+    // is removed from the returned name.
+    fn file(
+        &self,
+        filename: Option<OsString>,
+        extension: &str,
+    ) -> SeavanResult<(PathBuf, OsString)> {
+        if let Some(filename) = filename {
+            // Names which aren't UTF-8 are held under their lossy form.
+            let copied = format!("{}{}", filename.to_string_lossy(), extension);
+            let source = format!("/{}", copied);
+            self.copy(&source, self.staging)?;
+            let staged = self.staging.join(copied);
+            self.check_regular_file(&staged, &source)?;
+            return Ok((staged, filename));
+        }
+
+        let root = self.staging.join("root");
+        fs::create_dir(&root)?;
+        self.copy("/.", &root)?;
+        self.check_tree(&root, &root, "/", CONTAINER_FILES)?;
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&root)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let added_by_docker = CONTAINER_FILES.iter().any(|file| OsStr::new(file) == name);
+            if entry.file_type()?.is_file() && !added_by_docker {
+                files.push(name);
+            }
+        }
+        match files.as_slice() {
+            [filename] => {
+                let name = match filename
+                    .to_str()
+                    .and_then(|name| name.strip_suffix(extension))
+                {
+                    Some(name) if !name.is_empty() => name.into(),
+                    _ => filename.clone(),
+                };
+                Ok((root.join(filename), name))
+            }
+            _ => Err(SeavanError::DockerContainerFailure(format!(
+                "expected one file in the image, found {}",
+                files.len()
+            ))),
+        }
+    }
+
+    // Copies the file at `location` out of the container, returning its path
+    // and name. The file keeps its original name if it's known, otherwise
+    // it's named after its location.
+    fn located(
+        &self,
+        location: &str,
+        filename: Option<OsString>,
+    ) -> SeavanResult<(PathBuf, OsString)> {
+        let filename = match filename {
+            Some(filename) => filename,
+            None => location.rsplit('/').next().unwrap_or_default().into(),
+        };
+        let staged = self.staging.join("file");
+        self.copy(location, &staged)?;
+        self.check_regular_file(&staged, location)?;
+        Ok((staged, filename))
+    }
+
+    // Copies the chunks out of the container and reassembles them in the
+    // order given by `index`, or by the index file in the image if it has no
+    // `CHUNKS` label. Returns the path of the reassembled file.
+    fn chunked(&self, index: Option<&chunk::ChunkIndex>) -> SeavanResult<PathBuf> {
+        let directory = Path::new(chunk::CHUNK_DIRECTORY)
+            .parent()
+            .ok_or_else(|| SeavanError::NoDirectory(chunk::CHUNK_DIRECTORY.into()))?;
+        let source = format!("/{}", directory.display());
+        self.copy(&source, self.staging)?;
+        let copied = self.staging.join(directory);
+        self.check_tree(&copied, &copied, &source, &[])?;
+        let index = match index {
+            Some(index) => index.clone(),
+            None => {
+                let path = self.staging.join(chunk::CHUNK_INDEX);
+                self.check_regular_file(&path, &format!("/{}", chunk::CHUNK_INDEX))?;
+                serde_json::from_reader(io::BufReader::new(File::open(path)?))?
+            }
+        };
+        for hash in &index.chunks {
+            let source = format!("/{}/{}", chunk::CHUNK_DIRECTORY, hash);
+            let path = chunk::chunk_path(hash).ok_or_else(|| self.unsafe_entry(&source))?;
+            self.check_regular_file(&self.staging.join(path), &source)?;
+        }
+
+        let staged = self.staging.join("content");
+        let mut output = File::create(&staged)?;
+        let _ = chunk::reassemble_with_index(self.staging, &index, &mut output)?;
+        Ok(staged)
+    }
+}
+
+// Returns the content hash, possibly truncated, at the start of the last
+// component of the repository name, with the algorithm named in front of it.
+// Registries which don't support nested names, such as Quay, put the package
+// root in front of the component, separated by a `.`, so that is skipped if
+// needed. Returns an empty string if there isn't a hash.
+pub(crate) fn named_hash(reference: &str) -> (Option<HashAlgorithm>, &str) {
+    let repository = naming::repository_of(reference);
+    let component = repository.rsplit('/').next().unwrap_or(repository);
+    std::iter::once(component)
+        .chain(
+            component
+                .match_indices('.')
+                .map(|(i, _)| &component[i + 1..]),
+        )
+        .find_map(leading_hash)
+        .unwrap_or((None, ""))
+}
+
+// Returns the hash at the start of a repository name component, which must be
+// followed by a separator or the end of the component.
+fn leading_hash(component: &str) -> Option<(Option<HashAlgorithm>, &str)> {
+    let (algorithm, rest) = match component.split_once('-') {
+        Some((name, rest)) => match HashAlgorithm::from_name(name) {
+            Some(algorithm) => (Some(algorithm), rest),
+            None => (None, component),
+        },
+        None => (None, component),
+    };
+    let length = rest
+        .bytes()
+        .take_while(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(b))
+        .count();
+    match (length, rest[length..].chars().next()) {
+        (MIN_HASH_LENGTH..=HASH_LENGTH, None | Some('-')) => Some((algorithm, &rest[..length])),
+        _ => None,
+    }
+}
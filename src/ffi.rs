@@ -0,0 +1,181 @@
+//! A minimal C API, for linking seavan into non-Rust build systems.
+//!
+//! Strings returned by these functions are owned by the caller and must be
+//! released with `seavan_string_free`. Functions which fail return `NULL` and
+//! record an error message which can be retrieved with `seavan_last_error`.
+//! Panics are caught at the boundary and reported as failures in the same
+//! way, unless the library is built with `panic = "abort"`. A matching header is in `include/seavan.h`.
+//!
+//! The crate is built as an `rlib` by default. Build a shared library to
+//! link against with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    Seavan,
+};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+thread_local! {
+    // The message of the last error on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// Records `error` as the last error on this thread.
+fn set_last_error(error: &SeavanError) {
+    set_last_error_message(&error.to_string());
+}
+
+// Records `message` as the last error on this thread.
+fn set_last_error_message(message: &str) {
+    // Messages can't contain NULs, so strip any which came from paths.
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Runs `f`, the body of a C API function, returning `failed` after recording
+// the panic as the last error if it panics, so panics never unwind into C.
+pub(crate) fn guard<T, F: FnOnce() -> T>(failed: T, f: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let reason = match payload.downcast_ref::<&str>() {
+            Some(reason) => reason,
+            None => payload
+                .downcast_ref::<String>()
+                .map_or("unknown panic", String::as_str),
+        };
+        set_last_error_message(&format!("seavan panicked: {}", reason));
+        failed
+    })
+}
+
+// Reads the string `s`, failing if it's `NULL` or not UTF-8.
+unsafe fn read_str<'a>(s: *const c_char) -> SeavanResult<&'a str> {
+    if s.is_null() {
+        return Err(SeavanError::FailedStrConversion);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| SeavanError::FailedStrConversion)
+}
+
+// Converts `result` into a string owned by the caller, or `NULL` after
+// recording the error.
+fn into_c_string(result: SeavanResult<String>) -> *mut c_char {
+    match result.and_then(|s| CString::new(s).map_err(|_| SeavanError::FailedStrConversion)) {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+// Runs `f` on the `Seavan` for `path`, converting the result into a string
+// owned by the caller.
+unsafe fn with_seavan<F>(path: *const c_char, f: F) -> *mut c_char
+where
+    F: FnOnce(Seavan) -> SeavanResult<String>,
+{
+    guard(ptr::null_mut(), || {
+        into_c_string(read_str(path).and_then(|path| f(Seavan::new(path)?)))
+    })
+}
+
+/// Wraps the file at `path` in a container image using Docker, returning the
+/// image's repository name and tag, or `NULL` on failure.
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn seavan_create_image(path: *const c_char) -> *mut c_char {
+    with_seavan(path, |wrap| wrap.create_image())
+}
+
+/// Returns the repository name and tag the file at `path` would be wrapped
+/// under, without building an image, or `NULL` on failure.
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn seavan_repository_name_and_tag(path: *const c_char) -> *mut c_char {
+    with_seavan(path, |wrap| wrap.repository_name_and_tag())
+}
+
+/// Extracts the file wrapped in the image `reference` into the directory
+/// `destination`, pulling the image if it isn't available locally. Returns
+/// the path of the extracted file, or `NULL` on failure.
+///
+/// # Safety
+///
+/// `reference` and `destination` must each be `NULL` or a valid
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn seavan_extract(
+    reference: *const c_char,
+    destination: *const c_char,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        into_c_string((|| {
+            let path =
+                Seavan::extract_from_reference(read_str(reference)?, read_str(destination)?)?;
+            path.into_os_string()
+                .into_string()
+                .map_err(|_| SeavanError::FailedStrConversion)
+        })())
+    })
+}
+
+/// Checks whether the file or directory at `path` matches the image
+/// `reference`, as `Seavan::verify` does. Returns 1 if it matches, 0 if it
+/// doesn't, or -1 on failure.
+///
+/// # Safety
+///
+/// `reference` and `path` must each be `NULL` or a valid NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn seavan_verify(reference: *const c_char, path: *const c_char) -> c_int {
+    guard(-1, || {
+        match read_str(reference).and_then(|reference| Seavan::verify(reference, read_str(path)?)) {
+            Ok(report) => c_int::from(report.verified),
+            Err(e) => {
+                set_last_error(&e);
+                -1
+            }
+        }
+    })
+}
+
+/// Returns the message of the last error on the calling thread, or `NULL` if
+/// there hasn't been one. The message is owned by seavan and remains valid
+/// until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn seavan_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|last| match &*last.borrow() {
+            Some(message) => message.as_ptr(),
+            None => ptr::null(),
+        })
+    })
+}
+
+/// Releases a string returned by seavan.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a string returned by seavan which hasn't already
+/// been released.
+#[no_mangle]
+pub unsafe extern "C" fn seavan_string_free(s: *mut c_char) {
+    guard((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
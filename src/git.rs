@@ -0,0 +1,62 @@
+//! Detection of git metadata for wrapped files.
+
+use crate::labels;
+use log::debug;
+use std::path::Path;
+use std::process::Command;
+
+// The labels which may describe the enclosing git repository.
+pub(crate) const LABELS: [&str; 4] = [
+    labels::OCI_REVISION,
+    labels::OCI_SOURCE,
+    labels::GIT_BRANCH,
+    labels::GIT_DIRTY,
+];
+
+// Runs a git command in `directory`, returning its trimmed stdout on success.
+fn git(directory: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => None,
+    }
+}
+
+// Removes any credentials embedded in a remote URL.
+fn strip_credentials(url: &str) -> String {
+    match (url.find("://"), url.rfind('@')) {
+        (Some(scheme), Some(at)) if at > scheme => {
+            format!("{}{}", &url[..scheme + 3], &url[at + 1..])
+        }
+        _ => url.to_string(),
+    }
+}
+
+// Returns labels describing the git repository enclosing `directory`, or no
+// labels if it isn't in a git repository.
+pub(crate) fn labels(directory: &Path) -> Vec<(String, String)> {
+    let revision = match git(directory, &["rev-parse", "HEAD"]) {
+        Some(revision) => revision,
+        None => {
+            debug!("{} is not in a git repository", directory.display());
+            return Vec::new();
+        }
+    };
+
+    let mut labels = vec![(labels::OCI_REVISION.to_string(), revision)];
+    if let Some(url) = git(directory, &["remote", "get-url", "origin"]) {
+        labels.push((labels::OCI_SOURCE.into(), strip_credentials(&url)));
+    }
+    if let Some(branch) = git(directory, &["rev-parse", "--abbrev-ref", "HEAD"]) {
+        labels.push((labels::GIT_BRANCH.into(), branch));
+    }
+    if let Some(status) = git(directory, &["status", "--porcelain"]) {
+        labels.push((labels::GIT_DIRTY.into(), (!status.is_empty()).to_string()));
+    }
+    labels
+}
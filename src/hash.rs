@@ -0,0 +1,357 @@
+//! Content hashing.
+//!
+//! By default the content hash is the SHA-256 of the whole file; SHA-512, or
+//! BLAKE3 with the `blake3` feature, can be selected with
+//! `Seavan::with_hash_algorithm`. Huge files can instead use
+//! a tree hash, which can be computed in parallel: the file is split into
+//! segments of a fixed size (the last may be shorter, and an empty file is a
+//! single empty segment), each segment is hashed, and the content hash is the
+//! hash of the concatenated raw segment digests.
+
+use crate::error::{SeavanError, SeavanResult};
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Suffix added to the algorithm's name for tree hashes.
+const TREE_SUFFIX: &str = "-tree";
+
+// Bytes hashed between reports of hashing progress.
+const PROGRESS_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// The algorithms content can be hashed with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// SHA-256. This is the default.
+    #[default]
+    Sha256,
+
+    /// SHA-512, which is faster than SHA-256 on most 64-bit CPUs without
+    /// SHA extensions.
+    Sha512,
+
+    /// BLAKE3, which is several times faster than either SHA-2 algorithm.
+    /// Only available with the `blake3` feature; images hashed with it can
+    /// only be extracted and verified by builds with the feature too.
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl HashAlgorithm {
+    // All the algorithms, for parsing.
+    const ALL: &'static [HashAlgorithm] = &[
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512,
+        #[cfg(feature = "blake3")]
+        HashAlgorithm::Blake3,
+    ];
+
+    /// Returns the algorithm's name, e.g. `sha256`, as used in content
+    /// digests.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    // Returns the prefix of the hash in repository names, which names the
+    // algorithm unless it's the default, e.g. `sha512-`.
+    pub(crate) fn name_prefix(&self) -> String {
+        match self {
+            HashAlgorithm::Sha256 => String::new(),
+            algorithm => format!("{}-", algorithm.name()),
+        }
+    }
+
+    // Parses an algorithm's name.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+// A hasher for any algorithm.
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => {
+                let _ = hasher.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How content is hashed: the algorithm, and the segment size of tree
+/// hashes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Scheme {
+    pub(crate) algorithm: HashAlgorithm,
+    pub(crate) segment_size: Option<u64>,
+}
+
+impl Scheme {
+    // Returns the scheme's name, used as the content digest prefix.
+    fn name(&self) -> String {
+        match self.segment_size {
+            Some(_) => format!("{}{}", self.algorithm.name(), TREE_SUFFIX),
+            None => self.algorithm.name().into(),
+        }
+    }
+}
+
+/// Describes the hash scheme, for the `seavan.hash-scheme` label. Returns
+/// `None` for the default scheme.
+pub(crate) fn scheme(scheme: Scheme) -> Option<String> {
+    match scheme.segment_size {
+        Some(size) => Some(format!("{};segment-size={}", scheme.name(), size)),
+        None if scheme == Scheme::default() => None,
+        None => Some(scheme.name()),
+    }
+}
+
+/// Parses a `seavan.hash-scheme` label, as written by `scheme`. Returns
+/// `None` for unknown schemes.
+pub(crate) fn parse_scheme(scheme: &str) -> Option<Scheme> {
+    let (name, parameters) = match scheme.split_once(';') {
+        Some((name, parameters)) => (name, Some(parameters)),
+        None => (scheme, None),
+    };
+    match (name.strip_suffix(TREE_SUFFIX), parameters) {
+        (Some(algorithm), Some(parameters)) => {
+            let size = parameters.strip_prefix("segment-size=")?.parse().ok()?;
+            validate_segment_size(size).ok()?;
+            Some(Scheme {
+                algorithm: HashAlgorithm::from_name(algorithm)?,
+                segment_size: Some(size),
+            })
+        }
+        (None, None) => Some(Scheme {
+            algorithm: HashAlgorithm::from_name(name)?,
+            segment_size: None,
+        }),
+        _ => None,
+    }
+}
+
+/// Formats a content hash as a digest, prefixed with its scheme.
+pub(crate) fn digest(scheme: Scheme, hash: &str) -> String {
+    format!("{}:{}", scheme.name(), hash)
+}
+
+/// Checks that a tree hash segment size is usable.
+pub(crate) fn validate_segment_size(segment_size: u64) -> SeavanResult<()> {
+    match segment_size {
+        0 => Err(SeavanError::InvalidSegmentSize(segment_size)),
+        _ => Ok(()),
+    }
+}
+
+/// Incrementally computes a content hash using any scheme.
+pub(crate) struct ContentHasher {
+    algorithm: HashAlgorithm,
+    segment_size: Option<u64>,
+    segment: Hasher,
+    segment_len: u64,
+    segments: Hasher,
+    segment_count: u64,
+}
+
+impl ContentHasher {
+    /// Creates a hasher for the given scheme.
+    pub(crate) fn new(scheme: Scheme) -> Self {
+        Self {
+            algorithm: scheme.algorithm,
+            segment_size: scheme.segment_size,
+            segment: Hasher::new(scheme.algorithm),
+            segment_len: 0,
+            segments: Hasher::new(scheme.algorithm),
+            segment_count: 0,
+        }
+    }
+
+    /// Adds data to the hash.
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        let segment_size = match self.segment_size {
+            Some(segment_size) => segment_size,
+            None => return self.segment.update(data),
+        };
+        while !data.is_empty() {
+            let space = (segment_size - self.segment_len).min(data.len() as u64) as usize;
+            self.segment.update(&data[..space]);
+            self.segment_len += space as u64;
+            data = &data[space..];
+            if self.segment_len == segment_size {
+                self.finish_segment();
+            }
+        }
+    }
+
+    // Adds the current segment's digest to the tree.
+    fn finish_segment(&mut self) {
+        let segment = std::mem::replace(&mut self.segment, Hasher::new(self.algorithm));
+        self.segments.update(&segment.finalize());
+        self.segment_len = 0;
+        self.segment_count += 1;
+    }
+
+    /// Returns the hex-encoded content hash.
+    pub(crate) fn finalize(mut self) -> String {
+        if self.segment_size.is_none() {
+            return hex(&self.segment.finalize());
+        }
+        if self.segment_len > 0 || self.segment_count == 0 {
+            self.finish_segment();
+        }
+        hex(&self.segments.finalize())
+    }
+}
+
+impl Write for ContentHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Hex-encodes a raw digest.
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Hashes content held in memory.
+pub(crate) fn hash_bytes(content: &[u8], scheme: Scheme) -> String {
+    let mut hasher = ContentHasher::new(scheme);
+    hasher.update(content);
+    hasher.finalize()
+}
+
+/// Hashes everything read from `reader`, passing the number of bytes hashed
+/// so far to `on_progress` every `PROGRESS_INTERVAL` bytes, and once it's
+/// done.
+pub(crate) fn hash_reader_with_progress<R: Read>(
+    mut reader: R,
+    scheme: Scheme,
+    on_progress: &mut dyn FnMut(u64),
+) -> SeavanResult<String> {
+    let mut hasher = ContentHasher::new(scheme);
+    let mut buffer = vec![0; 1 << 16];
+    let (mut hashed, mut reported) = (0, 0);
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        hasher.update(&buffer[..read]);
+        hashed += read as u64;
+        if hashed - reported >= PROGRESS_INTERVAL {
+            on_progress(hashed);
+            reported = hashed;
+        }
+    }
+    on_progress(hashed);
+    Ok(hasher.finalize())
+}
+
+/// Hashes a file. Tree hashes are computed with one thread per available
+/// CPU, each reading its own segments.
+pub(crate) fn hash_file(path: &Path, scheme: Scheme) -> SeavanResult<String> {
+    let segment_size = match scheme.segment_size {
+        Some(segment_size) => segment_size,
+        None => {
+            let mut hasher = ContentHasher::new(scheme);
+            let _ = io::copy(&mut File::open(path)?, &mut hasher)?;
+            return Ok(hasher.finalize());
+        }
+    };
+
+    let len = std::fs::metadata(path)?.len();
+    let count = len.div_ceil(segment_size).max(1) as usize;
+    let threads = std::thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1)
+        .min(count);
+
+    let next = AtomicUsize::new(0);
+    let digests = Mutex::new(vec![Vec::new(); count]);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| -> SeavanResult<()> {
+                    let mut file = File::open(path)?;
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= count {
+                            return Ok(());
+                        }
+                        let _ = file.seek(SeekFrom::Start(index as u64 * segment_size))?;
+                        let mut segment = Hasher::new(scheme.algorithm);
+                        let _ = io::copy(&mut (&mut file).take(segment_size), &mut segment)?;
+                        digests.lock().unwrap_or_else(|e| e.into_inner())[index] =
+                            segment.finalize();
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("Hashing thread panicked").into()))
+        })
+    })?;
+
+    let mut tree = Hasher::new(scheme.algorithm);
+    for digest in digests.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        tree.update(&digest);
+    }
+    Ok(hex(&tree.finalize()))
+}
@@ -0,0 +1,291 @@
+//! Labels recorded in the metadata of generated images.
+
+use crate::{
+    client::Client,
+    docker::Docker,
+    error::{SeavanError, SeavanResult},
+    utils::{current_user, hostname},
+};
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+
+/// Label holding the exact original filename of the wrapped file. The raw
+/// bytes of the filename are percent-encoded, so characters lost to
+/// sanitization and non-UTF-8 filenames can be recovered.
+pub const ORIGINAL_FILENAME: &str = "seavan.original-filename";
+
+/// Label holding the path of the wrapped file inside the image, when it was
+/// wrapped with `Seavan::with_destination`. Images without the label hold
+/// the file at the root, under its own name.
+pub const DESTINATION: &str = "seavan.destination";
+
+/// Label holding the version of the layout of the image, so that tools can
+/// refuse to read images laid out by a later version of seavan rather than
+/// mis-extracting them. Images without the label have format version 1.
+pub const FORMAT_VERSION: &str = "seavan.format-version";
+
+/// The format version of the images built by this version of seavan. This
+/// is incremented whenever the layout of images changes incompatibly.
+///
+/// - Version 1 images hold the wrapped file whole and uncompressed.
+/// - Version 2 images may instead hold it compressed, as described by the
+///   `COMPRESSION` label, or split into chunks, as described by the
+///   `CHUNKING` label.
+///
+/// Wrapped files are stamped with the lowest version their layout needs, so
+/// images which don't use the newer features can still be read by earlier
+/// versions of seavan.
+pub const CURRENT_FORMAT_VERSION: u32 = 2;
+
+// The format version of images holding the wrapped file whole and
+// uncompressed, which every version of seavan can read. Images without a
+// `FORMAT_VERSION` label have this version.
+pub(crate) const PLAIN_FORMAT_VERSION: u32 = 1;
+
+/// Label holding the full content digest of the wrapped file, in the form
+/// `sha256:<hex>` (or `sha512:<hex>`, or `sha256-tree:<hex>` for tree
+/// hashes). This is recorded
+/// even when the repository name only holds a truncated hash.
+pub const CONTENT_DIGEST: &str = "seavan.content-digest";
+
+/// Label describing how the content hash was computed, when it isn't a plain
+/// SHA-256 of the file; e.g. `sha512` or `sha256-tree;segment-size=67108864`.
+pub const HASH_SCHEME: &str = "seavan.hash-scheme";
+
+/// Label describing how the wrapped file was split into chunks, when it was
+/// wrapped with `Seavan::with_chunking`; e.g.
+/// `fastcdc;average-size=16777216` or `fixed;size=524288000`.
+pub const CHUNKING: &str = "seavan.chunking";
+
+/// Label holding the SHA-256 hash of each chunk of a chunked image, in
+/// order, as a JSON `chunk::ChunkIndex`. The index is also written into the
+/// image at `chunk::CHUNK_INDEX`.
+pub const CHUNKS: &str = "seavan.chunks";
+
+/// Label describing how the wrapped file was compressed, when it was wrapped
+/// with `Seavan::with_compression`; e.g. `zstd;level=19`.
+pub const COMPRESSION: &str = "seavan.compression";
+
+/// Label holding the exact size in bytes of the wrapped file, or the total
+/// size of the files in a bundle, so disk space for extraction can be
+/// allocated before pulling the image.
+pub const PAYLOAD_SIZE: &str = "seavan.payload-size";
+
+/// Label holding an in-toto statement describing the wrapped file, when it
+/// was wrapped with `Seavan::with_attestation`, as a JSON
+/// `attestation::Statement`.
+pub const ATTESTATION: &str = "seavan.attestation";
+
+/// Label holding the inputs of an aggregate image built by
+/// `compose::Composition`, as a JSON list of `compose::ComposedInput`s.
+pub const COMPOSED_INPUTS: &str = "seavan.composed-inputs";
+
+/// Label holding the files of an image built by `bundle::SeavanBundle`, as a
+/// JSON list of `bundle::BundleEntry`s.
+pub const BUNDLE: &str = "seavan.bundle";
+
+/// Label holding the hostname of the machine which built the image.
+pub const BUILDER_HOSTNAME: &str = "seavan.builder.hostname";
+
+/// Label holding the operating system of the machine which built the image.
+pub const BUILDER_OS: &str = "seavan.builder.os";
+
+/// Label holding the name of the user which built the image.
+pub const BUILDER_USER: &str = "seavan.builder.user";
+
+/// Label holding the version of seavan which built the image.
+pub const SEAVAN_VERSION: &str = "seavan.version";
+
+/// Standard OCI label holding the source control revision the image was
+/// built from.
+pub const OCI_REVISION: &str = "org.opencontainers.image.revision";
+
+/// Standard OCI label holding the URL of the source the image was built from.
+pub const OCI_SOURCE: &str = "org.opencontainers.image.source";
+
+/// Label holding the git branch the image was built from.
+pub const GIT_BRANCH: &str = "seavan.git.branch";
+
+/// Label holding whether the git working tree had uncommitted changes when
+/// the image was built; either `true` or `false`.
+pub const GIT_DIRTY: &str = "seavan.git.dirty";
+
+/// Label holding the CI provider which built the image: one of
+/// `github-actions`, `gitlab-ci` or `azure-pipelines`.
+pub const CI_PROVIDER: &str = "seavan.ci.provider";
+
+/// Label holding the URL of the CI run which built the image.
+pub const CI_RUN_URL: &str = "seavan.ci.run-url";
+
+/// Label holding the identifier of the CI pipeline run which built the image.
+pub const CI_PIPELINE_ID: &str = "seavan.ci.pipeline-id";
+
+// Returns labels describing the environment building the image. Values which
+// can't be determined are omitted.
+pub(crate) fn provenance() -> Vec<(String, String)> {
+    let mut labels = vec![
+        (BUILDER_OS.into(), std::env::consts::OS.into()),
+        (SEAVAN_VERSION.into(), env!("CARGO_PKG_VERSION").into()),
+    ];
+    if let Some(hostname) = hostname() {
+        labels.push((BUILDER_HOSTNAME.into(), hostname));
+    }
+    if let Some(user) = current_user() {
+        labels.push((BUILDER_USER.into(), user));
+    }
+    labels
+}
+
+// Returns the raw bytes of an OsStr.
+#[cfg(unix)]
+fn os_str_bytes(value: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    value.as_bytes().to_vec()
+}
+
+// Returns the raw bytes of an OsStr. Filenames on non-unix platforms are
+// encoded as UTF-8, replacing any unpaired surrogates.
+#[cfg(not(unix))]
+fn os_str_bytes(value: &OsStr) -> Vec<u8> {
+    value.to_string_lossy().into_owned().into_bytes()
+}
+
+// Builds an OsString from raw bytes.
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> SeavanResult<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(OsString::from_vec(bytes))
+}
+
+// Builds an OsString from raw bytes, which must be valid UTF-8.
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> SeavanResult<OsString> {
+    String::from_utf8(bytes)
+        .map(OsString::from)
+        .map_err(|_| SeavanError::FailedStrConversion)
+}
+
+/// Percent-encodes a filename for storage in the `ORIGINAL_FILENAME` label.
+/// Unreserved characters (`A-Z a-z 0-9 - . _ ~`) are stored as-is, and every
+/// other byte is stored as `%XX`.
+pub fn encode_filename(filename: &OsStr) -> String {
+    let mut encoded = String::new();
+    for b in os_str_bytes(filename) {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", b);
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes a filename stored in the `ORIGINAL_FILENAME` label.
+pub fn decode_filename(encoded: &str) -> SeavanResult<OsString> {
+    let invalid = || SeavanError::InvalidLabel(ORIGINAL_FILENAME.into(), encoded.into());
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'%' => {
+                let hex = [
+                    iter.next().ok_or_else(invalid)?,
+                    iter.next().ok_or_else(invalid)?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            }
+            _ => bytes.push(b),
+        }
+    }
+    os_string_from_bytes(bytes)
+}
+
+/// Reads the size of the file wrapped in a remote image from its
+/// `PAYLOAD_SIZE` label. Only the image configuration is fetched, so the
+/// image isn't pulled.
+///
+/// Returns `None` if the image wasn't labelled with its size. Fails with
+/// `SeavanError::DockerInspectFailure` if the image can't be inspected.
+///
+/// # Arguments
+///
+/// * `reference`: The image reference to inspect.
+pub fn payload_size(reference: &str) -> SeavanResult<Option<u64>> {
+    Client::new()?.payload_size(reference)
+}
+
+// Reads the `PAYLOAD_SIZE` label of a remote image, as `payload_size` does,
+// using the given docker settings.
+pub(crate) fn remote_payload_size(docker: &Docker, reference: &str) -> SeavanResult<Option<u64>> {
+    match docker.remote_label(reference, PAYLOAD_SIZE)? {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| SeavanError::InvalidLabel(PAYLOAD_SIZE.into(), value)),
+        None => Ok(None),
+    }
+}
+
+/// Checks that an image with the given `FORMAT_VERSION` label can be read by
+/// this version of seavan, returning its format version. Fails with
+/// `SeavanError::UnsupportedFormatVersion` if the image was built by a later
+/// version of seavan with an incompatible layout.
+///
+/// # Arguments
+///
+/// * `reference`: The reference of the image, for error reporting.
+/// * `value`: The value of the image's `FORMAT_VERSION` label, if it has one.
+///
+/// # Examples
+/// ```
+/// use seavan::labels::check_format_version;
+/// assert_eq!(check_format_version("seavanpkg/abcd:latest", None).ok(), Some(1));
+/// assert!(check_format_version("seavanpkg/abcd:latest", Some("999")).is_err());
+/// ```
+pub fn check_format_version(reference: &str, value: Option<&str>) -> SeavanResult<u32> {
+    let version = match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| SeavanError::InvalidLabel(FORMAT_VERSION.into(), value.into()))?,
+        None => PLAIN_FORMAT_VERSION,
+    };
+    match version <= CURRENT_FORMAT_VERSION {
+        true => Ok(version),
+        false => Err(SeavanError::UnsupportedFormatVersion(
+            reference.into(),
+            version,
+        )),
+    }
+}
+
+// Namespace of the labels reserved for seavan's own use.
+const RESERVED_PREFIX: &str = "seavan.";
+
+// Checks that a label given by the user, rather than set by seavan itself,
+// can be written to a Dockerfile: the key must be non-empty, outside the
+// reserved namespace and free of whitespace, `=` and control characters, and
+// the value free of control characters such as newlines.
+pub(crate) fn validate_custom(key: &str, value: &str) -> SeavanResult<()> {
+    let invalid_key = key.is_empty()
+        || key.starts_with(RESERVED_PREFIX)
+        || key
+            .chars()
+            .any(|c| c == '=' || c.is_whitespace() || c.is_control());
+    if invalid_key {
+        return Err(SeavanError::InvalidLabelKey(key.into()));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(SeavanError::InvalidLabelValue(key.into()));
+    }
+    Ok(())
+}
+
+// Quotes a string for use in a Dockerfile LABEL instruction.
+pub(crate) fn dockerfile_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,150 @@
+//! Lockfiles pinning the wrapped artifacts a project depends on.
+//!
+//! A lockfile maps logical artifact names to the image references and
+//! content digests they were wrapped as, so a project can check that its
+//! artifacts haven't changed and reproduce their images exactly. Lockfiles
+//! are conventionally named `seavan.lock` and kept under version control:
+//!
+//! ```toml
+//! [[artifact]]
+//! name = "dataset"
+//! reference = "seavanpkg/<sha256>--data-csv:latest"
+//! digest = "sha256:<sha256>"
+//! ```
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    hash, Seavan,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// The conventional name of a lockfile.
+pub const LOCKFILE_NAME: &str = "seavan.lock";
+
+/// An artifact pinned in a `Lockfile`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedArtifact {
+    /// The logical name of the artifact.
+    pub name: String,
+    /// The repository name and tag of the artifact's image.
+    pub reference: String,
+    /// The digest of the artifact's content, as in the
+    /// `seavan.content-digest` label.
+    pub digest: String,
+}
+
+/// A set of artifacts pinned to image references and content digests.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// The pinned artifacts, sorted by name.
+    #[serde(default, rename = "artifact")]
+    pub artifacts: Vec<LockedArtifact>,
+}
+
+impl Lockfile {
+    /// Reads a lockfile saved with `save`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the lockfile.
+    pub fn load<P: AsRef<Path>>(path: P) -> SeavanResult<Self> {
+        let path = path.as_ref();
+        toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| SeavanError::InvalidLockfile(path.into(), e))
+    }
+
+    /// Saves the lockfile as TOML.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the lockfile.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> SeavanResult<()> {
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the artifact pinned under `name`, if there is one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The logical name of the artifact.
+    pub fn get(&self, name: &str) -> Option<&LockedArtifact> {
+        self.artifacts.iter().find(|artifact| artifact.name == name)
+    }
+
+    /// Pins the artifact `name` to the current content and image reference
+    /// of `wrap`, replacing any existing pin.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The logical name of the artifact.
+    /// * `wrap`: The wrapped artifact.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{lock::Lockfile, Seavan};
+    /// let mut lockfile = Lockfile::default();
+    /// let wrap = Seavan::from_content("data.csv", b"a,b\n1,2\n")?;
+    /// let _ = lockfile.update("dataset", &wrap)?;
+    /// lockfile.verify("dataset", &wrap)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn update(&mut self, name: &str, wrap: &Seavan) -> SeavanResult<&LockedArtifact> {
+        let artifact = Self::lock(name, wrap)?;
+        let index = match self
+            .artifacts
+            .binary_search_by(|existing| existing.name.as_str().cmp(name))
+        {
+            Ok(index) => {
+                self.artifacts[index] = artifact;
+                index
+            }
+            Err(index) => {
+                self.artifacts.insert(index, artifact);
+                index
+            }
+        };
+        Ok(&self.artifacts[index])
+    }
+
+    /// Checks that `wrap` still matches the artifact pinned under `name`.
+    /// Fails with `SeavanError::NotLocked` if the artifact isn't pinned, or
+    /// `SeavanError::LockMismatch` if its content or image reference has
+    /// changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The logical name of the artifact.
+    /// * `wrap`: The wrapped artifact.
+    pub fn verify(&self, name: &str, wrap: &Seavan) -> SeavanResult<()> {
+        let pinned = self
+            .get(name)
+            .ok_or_else(|| SeavanError::NotLocked(name.into()))?;
+        let current = Self::lock(name, wrap)?;
+        let mismatch = |expected: &str, actual: &str| {
+            SeavanError::LockMismatch(name.into(), expected.into(), actual.into())
+        };
+
+        if current.digest != pinned.digest {
+            return Err(mismatch(&pinned.digest, &current.digest));
+        }
+        if current.reference != pinned.reference {
+            return Err(mismatch(&pinned.reference, &current.reference));
+        }
+        Ok(())
+    }
+
+    // Creates the pin for `wrap` under `name`.
+    fn lock(name: &str, wrap: &Seavan) -> SeavanResult<LockedArtifact> {
+        let hash = wrap.hash()?;
+        Ok(LockedArtifact {
+            name: name.into(),
+            reference: wrap.resolve_repository_name_and_tag_for(&hash)?,
+            digest: hash::digest(wrap.hash_scheme, &hash),
+        })
+    }
+}
@@ -0,0 +1,206 @@
+//! Strategies for naming generated repositories.
+
+use crate::error::{SeavanError, SeavanResult};
+use regex::Regex;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of hash characters used by `NamingStrategy::Nix`.
+const NIX_HASH_LENGTH: usize = 32;
+
+/// Maximum length of a repository name, including the registry.
+pub(crate) const MAX_REPOSITORY_LENGTH: usize = 255;
+
+/// Maximum length of a tag.
+const MAX_TAG_LENGTH: usize = 128;
+
+/// Number of hash characters used by `TagPolicy::ShortHash`.
+const SHORT_HASH_LENGTH: usize = 12;
+
+/// Strategy used to derive the repository name component from the file's
+/// content hash and its sanitised filename.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NamingStrategy {
+    /// The full content hash followed by the filename, e.g.
+    /// `<sha256>--readme-md`. This is the default.
+    #[default]
+    Classic,
+
+    /// A truncated content hash in front of the filename, with the whole
+    /// component limited to `length` characters (similar to Nix store paths),
+    /// e.g. `<32 hash characters>-readme-md`. The filename is truncated to fit.
+    Nix {
+        /// The maximum total length of the repository name component.
+        length: usize,
+    },
+
+    /// The full content hash alone, omitting the filename, e.g. `<sha256>`.
+    /// The `seavan.original-filename` label is also omitted so the filename
+    /// isn't visible in registry listings or image metadata. Note that the
+    /// file is still stored under its own name inside the image.
+    HashOnly,
+}
+
+/// Policy for handling truncated hashes which collide with an existing
+/// repository holding different content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashCollisionPolicy {
+    /// Fail with `SeavanError::HashCollision`. This is the default.
+    #[default]
+    Error,
+
+    /// Extend the truncated hash until the name no longer collides, failing
+    /// only if the full hash still collides.
+    Extend,
+}
+
+/// Policy for choosing the tag of images which aren't given one with
+/// `Seavan::with_tag`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagPolicy {
+    /// Tag every image `latest`. This is the default.
+    #[default]
+    Latest,
+
+    /// Tag images with the first 12 characters of the content hash, e.g.
+    /// `0123456789ab`, so that tags are never reused for different content.
+    ShortHash,
+
+    /// Tag images with the time the policy was set, in UTC, e.g.
+    /// `20230102-030405`. The tag changes every run, so image references
+    /// can't be reproduced or pinned in a lockfile.
+    Timestamp,
+}
+
+impl TagPolicy {
+    // Returns the tag for the given content hash, using `time` as the time
+    // the policy was set.
+    pub(crate) fn tag(&self, hash: &str, time: SystemTime) -> String {
+        match self {
+            TagPolicy::Latest => "latest".into(),
+            TagPolicy::ShortHash => hash[..SHORT_HASH_LENGTH.min(hash.len())].into(),
+            TagPolicy::Timestamp => {
+                let secs = time
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs())
+                    .unwrap_or_default();
+                crate::retention::rfc3339(secs)
+                    .replace(['-', ':', 'Z'], "")
+                    .replace('T', "-")
+            }
+        }
+    }
+
+    // Returns whether every image gets the same tag under this policy.
+    pub(crate) fn is_fixed(&self) -> bool {
+        matches!(self, TagPolicy::Latest)
+    }
+}
+
+impl FromStr for TagPolicy {
+    type Err = SeavanError;
+
+    fn from_str(policy: &str) -> SeavanResult<Self> {
+        match policy {
+            "latest" => Ok(TagPolicy::Latest),
+            "short-hash" => Ok(TagPolicy::ShortHash),
+            "timestamp" => Ok(TagPolicy::Timestamp),
+            _ => Err(SeavanError::InvalidTagPolicy(policy.into())),
+        }
+    }
+}
+
+impl NamingStrategy {
+    // Returns whether the filename should be kept out of image names and
+    // metadata.
+    pub(crate) fn hides_filename(&self) -> bool {
+        matches!(self, NamingStrategy::HashOnly)
+    }
+
+    // Derives the repository name component from the content hash and the
+    // sanitised filename.
+    pub(crate) fn repository_component(&self, hash: &str, safe_filename: &str) -> String {
+        match *self {
+            NamingStrategy::Classic => format!("{}--{}", hash, safe_filename),
+            NamingStrategy::HashOnly => hash.to_string(),
+            NamingStrategy::Nix { length } => {
+                let short_hash = &hash[..NIX_HASH_LENGTH.min(hash.len()).min(length)];
+                let remaining = length.saturating_sub(short_hash.len() + 1);
+
+                // Truncate the name to fit, making sure the component doesn't
+                // end in a separator which Docker would reject.
+                let name: String = safe_filename.chars().take(remaining).collect();
+                let name = name.trim_end_matches(['-', '_']);
+                match name.is_empty() {
+                    true => short_hash.to_string(),
+                    false => format!("{}-{}", short_hash, name),
+                }
+            }
+        }
+    }
+}
+
+// Checks that `package_root` is a valid repository path prefix, e.g.
+// `team/seavanpkg`.
+pub(crate) fn validate_package_root(package_root: &str) -> SeavanResult<()> {
+    let component = Regex::new("^[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*$")?;
+    match package_root.split('/').all(|part| component.is_match(part)) {
+        true => Ok(()),
+        false => Err(SeavanError::InvalidPackageRoot(package_root.into())),
+    }
+}
+
+// Returns the repository part of a `repository:tag` reference.
+pub(crate) fn repository_of(reference: &str) -> &str {
+    match reference.rfind(':') {
+        Some(index) if !reference[index..].contains('/') => &reference[..index],
+        _ => reference,
+    }
+}
+
+/// Checks that `reference` is a valid `repository:tag` image reference, such
+/// as one returned by `Seavan::repository_name_and_tag`. Only the syntax is
+/// checked, so this never contacts a registry.
+///
+/// # Arguments
+///
+/// * `reference`: The image reference to check.
+///
+/// # Examples
+/// ```
+/// use seavan::naming::validate_reference;
+/// assert!(validate_reference("registry.example.com:5000/seavanpkg/abcd--readme-md:latest").is_ok());
+/// assert!(validate_reference("seavanpkg/ABCD:latest").is_err());
+/// ```
+pub fn validate_reference(reference: &str) -> SeavanResult<()> {
+    let invalid = |reason: &str| SeavanError::InvalidReference(reference.into(), reason.into());
+
+    let repository = repository_of(reference);
+    if repository.len() > MAX_REPOSITORY_LENGTH {
+        return Err(invalid("repository name is too long"));
+    }
+
+    // The first component is a registry host if it looks like one.
+    let mut components: Vec<&str> = repository.split('/').collect();
+    if components.len() > 1 && (components[0].contains(['.', ':']) || components[0] == "localhost")
+    {
+        let _ = components.remove(0);
+    }
+    let component_re = Regex::new(r"^[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*$")?;
+    if !components.iter().all(|c| component_re.is_match(c)) {
+        return Err(invalid("repository contains unsupported characters"));
+    }
+
+    if let Some(tag) = reference[repository.len()..].strip_prefix(':') {
+        let tag_re = Regex::new(r"^[A-Za-z0-9_][A-Za-z0-9_.-]*$")?;
+        if !tag_re.is_match(tag) {
+            return Err(invalid("tag contains unsupported characters"));
+        }
+        if tag.len() > MAX_TAG_LENGTH {
+            return Err(invalid("tag is too long"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Async variants of seavan operations, for calling seavan from tokio.
+//!
+//! The `_async` methods of `Seavan` and `client::Client` run docker with
+//! `tokio::process` and hash files with tokio's async file I/O, so many
+//! operations can be awaited at once without tying up a thread each. Short
+//! steps, such as inspecting images or writing the audit log, run on tokio's
+//! blocking thread pool, as do streamed builds, builds using a custom
+//! `runner::CommandRunner`, and hashes computed in parallel segments.
+//!
+//! The futures must be awaited within a tokio runtime with its I/O and time
+//! drivers enabled, e.g. one started with `#[tokio::main]`.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    hash::{self, ContentHasher},
+};
+use std::io;
+use std::panic;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+// Size of the buffer files are read into while hashing.
+const HASH_BUFFER_SIZE: usize = 1 << 16;
+
+// Runs `operation` on tokio's blocking thread pool, returning its result.
+pub(crate) async fn blocking<T, F>(operation: F) -> SeavanResult<T>
+where
+    F: FnOnce() -> SeavanResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(operation).await {
+        Ok(result) => result,
+        Err(e) => match e.try_into_panic() {
+            // Panics in the operation are raised in the awaiting task.
+            Ok(panic) => panic::resume_unwind(panic),
+            Err(e) => Err(SeavanError::IoError(io::Error::new(
+                io::ErrorKind::Interrupted,
+                e,
+            ))),
+        },
+    }
+}
+
+// Hashes the file at `path` with `scheme`, as `hash::hash_file` does, reading
+// it with async file I/O.
+pub(crate) async fn hash_file(path: &Path, scheme: hash::Scheme) -> SeavanResult<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = ContentHasher::new(scheme);
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        match file.read(&mut buffer).await? {
+            0 => return Ok(hasher.finalize()),
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+}
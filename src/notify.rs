@@ -0,0 +1,197 @@
+//! Notifications sent after images are pushed, so downstream systems such as
+//! artifact catalogs and deployment triggers learn about new images.
+
+use crate::error::{SeavanError, SeavanResult};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Default number of times a webhook delivery is retried.
+const DEFAULT_RETRIES: u32 = 3;
+
+// Delay before the first retry; each later retry waits twice as long.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+// Longest time a single webhook delivery may take.
+const DELIVERY_TIMEOUT_SECS: &str = "30";
+
+// Returns `@path`, which curl reads from the file at `path`.
+fn at_path(path: &Path) -> OsString {
+    let mut arg = OsString::from("@");
+    arg.push(path);
+    arg
+}
+
+/// An image which was pushed to a registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushEvent {
+    /// The pushed repository name and tag.
+    pub reference: String,
+    /// The digest of the wrapped file's content, as in the
+    /// `seavan.content-digest` label.
+    pub digest: String,
+    /// The labels of the image.
+    pub labels: BTreeMap<String, String>,
+}
+
+/// A destination for notifications about pushed images.
+///
+/// This is implemented for closures taking a `&PushEvent`, so a callback can
+/// be used directly.
+pub trait PushNotifier: Send + Sync {
+    /// Notifies the destination that an image was pushed.
+    fn notify(&self, event: &PushEvent) -> SeavanResult<()>;
+}
+
+impl<F> PushNotifier for F
+where
+    F: Fn(&PushEvent) -> SeavanResult<()> + Send + Sync,
+{
+    fn notify(&self, event: &PushEvent) -> SeavanResult<()> {
+        self(event)
+    }
+}
+
+/// A `PushNotifier` which POSTs each event as JSON to a webhook URL, using
+/// the `curl` CLI. Failed deliveries are retried with exponential backoff.
+///
+/// # Examples
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{notify::Webhook, Seavan};
+/// use std::sync::Arc;
+/// let webhook = Webhook::new("https://catalog.example.com/hooks/seavan")
+///     .with_header("Authorization: Bearer token")
+///     .with_retries(5);
+/// let wrap = Seavan::new("README.md")?.with_push_notifier(Arc::new(webhook))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Webhook {
+    url: String,
+    headers: Vec<String>,
+    retries: u32,
+}
+
+impl Webhook {
+    /// Creates a new `Webhook`, which retries failed deliveries 3 times.
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: The URL to POST events to.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.into(),
+            headers: Vec::new(),
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Adds a header to send with each event, e.g. for authentication.
+    ///
+    /// # Arguments
+    ///
+    /// * `header`: The header, in `Name: value` form.
+    pub fn with_header(mut self, header: &str) -> Self {
+        self.headers.push(header.into());
+        self
+    }
+
+    /// Sets how many times a failed delivery is retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `retries`: The number of retries.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    // Makes a single attempt to deliver `body`.
+    fn deliver(&self, body: &[u8]) -> SeavanResult<()> {
+        // Pass the body and headers in files so they don't appear in the
+        // process list.
+        let mut body_file = tempfile::NamedTempFile::new()?;
+        body_file.write_all(body)?;
+        body_file.flush()?;
+        let mut header_file = tempfile::NamedTempFile::new()?;
+        writeln!(header_file, "Content-Type: application/json")?;
+        for header in &self.headers {
+            writeln!(header_file, "{}", header)?;
+        }
+        header_file.flush()?;
+
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--max-time"])
+            .arg(DELIVERY_TIMEOUT_SECS)
+            .arg("--header")
+            .arg(at_path(header_file.path()))
+            .arg("--data-binary")
+            .arg(at_path(body_file.path()))
+            .arg("--url")
+            .arg(&self.url)
+            .output()?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(SeavanError::WebhookFailure(
+                String::from_utf8_lossy(&output.stderr).trim().into(),
+            )),
+        }
+    }
+}
+
+// Headers often hold credentials, so only their names are shown.
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: Vec<String> = self
+            .headers
+            .iter()
+            .map(|header| match header.split_once(':') {
+                Some((name, _)) => format!("{}: <redacted>", name),
+                None => "<redacted>".into(),
+            })
+            .collect();
+        f.debug_struct("Webhook")
+            .field("url", &self.url)
+            .field("headers", &headers)
+            .field("retries", &self.retries)
+            .finish()
+    }
+}
+
+impl PushNotifier for Webhook {
+    fn notify(&self, event: &PushEvent) -> SeavanResult<()> {
+        let body = serde_json::to_vec(event)?;
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            match self.deliver(&body) {
+                Err(e) if attempt < self.retries => {
+                    debug!("Webhook delivery to {} failed, retrying: {}", self.url, e);
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// A shareable push notifier.
+#[derive(Clone)]
+pub(crate) struct Notifier(pub(crate) Arc<dyn PushNotifier>);
+
+impl fmt::Debug for Notifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Notifier")
+    }
+}
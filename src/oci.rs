@@ -0,0 +1,256 @@
+//! Native building of images as OCI image layouts, without a container
+//! runtime.
+//!
+//! Images built natively hold the wrapped file in a single uncompressed layer
+//! with the same labels as images built by Docker. The layer is written
+//! deterministically, so the same file always produces the same image
+//! digest. Load them with e.g. `skopeo copy oci:<layout>:<tag>` or
+//! `podman load`.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    Seavan,
+};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tar::{Builder, EntryType, Header, HeaderMode};
+
+const INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+// Annotation naming each image in an image layout's index.
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// Where natively built images are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OciOutput {
+    /// An OCI image layout directory. The directory is created if it doesn't
+    /// exist; images already in it are kept, except any image with the same
+    /// reference, which is replaced.
+    Layout(PathBuf),
+
+    /// An `oci-archive` tarball: a tar of an image layout holding only the
+    /// new image. Any existing file is overwritten.
+    Archive(PathBuf),
+}
+
+// A blob written to an image layout.
+struct Descriptor {
+    digest: String,
+    size: u64,
+}
+
+impl Descriptor {
+    fn to_json(&self, media_type: &str) -> Value {
+        json!({
+            "mediaType": media_type,
+            "digest": self.digest,
+            "size": self.size,
+        })
+    }
+}
+
+// Writer which hashes and counts everything written through it.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    count: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Builds the image for `wrap`, whose content hash is `hash`, as `reference`
+// and writes it to `output`.
+pub(crate) fn build(
+    wrap: &Seavan,
+    hash: &str,
+    reference: &str,
+    output: &OciOutput,
+) -> SeavanResult<()> {
+    if wrap.chunk_sizes()?.is_some() {
+        return Err(SeavanError::UnsupportedNativeOption("chunking".into()));
+    }
+    if wrap.compression.is_some() {
+        return Err(SeavanError::UnsupportedNativeOption("compression".into()));
+    }
+    match output {
+        OciOutput::Layout(directory) => write_layout(wrap, hash, reference, directory),
+        OciOutput::Archive(path) => {
+            let parent = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            let staging = tempfile::Builder::new()
+                .prefix(".seavan")
+                .tempdir_in(parent)?;
+            write_layout(wrap, hash, reference, staging.path())?;
+
+            let archive = tempfile::NamedTempFile::new_in(parent)?;
+            let mut builder = Builder::new(archive);
+            builder.mode(HeaderMode::Deterministic);
+            builder.append_dir_all(".", staging.path())?;
+            let archive = builder.into_inner()?;
+            let _ = archive.persist(path).map_err(|e| e.error)?;
+            Ok(())
+        }
+    }
+}
+
+// Writes the image into the image layout at `directory`.
+fn write_layout(wrap: &Seavan, hash: &str, reference: &str, directory: &Path) -> SeavanResult<()> {
+    let blobs = directory.join("blobs").join("sha256");
+    fs::create_dir_all(&blobs)?;
+    fs::write(
+        directory.join("oci-layout"),
+        serde_json::to_vec(&json!({ "imageLayoutVersion": "1.0.0" }))?,
+    )?;
+
+    let layer = write_layer(wrap, &blobs)?;
+    let labels: Map<String, Value> = wrap
+        .labels(hash)?
+        .into_iter()
+        .map(|(key, value)| (key, Value::String(value)))
+        .collect();
+    let mut config = json!({
+        "config": { "Labels": labels },
+        "rootfs": { "type": "layers", "diff_ids": [layer.digest] },
+        "history": [{ "created_by": format!(
+            "COPY {} {}",
+            wrap.filename_lossy()?,
+            wrap.destination.as_deref().unwrap_or("/")
+        ) }],
+    });
+    for (key, value) in platform(wrap.platform.as_deref()) {
+        config[key] = Value::String(value.into());
+    }
+    let config = write_blob(&blobs, &serde_json::to_vec(&config)?)?;
+    let manifest = json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "config": config.to_json(CONFIG_MEDIA_TYPE),
+        "layers": [layer.to_json(LAYER_MEDIA_TYPE)],
+    });
+    let manifest = write_blob(&blobs, &serde_json::to_vec(&manifest)?)?;
+
+    // Keep the other images in the layout, replacing any with the same
+    // reference.
+    let index_path = directory.join("index.json");
+    let mut manifests = match fs::read(&index_path) {
+        Ok(index) => {
+            let index: Value = serde_json::from_slice(&index)?;
+            index["manifests"].as_array().cloned().unwrap_or_default()
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    manifests.retain(|m| m["annotations"][REF_NAME_ANNOTATION] != reference);
+    let mut descriptor = manifest.to_json(MANIFEST_MEDIA_TYPE);
+    descriptor["annotations"] = json!({ REF_NAME_ANNOTATION: reference });
+    manifests.push(descriptor);
+    let index = json!({
+        "schemaVersion": 2,
+        "mediaType": INDEX_MEDIA_TYPE,
+        "manifests": manifests,
+    });
+    fs::write(index_path, serde_json::to_vec_pretty(&index)?)?;
+    Ok(())
+}
+
+// Writes the layer holding the wrapped file into `blobs`. The layer is
+// uncompressed, so its digest is also its diff ID.
+fn write_layer(wrap: &Seavan, blobs: &Path) -> SeavanResult<Descriptor> {
+    let file = wrap.open()?;
+    let metadata = file.metadata()?;
+    let staged = tempfile::NamedTempFile::new_in(blobs)?;
+    let mut builder = Builder::new(HashingWriter {
+        inner: staged,
+        hasher: Sha256::new(),
+        count: 0,
+    });
+    let path = match &wrap.destination {
+        Some(destination) => {
+            // Add the directories leading to the destination, as Docker
+            // would create them.
+            let path = destination.trim_start_matches('/');
+            for (end, _) in path.match_indices('/') {
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder.append_data(&mut header, &path[..=end], io::empty())?;
+            }
+            path.into()
+        }
+        None => wrap.filename_lossy()?,
+    };
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(&metadata, HeaderMode::Deterministic);
+    builder.append_data(&mut header, &*path, file.take(metadata.len()))?;
+    let mut writer = builder.into_inner()?;
+    writer.flush()?;
+
+    let hex = format!("{:x}", writer.hasher.finalize());
+    let _ = writer
+        .inner
+        .persist(blobs.join(&hex))
+        .map_err(|e| e.error)?;
+    Ok(Descriptor {
+        digest: format!("sha256:{}", hex),
+        size: writer.count,
+    })
+}
+
+// Writes `content` into `blobs` under its digest.
+fn write_blob(blobs: &Path, content: &[u8]) -> SeavanResult<Descriptor> {
+    let hex = format!("{:x}", Sha256::digest(content));
+    let mut file = File::create(blobs.join(&hex))?;
+    file.write_all(content)?;
+    Ok(Descriptor {
+        digest: format!("sha256:{}", hex),
+        size: content.len() as u64,
+    })
+}
+
+// Returns the configuration fields describing `platform`, or the host's
+// platform if it isn't given, as Docker would record.
+fn platform(platform: Option<&str>) -> Vec<(&'static str, &str)> {
+    let (os, architecture, variant) = match platform {
+        Some(platform) => {
+            let mut components = platform.split('/');
+            (
+                components.next().unwrap_or_default(),
+                components.next().unwrap_or_default(),
+                components.next(),
+            )
+        }
+        None => {
+            let architecture = match std::env::consts::ARCH {
+                "x86_64" => "amd64",
+                "aarch64" => "arm64",
+                "x86" => "386",
+                "powerpc64" => "ppc64le",
+                architecture => architecture,
+            };
+            ("linux", architecture, None)
+        }
+    };
+    let mut fields = vec![("os", os), ("architecture", architecture)];
+    fields.extend(variant.map(|variant| ("variant", variant)));
+    fields
+}
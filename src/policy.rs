@@ -0,0 +1,145 @@
+//! Policies which apply organizational data-handling rules to files based on
+//! their type.
+//!
+//! Policies match files by extension or by the first bytes of their content,
+//! and are evaluated whenever an image is named or built, so they can't be
+//! bypassed by later builder calls. They can be set in code with
+//! `Seavan::with_policy`, or in configuration files:
+//!
+//! ```toml
+//! [[policies]]
+//! extensions = ["key"]
+//! action = "reject"
+//!
+//! [[policies]]
+//! content_prefix = "-----BEGIN"
+//! action = "label"
+//! key = "com.example.classification"
+//! value = "secret"
+//! ```
+
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Number of bytes read from the start of files to match content prefixes.
+pub(crate) const HEAD_LENGTH: usize = 512;
+
+/// What a policy does to the files it matches.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "action")]
+pub enum PolicyAction {
+    /// Refuse to build images of the file, failing with
+    /// `SeavanError::PolicyRejected`.
+    Reject,
+
+    /// Keep the filename out of the image name and metadata, as with
+    /// `NamingStrategy::HashOnly`.
+    HideFilename,
+
+    /// Squash the image into a single layer, as with `Seavan::with_squash`.
+    Squash,
+
+    /// Add a label to the image.
+    Label {
+        /// The label key.
+        key: String,
+
+        /// The label value.
+        value: String,
+    },
+}
+
+/// A rule applying an action to files of a given type. A file matches if
+/// its extension is one of the policy's extensions (ignoring case), or its
+/// content starts with the policy's content prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    content_prefix: Option<String>,
+    #[serde(flatten)]
+    action: PolicyAction,
+}
+
+impl Policy {
+    /// Creates a policy matching files by extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `extensions`: The file extensions to match, without the leading `.`.
+    /// * `action`: The action to apply to matching files.
+    ///
+    /// # Examples
+    /// ```
+    /// use seavan::policy::{Policy, PolicyAction};
+    /// let policy = Policy::for_extensions(&["key", "p12"], PolicyAction::Reject);
+    /// ```
+    pub fn for_extensions(extensions: &[&str], action: PolicyAction) -> Self {
+        Self {
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            content_prefix: None,
+            action,
+        }
+    }
+
+    /// Creates a policy matching files whose content starts with `prefix`,
+    /// e.g. `-----BEGIN` for PEM files. Content isn't available for
+    /// reference-only `Seavan`s, so they are only matched by extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix`: The content prefix to match.
+    /// * `action`: The action to apply to matching files.
+    pub fn for_content_prefix(prefix: &str, action: PolicyAction) -> Self {
+        Self {
+            extensions: Vec::new(),
+            content_prefix: Some(prefix.into()),
+            action,
+        }
+    }
+
+    /// Returns the action applied to matching files.
+    pub fn action(&self) -> &PolicyAction {
+        &self.action
+    }
+
+    // Returns whether the policy matches on content, so the start of the file
+    // must be read to evaluate it.
+    pub(crate) fn matches_content(&self) -> bool {
+        self.content_prefix.is_some()
+    }
+
+    // Returns a copy of the policy which only matches by extension.
+    pub(crate) fn extensions_only(&self) -> Self {
+        Self {
+            content_prefix: None,
+            ..self.clone()
+        }
+    }
+
+    // Returns whether the file at `path`, starting with `head`, matches.
+    pub(crate) fn matches(&self, path: &Path, head: &[u8]) -> bool {
+        let extension = path.extension().and_then(OsStr::to_str);
+        let by_extension = extension.is_some_and(|extension| {
+            self.extensions
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(extension))
+        });
+        let by_content = self
+            .content_prefix
+            .as_ref()
+            .is_some_and(|prefix| head.starts_with(prefix.as_bytes()));
+        by_extension || by_content
+    }
+}
+
+// Reads the start of `file`, for matching content prefixes.
+pub(crate) fn read_head(file: File) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(HEAD_LENGTH);
+    let _ = file.take(HEAD_LENGTH as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
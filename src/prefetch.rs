@@ -0,0 +1,106 @@
+//! Pulling images ahead of time.
+
+use crate::{
+    client::Client,
+    docker::Docker,
+    error::{SeavanError, SeavanResult},
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Progress of a prefetch, reported for each image.
+#[derive(Debug)]
+pub enum PrefetchEvent<'a> {
+    /// The image has started pulling.
+    Started(&'a str),
+
+    /// The image has been pulled.
+    Pulled(&'a str),
+
+    /// The image couldn't be pulled.
+    Failed(&'a str, &'a SeavanError),
+}
+
+/// Pulls images ahead of time, so jobs which use them don't wait for pulls
+/// on their critical path. At most `parallelism` images are pulled at once,
+/// and `on_event` is called from the pulling threads as each image starts
+/// and finishes.
+///
+/// Returns the outcome for each image, in the same order as `references`.
+///
+/// # Arguments
+///
+/// * `references`: The images to pull.
+/// * `parallelism`: The most images to pull at once; at least one image is
+///   always pulled at a time.
+/// * `on_event`: Called with progress events.
+///
+/// # Examples
+/// ```no_run
+/// use seavan::prefetch::{prefetch, PrefetchEvent};
+/// let results = prefetch(
+///     &["acr.azurecr.io/seavanpkg/abcd--readme-md:latest"],
+///     4,
+///     |event| {
+///         if let PrefetchEvent::Pulled(reference) = event {
+///             println!("Pulled {}", reference);
+///         }
+///     },
+/// );
+/// ```
+pub fn prefetch<F>(references: &[&str], parallelism: usize, on_event: F) -> Vec<SeavanResult<()>>
+where
+    F: Fn(PrefetchEvent<'_>) + Sync,
+{
+    match Client::new() {
+        Ok(client) => client.prefetch(references, parallelism, on_event),
+        // The environment names an invalid engine; report it for each image.
+        Err(_) => references
+            .iter()
+            .map(|_| Client::new().map(|_| ()))
+            .collect(),
+    }
+}
+
+// Pulls images ahead of time, as `prefetch` does, using the given docker
+// settings.
+pub(crate) fn prefetch_with<F>(
+    docker: &Docker,
+    references: &[&str],
+    parallelism: usize,
+    on_event: F,
+) -> Vec<SeavanResult<()>>
+where
+    F: Fn(PrefetchEvent<'_>) + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<SeavanResult<()>>>> =
+        Mutex::new(references.iter().map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, references.len().max(1)) {
+            let _ = scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let reference = match references.get(index) {
+                    Some(reference) => *reference,
+                    None => break,
+                };
+
+                on_event(PrefetchEvent::Started(reference));
+                let result = docker.pull(reference);
+                match &result {
+                    Ok(()) => on_event(PrefetchEvent::Pulled(reference)),
+                    Err(e) => on_event(PrefetchEvent::Failed(reference, e)),
+                }
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|result| result.unwrap_or(Ok(())))
+        .collect()
+}
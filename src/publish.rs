@@ -0,0 +1,101 @@
+//! Publishing wrapped files to a registry.
+//!
+//! A `Publisher` is a `Seavan` which is known to have a registry, so the
+//! operations which need one, such as pushing images or checking whether
+//! they've been pushed, can't fail with `SeavanError::NoRegistry`. Code which
+//! takes a `Publisher` rather than a `Seavan` can only be handed one with a
+//! registry configured; the check is made once, where the `Publisher` is
+//! created, rather than by each operation.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    registry::Registry,
+    report::PushedImage,
+    Seavan,
+};
+use std::convert::TryFrom;
+
+/// A `Seavan` with a registry configured, created with `Seavan::publisher`
+/// or converted from a `Seavan` with `Publisher::try_from`.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{publish::Publisher, Seavan};
+///
+/// fn release(publisher: &Publisher) -> seavan::error::SeavanResult<String> {
+///     Ok(publisher.create_and_push()?.reference)
+/// }
+///
+/// let publisher = Seavan::new("README.md")?.publisher("registry.example.com")?;
+/// println!("Pushed {}", release(&publisher)?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Publisher {
+    wrap: Seavan,
+    registry: Registry,
+}
+
+impl Publisher {
+    /// Returns the registry images are pushed to.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Returns the `Seavan` being published, for operations which don't
+    /// need a registry.
+    pub fn seavan(&self) -> &Seavan {
+        &self.wrap
+    }
+
+    /// Returns the `Seavan` being published.
+    pub fn into_seavan(self) -> Seavan {
+        self.wrap
+    }
+
+    /// Pushes the image built by `Seavan::create_image`, as
+    /// `Seavan::push_image` does.
+    pub fn push_image(&self) -> SeavanResult<PushedImage> {
+        self.wrap.push_image()
+    }
+
+    /// Creates the image and pushes it, as `Seavan::create_and_push` does.
+    pub fn create_and_push(&self) -> SeavanResult<PushedImage> {
+        self.wrap.create_and_push()
+    }
+
+    /// Builds the image natively and pushes it over the registry API, as
+    /// `Seavan::push_via_registry_api` does.
+    #[cfg(feature = "oci-native")]
+    pub fn push_via_registry_api(&self) -> SeavanResult<PushedImage> {
+        self.wrap.push_via_registry_api()
+    }
+
+    /// Removes older content versions of the wrapped file from the
+    /// registry, as `Seavan::prune_keep_latest_remote` does.
+    pub fn prune_keep_latest_remote(&self, keep: usize) -> SeavanResult<Vec<String>> {
+        self.wrap.prune_keep_latest_remote(keep)
+    }
+
+    /// Checks whether the image for the file's current content has been
+    /// pushed, as `Seavan::exists_in_registry` does.
+    pub fn exists_in_registry(&self) -> SeavanResult<bool> {
+        self.wrap.exists_in_registry()
+    }
+}
+
+impl TryFrom<Seavan> for Publisher {
+    type Error = SeavanError;
+
+    /// Fails with `SeavanError::NoRegistry` if no registry is configured,
+    /// e.g. with `Seavan::with_registry` or the `SEAVAN_REGISTRY`
+    /// environment variable.
+    fn try_from(wrap: Seavan) -> SeavanResult<Self> {
+        match wrap.registry.clone() {
+            Some(registry) => Ok(Publisher { wrap, registry }),
+            None => Err(SeavanError::NoRegistry(wrap.path)),
+        }
+    }
+}
@@ -0,0 +1,638 @@
+//! Container registries to store images in.
+
+use crate::{
+    client::Client,
+    docker::{self, Docker},
+    error::{SeavanError, SeavanResult},
+    labels,
+    utils::curl_quote,
+};
+use log::debug;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Hostname of the GitHub Container Registry.
+const GHCR_HOST: &str = "ghcr.io";
+
+// Hostname of Quay.io.
+const QUAY_HOST: &str = "quay.io";
+
+// Longest Harbor project name.
+const MAX_HARBOR_PROJECT_LENGTH: usize = 255;
+
+// Longest Artifactory repository key.
+const MAX_ARTIFACTORY_KEY_LENGTH: usize = 64;
+
+/// Storage used by a registry, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageUsage {
+    /// The storage currently in use.
+    pub used: u64,
+
+    /// The registry's storage quota, if it has one.
+    pub limit: Option<u64>,
+}
+
+/// How seavan's own registry clients, such as `Seavan::push_via_registry_api`
+/// and `retention::prune_remote`, connect to a registry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Transport {
+    proxy: Option<String>,
+    insecure: bool,
+    ca_certificates: Option<PathBuf>,
+    client_certificate: Option<(PathBuf, PathBuf)>,
+}
+
+impl Transport {
+    // Returns the URL scheme for the registry API at `host`. Insecure and
+    // loopback registries are assumed to serve plain HTTP.
+    pub(crate) fn scheme(&self, host: &str) -> &'static str {
+        match self.insecure || is_loopback(host) {
+            true => "http",
+            false => "https",
+        }
+    }
+
+    // Returns whether the registry is insecure.
+    pub(crate) fn insecure(&self) -> bool {
+        self.insecure
+    }
+
+    // Returns the curl configuration for a request to `url`. A configured
+    // proxy is always used. Otherwise curl uses the `https_proxy`,
+    // `HTTPS_PROXY`, `http_proxy` and `NO_PROXY` environment variables
+    // itself; it ignores `HTTP_PROXY`, so that is passed on explicitly.
+    pub(crate) fn curl_config(&self, url: &str) -> String {
+        let mut config = String::new();
+        if self.insecure {
+            // Insecure registries may still redirect to, or authenticate
+            // with, HTTPS servers whose certificates can't be verified.
+            config += "insecure\n";
+        }
+        if let Some(path) = &self.ca_certificates {
+            config += &format!("cacert = \"{}\"\n", curl_quote(&path.to_string_lossy()));
+        }
+        if let Some((certificate, key)) = &self.client_certificate {
+            config += &format!(
+                "cert = \"{}\"\nkey = \"{}\"\n",
+                curl_quote(&certificate.to_string_lossy()),
+                curl_quote(&key.to_string_lossy())
+            );
+        }
+        if let Some(proxy) = &self.proxy {
+            return config + &format!("proxy = \"{}\"\nnoproxy = \"\"\n", curl_quote(proxy));
+        }
+        let plain_http = url.starts_with("http://") && std::env::var_os("http_proxy").is_none();
+        match std::env::var("HTTP_PROXY") {
+            Ok(proxy) if plain_http && !proxy.is_empty() => {
+                config + &format!("proxy = \"{}\"\n", curl_quote(&proxy))
+            }
+            _ => config,
+        }
+    }
+}
+
+/// The kind of registry, used to apply registry-specific conventions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Profile {
+    Generic,
+    Ghcr { owner: String },
+    Project { project: String },
+    Quay,
+}
+
+/// A container registry to store images in, along with any labels the
+/// registry expects images to carry.
+///
+/// A `Registry` can be created from a string holding the registry name, or
+/// with a helper for a specific registry such as `Registry::ghcr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registry {
+    name: String,
+    profile: Profile,
+    labels: Vec<(String, String)>,
+    transport: Transport,
+}
+
+impl Registry {
+    /// Creates a new generic `Registry`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The registry host, optionally followed by a path prefix,
+    ///   e.g. `acr.azurecr.io` or `registry.example.com/team`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.trim_end_matches('/').into(),
+            profile: Profile::Generic,
+            labels: Vec::new(),
+            transport: Transport::default(),
+        }
+    }
+
+    /// Creates a `Registry` for the GitHub Container Registry, storing images
+    /// under the given user or organisation. GHCR requires lower-case names,
+    /// so the owner is lower-cased.
+    ///
+    /// To link the packages to a GitHub repository, use
+    /// `with_source_repository`.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner`: The GitHub user or organisation which owns the packages.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{registry::Registry, Seavan};
+    /// let registry = Registry::ghcr("cmeister2")?.with_source_repository("seavan")?;
+    /// let wrap = Seavan::new("README.md")?.with_registry(registry)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn ghcr(owner: &str) -> SeavanResult<Self> {
+        let valid = !owner.is_empty()
+            && !owner.starts_with('-')
+            && owner.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return Err(SeavanError::InvalidRegistryComponent(owner.into()));
+        }
+
+        let owner = owner.to_ascii_lowercase();
+        Ok(Self {
+            name: format!("{}/{}", GHCR_HOST, owner),
+            profile: Profile::Ghcr { owner },
+            labels: Vec::new(),
+            transport: Transport::default(),
+        })
+    }
+
+    /// Creates a `Registry` for a Harbor instance, storing images under the
+    /// given project. Harbor only accepts pushes to projects which already
+    /// exist.
+    ///
+    /// Project names may contain lower-case letters, digits, `.`, `_` and
+    /// `-`, and must start with a letter or digit.
+    ///
+    /// # Arguments
+    ///
+    /// * `host`: The Harbor registry host, e.g. `harbor.example.com`.
+    /// * `project`: The Harbor project to store images in.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{registry::Registry, Seavan};
+    /// let registry = Registry::harbor("harbor.example.com", "data")?;
+    /// let wrap = Seavan::new("README.md")?.with_registry(registry)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn harbor(host: &str, project: &str) -> SeavanResult<Self> {
+        let valid = (1..=MAX_HARBOR_PROJECT_LENGTH).contains(&project.len())
+            && project.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && project.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-')
+            });
+        Self::with_project(host, project, valid)
+    }
+
+    /// Creates a `Registry` for an Artifactory instance, storing images in
+    /// the given Docker repository. Artifactory only accepts pushes to
+    /// repositories which already exist.
+    ///
+    /// Repository keys may contain letters, digits, `.`, `_` and `-`, and are
+    /// at most 64 characters long.
+    ///
+    /// # Arguments
+    ///
+    /// * `host`: The Artifactory registry host, e.g. `example.jfrog.io`.
+    /// * `repository_key`: The key of the Artifactory Docker repository.
+    pub fn artifactory(host: &str, repository_key: &str) -> SeavanResult<Self> {
+        let valid = (1..=MAX_ARTIFACTORY_KEY_LENGTH).contains(&repository_key.len())
+            && repository_key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        Self::with_project(host, repository_key, valid)
+    }
+
+    // Creates a `Registry` which stores images under a project component.
+    fn with_project(host: &str, project: &str, valid: bool) -> SeavanResult<Self> {
+        if !valid {
+            return Err(SeavanError::InvalidRegistryComponent(project.into()));
+        }
+        let host = host.trim_end_matches('/');
+        if host.is_empty() || host.contains('/') {
+            return Err(SeavanError::InvalidRegistryComponent(host.into()));
+        }
+
+        Ok(Self {
+            name: format!("{}/{}", host, project),
+            profile: Profile::Project {
+                project: project.into(),
+            },
+            labels: Vec::new(),
+            transport: Transport::default(),
+        })
+    }
+
+    /// Creates a `Registry` for Quay.io, storing images under the given user
+    /// or organisation namespace.
+    ///
+    /// Quay doesn't support nested repository names, so the generated
+    /// repository path is flattened into a single component, e.g.
+    /// `quay.io/<namespace>/seavanpkg.<hash>--<filename>`.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace`: The Quay user or organisation which owns the
+    ///   repositories.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{registry::Registry, Seavan};
+    /// let wrap = Seavan::new("README.md")?.with_registry(Registry::quay("myorg")?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn quay(namespace: &str) -> SeavanResult<Self> {
+        Self::quay_host(QUAY_HOST, namespace)
+    }
+
+    /// Creates a `Registry` for a self-hosted Quay instance, storing images
+    /// under the given user or organisation namespace. Repository paths are
+    /// flattened as for `Registry::quay`.
+    ///
+    /// # Arguments
+    ///
+    /// * `host`: The Quay registry host, e.g. `quay.example.com`.
+    /// * `namespace`: The Quay user or organisation which owns the
+    ///   repositories.
+    pub fn quay_host(host: &str, namespace: &str) -> SeavanResult<Self> {
+        let valid = !namespace.is_empty()
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(SeavanError::InvalidRegistryComponent(namespace.into()));
+        }
+        let host = host.trim_end_matches('/');
+        if host.is_empty() || host.contains('/') {
+            return Err(SeavanError::InvalidRegistryComponent(host.into()));
+        }
+
+        Ok(Self {
+            name: format!("{}/{}", host, namespace),
+            profile: Profile::Quay,
+            labels: Vec::new(),
+            transport: Transport::default(),
+        })
+    }
+
+    /// Returns the project (or repository key) images are stored under, for
+    /// registries created with `Registry::harbor` or `Registry::artifactory`.
+    pub fn project(&self) -> Option<&str> {
+        match &self.profile {
+            Profile::Project { project } => Some(project),
+            _ => None,
+        }
+    }
+
+    // Returns whether `stderr` from a failed push reports that the project
+    // images are pushed to doesn't exist, as Harbor's `project <name> not
+    // found` or Artifactory's `repository '<name>' not found` do. Other
+    // missing resources, such as blobs, aren't missing projects.
+    pub(crate) fn reports_missing_project(&self, stderr: &str) -> bool {
+        let project = match self.project() {
+            Some(project) => project.to_ascii_lowercase(),
+            None => return false,
+        };
+        let stderr = stderr.to_ascii_lowercase();
+        [
+            format!("project {} not found", project),
+            format!("repository '{}' not found", project),
+            format!("repository \"{}\" not found", project),
+        ]
+        .iter()
+        .any(|message| stderr.contains(message.as_str()))
+    }
+
+    /// Links images pushed to GHCR with the given GitHub repository, by
+    /// setting the `org.opencontainers.image.source` label GitHub uses to
+    /// connect packages to repositories. The repository must belong to the
+    /// registry's owner.
+    ///
+    /// Fails with `SeavanError::UnsupportedRegistryOption` on registries which
+    /// weren't created with `Registry::ghcr`.
+    ///
+    /// # Arguments
+    ///
+    /// * `repository`: The name of the GitHub repository, without the owner.
+    pub fn with_source_repository(self, repository: &str) -> SeavanResult<Self> {
+        let owner = match &self.profile {
+            Profile::Ghcr { owner } => owner.clone(),
+            _ => {
+                return Err(SeavanError::UnsupportedRegistryOption(
+                    "source repository".into(),
+                ))
+            }
+        };
+        Ok(self.with_label(
+            labels::OCI_SOURCE,
+            &format!("https://github.com/{}/{}", owner, repository),
+        ))
+    }
+
+    /// Sends the requests seavan makes to the registry API itself, such as
+    /// those of `Seavan::push_via_registry_api` and
+    /// `retention::prune_remote`, through an HTTP proxy, even if `NO_PROXY`
+    /// lists the registry. Without this, the `HTTPS_PROXY`, `HTTP_PROXY` and
+    /// `NO_PROXY` environment variables are honoured. Docker commands inherit
+    /// the environment, and use the proxies configured for the Docker daemon
+    /// to reach the registry.
+    ///
+    /// # Arguments
+    ///
+    /// * `proxy`: The proxy URL, e.g. `http://proxy.example.com:3128`.
+    ///
+    /// # Examples
+    /// ```
+    /// use seavan::registry::Registry;
+    /// let registry = Registry::new("registry.example.com")
+    ///     .with_proxy("http://proxy.example.com:3128");
+    /// ```
+    pub fn with_proxy(mut self, proxy: &str) -> Self {
+        self.transport.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Talks to the registry over plain HTTP rather than HTTPS, and skips
+    /// verifying the certificates of any HTTPS servers it sends seavan to,
+    /// for lab and air-gapped registries without certificates. This applies
+    /// to the requests seavan makes itself, such as those of
+    /// `Seavan::push_via_registry_api` and `retention::prune_remote`, and to
+    /// the docker commands seavan runs to inspect images in the registry.
+    /// Docker only pushes to and pulls from such registries if they're
+    /// listed in the `insecure-registries` of the Docker daemon's
+    /// configuration.
+    ///
+    /// Registries at `localhost`, `127.0.0.1` or `[::1]` are always reached
+    /// over plain HTTP.
+    ///
+    /// # Arguments
+    ///
+    /// * `insecure`: Whether the registry is insecure.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::registry::Registry;
+    /// let registry = Registry::new("registry.lab:5000").with_insecure_registry(true)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_insecure_registry(mut self, insecure: bool) -> SeavanResult<Self> {
+        let _ = self.set_insecure_registry(insecure)?;
+        Ok(self)
+    }
+
+    /// Marks the registry as insecure, as `with_insecure_registry` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `insecure`: Whether the registry is insecure.
+    pub fn set_insecure_registry(&mut self, insecure: bool) -> SeavanResult<&mut Self> {
+        self.transport.insecure = insecure;
+        Ok(self)
+    }
+
+    /// Trusts the root CA certificates in a PEM bundle, instead of the
+    /// system's, for the requests seavan makes to the registry API itself,
+    /// such as those of `Seavan::push_via_registry_api` and
+    /// `retention::prune_remote`, so registries with internally signed
+    /// certificates can be reached. Docker reads the CA certificates for a
+    /// registry from `/etc/docker/certs.d/<host>/ca.crt` on the Docker
+    /// daemon's host instead.
+    ///
+    /// Fails with `SeavanError::IoError` if the bundle can't be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The PEM file holding the CA certificates.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::registry::Registry;
+    /// let registry = Registry::new("registry.corp.example.com")
+    ///     .with_ca_certificates("/etc/pki/corp-ca.pem")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_ca_certificates<P: AsRef<Path>>(mut self, path: P) -> SeavanResult<Self> {
+        let _ = self.set_ca_certificates(path)?;
+        Ok(self)
+    }
+
+    /// Trusts the root CA certificates in a PEM bundle for the registry API,
+    /// as `with_ca_certificates` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The PEM file holding the CA certificates.
+    pub fn set_ca_certificates<P: AsRef<Path>>(&mut self, path: P) -> SeavanResult<&mut Self> {
+        self.transport.ca_certificates = Some(readable_file(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Presents a client certificate to registries which require mutual TLS,
+    /// for the requests seavan makes to the registry API itself, such as
+    /// those of `Seavan::push_via_registry_api` and
+    /// `retention::prune_remote`. Docker reads the client certificate for a
+    /// registry from `client.cert` and `client.key` in
+    /// `/etc/docker/certs.d/<host>/` on the Docker daemon's host instead.
+    ///
+    /// Fails with `SeavanError::IoError` if either file can't be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `certificate`: The PEM file holding the client certificate.
+    /// * `key`: The PEM file holding the certificate's private key.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::registry::Registry;
+    /// let registry = Registry::new("registry.corp.example.com")
+    ///     .with_client_certificate("/etc/pki/ci.crt", "/etc/pki/ci.key")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_client_certificate<P: AsRef<Path>, Q: AsRef<Path>>(
+        mut self,
+        certificate: P,
+        key: Q,
+    ) -> SeavanResult<Self> {
+        let _ = self.set_client_certificate(certificate, key)?;
+        Ok(self)
+    }
+
+    /// Presents a client certificate to registries which require mutual TLS,
+    /// as `with_client_certificate` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `certificate`: The PEM file holding the client certificate.
+    /// * `key`: The PEM file holding the certificate's private key.
+    pub fn set_client_certificate<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        certificate: P,
+        key: Q,
+    ) -> SeavanResult<&mut Self> {
+        self.transport.client_certificate = Some((
+            readable_file(certificate.as_ref())?,
+            readable_file(key.as_ref())?,
+        ));
+        Ok(self)
+    }
+
+    // Returns the settings for connecting to the registry API.
+    pub(crate) fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    // Sets a label which images stored in this registry should carry.
+    fn with_label(mut self, key: &str, value: &str) -> Self {
+        self.labels.retain(|(k, _)| k != key);
+        self.labels.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns the registry name, including any path prefix.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Returns the registry host, without any path prefix.
+    pub(crate) fn host(&self) -> &str {
+        self.name.split('/').next().unwrap_or(&self.name)
+    }
+
+    // Returns the full repository path for a repository component stored
+    // under the package root in this registry.
+    pub(crate) fn repository(&self, package_root: &str, component: &str) -> String {
+        match self.profile {
+            Profile::Quay => format!("{}/{}.{}", self.name, package_root, component),
+            _ => format!("{}/{}/{}", self.name, package_root, component),
+        }
+    }
+
+    /// Returns the labels which images stored in this registry should carry.
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+
+    /// Returns the storage used by this registry, or `None` if seavan can't
+    /// query the registry's usage. Usage is currently available for Azure
+    /// Container Registry with the `azure` feature.
+    pub fn storage_usage(&self) -> SeavanResult<Option<StorageUsage>> {
+        #[cfg(feature = "azure")]
+        if crate::acr::is_acr(self) {
+            return crate::acr::storage_usage(self).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+// Checks that the file at `path` can be read, returning its absolute path so
+// that it's found from any working directory.
+fn readable_file(path: &Path) -> SeavanResult<PathBuf> {
+    let _ = fs::File::open(path)?;
+    Ok(fs::canonicalize(path)?)
+}
+
+impl From<&str> for Registry {
+    fn from(name: &str) -> Self {
+        Registry::new(name)
+    }
+}
+
+impl From<String> for Registry {
+    fn from(name: String) -> Self {
+        Registry::new(&name)
+    }
+}
+
+/// Checks whether an image can be fetched anonymously, i.e. whether it is
+/// publicly accessible. Use this to verify that internal data has not been
+/// pushed somewhere public.
+///
+/// The image manifest is fetched with `docker manifest inspect` using an
+/// empty Docker configuration, so no stored credentials are used. Returns
+/// `true` if the manifest could be fetched, or `false` if the registry
+/// refused the anonymous request. Any other failure, such as a network error
+/// or a missing image, is returned as an error rather than being taken to
+/// mean the image is private.
+///
+/// # Arguments
+///
+/// * `reference`: The image reference to check.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::registry::check_public_access;
+/// assert!(!check_public_access("acr.azurecr.io/seavanpkg/abcd--readme-md:latest")?);
+/// # Ok(())
+/// # }
+/// ```
+pub fn check_public_access(reference: &str) -> SeavanResult<bool> {
+    Client::new()?.check_public_access(reference)
+}
+
+// Returns whether the registry `host`, which may have a port, is on the
+// loopback interface.
+fn is_loopback(host: &str) -> bool {
+    let name = match host.starts_with('[') {
+        true => host.find(']').map(|end| &host[..=end]),
+        false => host.split(':').next(),
+    };
+    matches!(name, Some("localhost") | Some("127.0.0.1") | Some("[::1]"))
+}
+
+// Checks whether the image `reference` can be fetched anonymously, running
+// docker with the given settings.
+pub(crate) fn public_access(docker: &Docker, reference: &str) -> SeavanResult<bool> {
+    let anonymous_config = tempfile::tempdir()?;
+    let output = docker.run(
+        docker
+            .command()
+            .args(docker.manifest_inspect_args(reference))
+            .env("DOCKER_CONFIG", anonymous_config.path()),
+        None,
+    )?;
+    if output.status.success() {
+        return Ok(true);
+    }
+
+    // Registries refuse anonymous requests in different ways.
+    let stderr = docker::stderr_string(output);
+    let lowercase = stderr.to_lowercase();
+    match ["unauthorized", "denied", "authentication required"]
+        .iter()
+        .any(|refused| lowercase.contains(refused))
+    {
+        true => {
+            debug!(
+                "Anonymous fetch of {} was refused: {}",
+                reference,
+                stderr.trim()
+            );
+            Ok(false)
+        }
+        false => Err(SeavanError::DockerInspectFailure(stderr)),
+    }
+}
@@ -0,0 +1,402 @@
+//! Progress reporting for the operations seavan runs.
+
+use crate::hash::HashAlgorithm;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A phase of creating or pushing an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Resolving the canonical path of the wrapped file.
+    Canonicalize,
+    /// Hashing the wrapped file.
+    Hash,
+    /// Rendering the Dockerfile.
+    Render,
+    /// Building the image with Docker.
+    Build,
+    /// Tagging the image with its final name.
+    Tag,
+    /// Pushing the image to a registry.
+    Push,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Canonicalize => "canonicalize",
+            Phase::Hash => "hash",
+            Phase::Render => "render",
+            Phase::Build => "build",
+            Phase::Tag => "tag",
+            Phase::Push => "push",
+        })
+    }
+}
+
+/// Whether the local image of a wrapped file is up to date, as returned by
+/// `Seavan::is_up_to_date`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageStatus {
+    /// The image for the file's current content exists locally.
+    UpToDate(String),
+
+    /// There is no image for the file's current content, but there are
+    /// images of an earlier version of the file under the same tag. Holds
+    /// their references.
+    Stale(Vec<String>),
+
+    /// There are no local images of the file.
+    Missing,
+}
+
+/// What `Seavan::create_image_if_missing` did. Each variant holds the
+/// repository name and tag of the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildOutcome {
+    /// The image was built.
+    Built(String),
+
+    /// The image for the file's current content already existed locally.
+    AlreadyLocal(String),
+
+    /// The image for the file's current content had already been pushed to
+    /// the configured registry.
+    AlreadyRemote(String),
+}
+
+impl BuildOutcome {
+    /// Returns the repository name and tag of the image.
+    pub fn reference(&self) -> &str {
+        match self {
+            BuildOutcome::Built(reference)
+            | BuildOutcome::AlreadyLocal(reference)
+            | BuildOutcome::AlreadyRemote(reference) => reference,
+        }
+    }
+}
+
+/// The progress an operation made before it was stopped by its deadline.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartialResult {
+    /// The phases which completed, in order.
+    pub completed: Vec<Phase>,
+
+    /// The phase which was skipped or cancelled when the deadline passed.
+    pub interrupted: Option<Phase>,
+
+    /// The repository name and tag of the image the operation was working
+    /// on, once it was known.
+    pub reference: Option<String>,
+}
+
+impl fmt::Display for PartialResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.interrupted {
+            Some(phase) => write!(f, "{} phase was interrupted", phase)?,
+            None => f.write_str("operation was interrupted")?,
+        }
+        if !self.completed.is_empty() {
+            let completed: Vec<String> = self.completed.iter().map(Phase::to_string).collect();
+            write!(f, " after {}", completed.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A decision seavan made on the user's behalf which didn't stop the image
+/// being built, but may not be what they expect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// Characters which aren't allowed in image references were replaced,
+    /// so different filenames or tags may produce the same reference. Holds
+    /// the original and sanitized values.
+    LossySanitization(String, String),
+
+    /// The filename was truncated to fit the repository name, as with
+    /// `NamingStrategy::Nix`. Holds the sanitized filename and the part
+    /// which was kept.
+    FilenameTruncated(String, String),
+
+    /// A label was left out of the image. Holds the label key and the
+    /// reason.
+    LabelDropped(String, String),
+
+    /// The file is sparse, and its holes will be filled with zeros in the
+    /// image. Holds the file's length and the bytes allocated for it.
+    SparseFile(u64, u64),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::LossySanitization(original, sanitized) => {
+                write!(f, "{:?} was sanitized to {:?}", original, sanitized)
+            }
+            Warning::FilenameTruncated(filename, kept) => {
+                write!(f, "filename {:?} was truncated to {:?}", filename, kept)
+            }
+            Warning::LabelDropped(key, reason) => {
+                write!(f, "label {} was dropped: {}", key, reason)
+            }
+            Warning::SparseFile(size, allocated) => write!(
+                f,
+                "file is sparse: {} bytes are allocated on disk, but all {} bytes will be \
+                 copied into the image",
+                allocated, size
+            ),
+        }
+    }
+}
+
+/// A handler for warnings raised while creating images.
+///
+/// This is implemented for closures taking a `&Warning`, so a callback can be
+/// used directly.
+pub trait WarningHandler: Send + Sync {
+    /// Handles a warning.
+    fn warn(&self, warning: &Warning);
+}
+
+impl<F> WarningHandler for F
+where
+    F: Fn(&Warning) + Send + Sync,
+{
+    fn warn(&self, warning: &Warning) {
+        self(warning)
+    }
+}
+
+// A shareable warning handler.
+#[derive(Clone)]
+pub(crate) struct Handler(pub(crate) Arc<dyn WarningHandler>);
+
+impl fmt::Debug for Handler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Handler")
+    }
+}
+
+/// Progress of a build or push, as passed to the callback of
+/// `Seavan::create_image_with_progress` and `Seavan::push_image_with_progress`.
+///
+/// BuildKit's plain progress output and the legacy builder's output are
+/// parsed into steps, and `docker push`'s output into the status of each
+/// layer; other lines are passed on as `Output`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildEvent {
+    /// A build step started. Holds the step's number and its description,
+    /// e.g. `[1/1] COPY data.csv /`.
+    StepStarted(u32, String),
+
+    /// A line of output from a build step. Holds the step's number and the
+    /// line.
+    StepOutput(u32, String),
+
+    /// A build step finished. Holds the step's number and how long it took,
+    /// if the builder reported it.
+    StepDone(u32, Option<Duration>),
+
+    /// A build step was skipped because its result was cached. Holds the
+    /// step's number.
+    StepCached(u32),
+
+    /// A build step failed. Holds the step's number and the error.
+    StepFailed(u32, String),
+
+    /// Part of the file was hashed before it's built or pushed. Holds the
+    /// number of bytes hashed so far and the size of the file. Sent
+    /// periodically while the file is hashed, and once it's done.
+    Hashing(u64, u64),
+
+    /// Docker reported the status of a layer it's pushing. Holds the layer's
+    /// ID and its status, e.g. `Preparing`, `Pushed` or `Layer already
+    /// exists`. Docker doesn't report how much of a layer it's uploaded
+    /// unless its output is a terminal.
+    LayerStatus(String, String),
+
+    /// A line of output which isn't part of a step or a layer.
+    Output(String),
+}
+
+// Parses lines of build output into `BuildEvent`s.
+#[derive(Debug, Default)]
+pub(crate) struct BuildEventParser {
+    started: Vec<u32>,
+}
+
+impl BuildEventParser {
+    // Parses one line of output.
+    pub(crate) fn parse(&mut self, line: &str) -> BuildEvent {
+        // The legacy builder prints e.g. `Step 1/2 : COPY data.csv /`.
+        if let Some((step, description)) = line
+            .strip_prefix("Step ")
+            .and_then(|rest| rest.split_once(" : "))
+        {
+            if let Some(number) = step.split('/').next().and_then(|n| n.parse().ok()) {
+                return BuildEvent::StepStarted(number, description.into());
+            }
+        }
+
+        // BuildKit prints e.g. `#5 [1/1] COPY data.csv /`, then the step's
+        // output, then `#5 DONE 0.1s`.
+        let (number, rest) = match line
+            .strip_prefix('#')
+            .and_then(|rest| rest.split_once(' '))
+            .and_then(|(number, rest)| Some((number.parse::<u32>().ok()?, rest)))
+        {
+            Some(step) => step,
+            None => return BuildEvent::Output(line.into()),
+        };
+        if !self.started.contains(&number) {
+            self.started.push(number);
+            return BuildEvent::StepStarted(number, rest.into());
+        }
+        if rest == "CACHED" {
+            return BuildEvent::StepCached(number);
+        }
+        if let Some(elapsed) = rest.strip_prefix("DONE") {
+            let elapsed = elapsed
+                .trim()
+                .strip_suffix('s')
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs_f64);
+            return BuildEvent::StepDone(number, elapsed);
+        }
+        match rest.strip_prefix("ERROR") {
+            Some(error) => {
+                BuildEvent::StepFailed(number, error.trim_start_matches(':').trim().into())
+            }
+            None => BuildEvent::StepOutput(number, rest.into()),
+        }
+    }
+
+    // Parses one line of `docker push` output, e.g. `5f70bf18a086: Pushed`.
+    pub(crate) fn parse_push(line: &str) -> BuildEvent {
+        match line.split_once(": ") {
+            Some((layer, status))
+                if !layer.is_empty() && layer.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                BuildEvent::LayerStatus(layer.into(), status.trim().into())
+            }
+            _ => BuildEvent::Output(line.into()),
+        }
+    }
+}
+
+// Reborrows an optional callback for `BuildEvent`s, so it can be passed on
+// more than once.
+pub(crate) fn reborrow<'a>(
+    on_event: &'a mut Option<&mut dyn FnMut(&BuildEvent)>,
+) -> Option<&'a mut dyn FnMut(&BuildEvent)> {
+    match on_event {
+        Some(on_event) => Some(&mut **on_event),
+        None => None,
+    }
+}
+
+/// A report on an image created by `Seavan::create_image_with_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildReport {
+    /// The repository name and tag of the image.
+    pub reference: String,
+
+    /// How long each phase took, in the order the phases ran.
+    pub timings: Vec<(Phase, Duration)>,
+
+    /// The warnings raised while creating the image.
+    pub warnings: Vec<Warning>,
+}
+
+impl BuildReport {
+    /// Returns how long `phase` took, or `None` if it didn't run.
+    ///
+    /// # Arguments
+    ///
+    /// * `phase`: The phase to look up.
+    pub fn timing(&self, phase: Phase) -> Option<Duration> {
+        self.timings
+            .iter()
+            .find(|(p, _)| *p == phase)
+            .map(|(_, duration)| *duration)
+    }
+
+    /// Returns the total time taken by all phases.
+    pub fn total(&self) -> Duration {
+        self.timings.iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+/// An image pushed by `Seavan::push_image`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushedImage {
+    /// The repository name and tag the image was pushed as.
+    pub reference: String,
+
+    /// The digest of the pushed manifest, e.g. `sha256:<hex>`, or `None` if
+    /// docker didn't report it. Pulling `<repository>@<digest>` always
+    /// fetches exactly this image.
+    pub digest: Option<String>,
+}
+
+/// The result of checking a file against an image with `Seavan::verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// The image reference the file was checked against.
+    pub reference: String,
+
+    /// The file which was checked.
+    pub path: PathBuf,
+
+    /// The algorithm the file was hashed with.
+    pub algorithm: HashAlgorithm,
+
+    /// The hash in the image's repository name, which may be truncated.
+    pub expected: String,
+
+    /// The full content hash of the file.
+    pub actual: String,
+
+    /// Whether the file matches: its hash starts with the hash in the
+    /// repository name, and was computed with the algorithm named there.
+    /// A directory checked against a bundle must also have no `mismatched`
+    /// files.
+    pub verified: bool,
+
+    /// When a directory is checked against a `bundle::SeavanBundle` which is
+    /// available locally, the paths of the bundle's files which are missing
+    /// from the directory or differ from the bundle's, and of files in the
+    /// directory which aren't in the bundle, sorted. Always empty for single
+    /// files.
+    pub mismatched: Vec<String>,
+}
+
+// The phases an operation has completed so far, with their timings.
+#[derive(Debug, Default)]
+pub(crate) struct Progress {
+    pub(crate) timings: Vec<(Phase, Duration)>,
+    pub(crate) reference: Option<String>,
+    pub(crate) warnings: Vec<Warning>,
+}
+
+impl Progress {
+    // Creates the partial result for an operation interrupted during `phase`.
+    pub(crate) fn partial(&self, phase: Phase) -> PartialResult {
+        PartialResult {
+            completed: self.timings.iter().map(|(phase, _)| *phase).collect(),
+            interrupted: Some(phase),
+            reference: self.reference.clone(),
+        }
+    }
+
+    // Creates the report for a completed image.
+    pub(crate) fn report(self, reference: String) -> BuildReport {
+        BuildReport {
+            reference,
+            timings: self.timings,
+            warnings: self.warnings,
+        }
+    }
+}
@@ -0,0 +1,691 @@
+//! Retention of older generations of wrapped files.
+
+use crate::{
+    client::Client,
+    distribution,
+    docker::{self, Docker},
+    error::{SeavanError, SeavanResult},
+    labels,
+    registry::Registry,
+};
+use log::debug;
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Number of repositories requested from the registry catalog at once. The
+// registry may return fewer, linking to the next page.
+const CATALOG_PAGE_SIZE: usize = 1000;
+
+// Length of an RFC 3339 timestamp up to the seconds, e.g.
+// `2023-01-02T03:04:05`.
+const RFC3339_SECONDS_LENGTH: usize = 19;
+
+// A content version of a wrapped file held in the local image store.
+#[derive(Debug, Default)]
+struct Generation {
+    created: Option<(i64, u32)>,
+    references: Vec<String>,
+}
+
+/// Removes older content versions of a wrapped file from the local image
+/// store, keeping the newest `keep`. Images with the same content digest
+/// count as one version however many tags they have.
+///
+/// Images are matched by their `seavan.original-filename` label, so images
+/// built with `NamingStrategy::HashOnly` are never pruned. Remote images are
+/// left alone; use `prune_keep_latest_remote` to prune a registry too. Use
+/// `Seavan::prune_keep_latest` to record the removals in an audit log.
+///
+/// Returns the references which were removed.
+///
+/// # Arguments
+///
+/// * `filename`: The name of the wrapped file, without any directory.
+/// * `keep`: The number of content versions to keep.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::retention::prune_keep_latest;
+/// let removed = prune_keep_latest("model.onnx", 24)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn prune_keep_latest<S: AsRef<OsStr> + ?Sized>(
+    filename: &S,
+    keep: usize,
+) -> SeavanResult<Vec<String>> {
+    Client::new()?.prune_keep_latest(filename, keep)
+}
+
+// Removes older content versions of a wrapped file from the local image store
+// as `prune_keep_latest` does, calling `on_removed` with each reference
+// removed.
+pub(crate) fn prune_generations<F: FnMut(&str) -> SeavanResult<()>>(
+    docker: &Docker,
+    filename: &OsStr,
+    keep: usize,
+    mut on_removed: F,
+) -> SeavanResult<Vec<String>> {
+    let label = format!(
+        "label={}={}",
+        labels::ORIGINAL_FILENAME,
+        labels::encode_filename(filename)
+    );
+    let output = docker.run(
+        docker
+            .command()
+            .args(["images", "-q", "--no-trunc", "--filter"])
+            .arg(label),
+        None,
+    )?;
+    if !output.status.success() {
+        return Err(SeavanError::DockerInspectFailure(docker::stderr_string(
+            output,
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut ids: Vec<&str> = stdout.lines().filter(|id| !id.is_empty()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Group the images by content, remembering when each was created.
+    let format = format!(
+        "{{{{.Created}}}} {{{{index .Config.Labels {:?}}}}} {{{{join .RepoTags \" \"}}}}",
+        labels::CONTENT_DIGEST
+    );
+    let output = docker.run(
+        docker
+            .command()
+            .args(["image", "inspect", "--format", &format])
+            .args(ids),
+        None,
+    )?;
+    if !output.status.success() {
+        return Err(SeavanError::DockerInspectFailure(docker::stderr_string(
+            output,
+        )));
+    }
+    let mut generations: BTreeMap<String, Generation> = BTreeMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.split_whitespace();
+        let (created, digest) = match (fields.next(), fields.next()) {
+            (Some(created), Some(digest)) => (created, digest),
+            _ => continue,
+        };
+        let generation = generations.entry(digest.into()).or_default();
+        generation.created = generation.created.max(parse_rfc3339(created));
+        generation.references.extend(fields.map(String::from));
+    }
+
+    // Fractional seconds vary in length, so compare the parsed times rather
+    // than the strings. Unparseable times count as the oldest.
+    let mut generations: Vec<Generation> = generations.into_values().collect();
+    generations.sort_by_key(|generation| std::cmp::Reverse(generation.created));
+
+    let mut removed = Vec::new();
+    for generation in generations.into_iter().skip(keep) {
+        for reference in generation.references {
+            debug!("Pruning {}", reference);
+            docker.remove_image(&reference)?;
+            on_removed(&reference)?;
+            removed.push(reference);
+        }
+    }
+    Ok(removed)
+}
+
+/// Selects the remote images removed by `prune_remote`. An image is removed
+/// only if it matches every criterion set; a default filter matches every
+/// seavan image.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteFilter {
+    older_than: Option<Duration>,
+    tag_pattern: Option<Regex>,
+    labels: Vec<(String, String)>,
+}
+
+impl RemoteFilter {
+    /// Creates a new `RemoteFilter` matching every seavan image.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches images created more than `age` ago.
+    ///
+    /// # Arguments
+    ///
+    /// * `age`: The minimum age of matching images.
+    pub fn with_older_than(mut self, age: Duration) -> Self {
+        self.older_than = Some(age);
+        self
+    }
+
+    /// Only matches tags matching the regular expression `pattern`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern`: The regular expression, e.g. `^pr-` for pull request
+    ///   builds.
+    pub fn with_tag_pattern(mut self, pattern: &str) -> SeavanResult<Self> {
+        self.tag_pattern = Some(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Only matches images with the label `key` set to `value`. Can be called
+    /// multiple times to require several labels.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The label key, e.g. `com.example.team`.
+    /// * `value`: The label value.
+    pub fn with_label(mut self, key: &str, value: &str) -> Self {
+        self.labels.push((key.into(), value.into()));
+        self
+    }
+
+    // Returns whether images tagged `tag` may match.
+    pub(crate) fn matches_tag(&self, tag: &str) -> bool {
+        self.tag_pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(tag))
+    }
+
+    // Returns whether an image created at the RFC 3339 time `created`, with
+    // `labels`, matches. Images without a content digest label weren't built
+    // by seavan, so never match.
+    pub(crate) fn matches_image(
+        &self,
+        created: &str,
+        labels: &BTreeMap<String, String>,
+        now: SystemTime,
+    ) -> bool {
+        if !labels.contains_key(labels::CONTENT_DIGEST) {
+            return false;
+        }
+        let old_enough = self.older_than.is_none_or(|age| {
+            let cutoff = now
+                .checked_sub(age)
+                .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            let cutoff = (
+                i64::try_from(cutoff.as_secs()).unwrap_or(i64::MAX),
+                cutoff.subsec_nanos(),
+            );
+            // Times are compared once parsed, as they may have any offset
+            // from UTC. Images created at unparseable times are kept.
+            parse_rfc3339(created).is_some_and(|created| created < cutoff)
+        });
+        old_enough
+            && self
+                .labels
+                .iter()
+                .all(|(key, value)| labels.get(key) == Some(value))
+    }
+}
+
+// Formats seconds since the Unix epoch as an RFC 3339 timestamp in UTC,
+// e.g. `2023-01-02T03:04:05Z`.
+pub(crate) fn rfc3339(secs: u64) -> String {
+    let days = secs / 86400;
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // Convert days since the epoch to a civil date; see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+// Parses an RFC 3339 timestamp, e.g. `2023-01-02T03:04:05.123456789Z`, into
+// seconds since the Unix epoch and nanoseconds.
+pub(crate) fn parse_rfc3339(timestamp: &str) -> Option<(i64, u32)> {
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = timestamp.get(range)?;
+        match digits.bytes().all(|b| b.is_ascii_digit()) {
+            true => digits.parse().ok(),
+            false => None,
+        }
+    };
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':')];
+    let separated = separators.iter().all(|&(i, c)| {
+        timestamp
+            .as_bytes()
+            .get(i)
+            .is_some_and(|b| b.eq_ignore_ascii_case(&c))
+    });
+    if !separated {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    // Any fractional part, then the offset from UTC.
+    let mut rest = &timestamp[RFC3339_SECONDS_LENGTH..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let length = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if length == 0 {
+            return None;
+        }
+        let digits: String = fraction[..length]
+            .chars()
+            .chain("000000000".chars())
+            .take(9)
+            .collect();
+        nanos = digits.parse().ok()?;
+        rest = &fraction[length..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            if rest.len() != 6 || rest.as_bytes()[3] != b':' {
+                return None;
+            }
+            let hours: i64 = rest[1..3].parse().ok()?;
+            let minutes: i64 = rest[4..6].parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    // Convert the civil date to days since the epoch; see
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some((
+        days * 86400 + hour * 3600 + minute * 60 + second - offset,
+        nanos,
+    ))
+}
+
+// A page of the registry's repository catalog.
+#[derive(Deserialize)]
+struct Catalog {
+    #[serde(default)]
+    repositories: Vec<String>,
+}
+
+// The tags of a repository.
+#[derive(Deserialize)]
+struct TagList {
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+}
+
+// The parts of an image configuration used to filter images.
+#[derive(Deserialize)]
+struct ImageConfig {
+    #[serde(default)]
+    created: String,
+    #[serde(default)]
+    config: ImageLabels,
+}
+
+#[derive(Default, Deserialize)]
+struct ImageLabels {
+    #[serde(default, rename = "Labels")]
+    labels: Option<BTreeMap<String, String>>,
+}
+
+// The parts of a manifest used to find its image configuration. Indexes of
+// several images have no configuration.
+#[derive(Deserialize)]
+struct Manifest {
+    config: Option<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    digest: String,
+}
+
+// Fetches `url` from the registry API, parsing the response as JSON.
+fn get_json<T: for<'de> Deserialize<'de>>(
+    client: &mut distribution::Client<'_>,
+    url: &str,
+) -> SeavanResult<T> {
+    let response = client.request("GET", url, None)?;
+    distribution::expect(&response, 200, &format!("fetching {}", url))?;
+    Ok(serde_json::from_slice(&response.body)?)
+}
+
+// Fetches every page of the list at `url` from the registry API, following
+// the `Link` header of each page to the next.
+fn get_pages<T: for<'de> Deserialize<'de>>(
+    client: &mut distribution::Client<'_>,
+    url: &str,
+) -> SeavanResult<Vec<T>> {
+    let mut pages = Vec::new();
+    let mut next = Some(url.to_string());
+    while let Some(url) = next {
+        let response = client.request("GET", &url, None)?;
+        distribution::expect(&response, 200, &format!("fetching {}", url))?;
+        pages.push(serde_json::from_slice(&response.body)?);
+        next = client.next_page(&response);
+    }
+    Ok(pages)
+}
+
+/// Removes seavan images matching `filter` from a registry, by deleting
+/// their manifests through the registry API. Only repositories under the
+/// default `seavanpkg` package root are considered, and only images labelled
+/// with a content digest are removed, so unrelated images are never touched.
+/// Use `client::Client::with_package_root` to look under another root.
+///
+/// Deleting a manifest removes every tag pointing to it, so an image is only
+/// removed if all of its tags match `filter`; images which also have tags
+/// the filter doesn't match are kept.
+///
+/// Requests are made over the OCI distribution API with the `curl` CLI,
+/// using credentials from `~/.netrc` if there are any; registries which
+/// issue bearer tokens are supported. The registry must allow manifests to
+/// be deleted, and typically only frees the storage on its next garbage
+/// collection. Use `client::Client::prune_remote` to give registry
+/// credentials or another package root.
+///
+/// Returns the references which were removed.
+///
+/// # Arguments
+///
+/// * `registry`: The registry to remove images from.
+/// * `filter`: Selects the images to remove.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{registry::Registry, retention::{prune_remote, RemoteFilter}};
+/// use std::time::Duration;
+/// let filter = RemoteFilter::new()
+///     .with_older_than(Duration::from_secs(30 * 24 * 60 * 60))
+///     .with_tag_pattern("^pr-")?
+///     .with_label("com.example.team", "data");
+/// let removed = prune_remote(&Registry::new("registry.example.com"), &filter)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn prune_remote(registry: &Registry, filter: &RemoteFilter) -> SeavanResult<Vec<String>> {
+    Client::new()?.prune_remote(registry, filter)
+}
+
+// Removes seavan images matching `filter` from a registry, as `prune_remote`
+// does, looking under `package_root` and using the credentials `docker` holds
+// for the registry.
+pub(crate) fn prune_remote_with(
+    docker: &Docker,
+    registry: &Registry,
+    package_root: &str,
+    filter: &RemoteFilter,
+) -> SeavanResult<Vec<String>> {
+    let now = SystemTime::now();
+    let (host, prefix) = registry_host_and_prefix(registry, package_root);
+    let mut client = registry_client(docker, registry, &host)?;
+
+    let images = remote_images(&mut client, &prefix, |repository, digest, tags| match tags
+        .iter()
+        .find(|tag| !filter.matches_tag(tag))
+    {
+        Some(kept) => {
+            if tags.iter().any(|tag| filter.matches_tag(tag)) {
+                debug!("Keeping {}@{}: also tagged {}", repository, digest, kept);
+            }
+            false
+        }
+        None => true,
+    })?;
+
+    let mut removed = Vec::new();
+    for image in images {
+        if filter.matches_image(&image.created, &image.labels, now) {
+            removed.extend(delete_image(&mut client, &host, &image)?);
+        }
+    }
+    Ok(removed)
+}
+
+/// Removes older content versions of a wrapped file from a registry, keeping
+/// the newest `keep`, as `prune_keep_latest` does for the local image store.
+/// Images with the same content digest count as one version however many
+/// repositories or tags hold them.
+///
+/// Images are matched by their `seavan.original-filename` label, and are
+/// removed by deleting their manifests through the registry API, as
+/// `prune_remote` does; see it for what the registry must allow. Use
+/// `client::Client::prune_keep_latest_remote` to give registry credentials,
+/// or `Seavan::prune_keep_latest_remote` to record the removals in an audit
+/// log.
+///
+/// Returns the references which were removed.
+///
+/// # Arguments
+///
+/// * `registry`: The registry to remove images from.
+/// * `filename`: The name of the wrapped file, without any directory.
+/// * `keep`: The number of content versions to keep.
+///
+/// # Examples
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{registry::Registry, retention::prune_keep_latest_remote};
+/// let registry = Registry::new("registry.example.com");
+/// let removed = prune_keep_latest_remote(&registry, "model.onnx", 24)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn prune_keep_latest_remote<S: AsRef<OsStr> + ?Sized>(
+    registry: &Registry,
+    filename: &S,
+    keep: usize,
+) -> SeavanResult<Vec<String>> {
+    Client::new()?.prune_keep_latest_remote(registry, filename, keep)
+}
+
+// Removes older content versions of a wrapped file from a registry as
+// `prune_keep_latest_remote` does, looking under `package_root` and calling
+// `on_removed` with each reference removed.
+pub(crate) fn prune_remote_generations<F: FnMut(&str) -> SeavanResult<()>>(
+    docker: &Docker,
+    registry: &Registry,
+    package_root: &str,
+    filename: &OsStr,
+    keep: usize,
+    mut on_removed: F,
+) -> SeavanResult<Vec<String>> {
+    let (host, prefix) = registry_host_and_prefix(registry, package_root);
+    let mut client = registry_client(docker, registry, &host)?;
+    let filename = labels::encode_filename(filename);
+
+    // Group the images by content, remembering when each was created.
+    let mut generations: BTreeMap<String, RemoteGeneration> = BTreeMap::new();
+    for image in remote_images(&mut client, &prefix, |_, _, _| true)? {
+        if image.labels.get(labels::ORIGINAL_FILENAME) != Some(&filename) {
+            continue;
+        }
+        let digest = match image.labels.get(labels::CONTENT_DIGEST) {
+            Some(digest) => digest.clone(),
+            None => continue,
+        };
+        let generation = generations.entry(digest).or_default();
+        generation.created = generation.created.max(parse_rfc3339(&image.created));
+        generation.images.push(image);
+    }
+
+    // Unparseable times count as the oldest.
+    let mut generations: Vec<RemoteGeneration> = generations.into_values().collect();
+    generations.sort_by_key(|generation| std::cmp::Reverse(generation.created));
+
+    let mut removed = Vec::new();
+    for generation in generations.into_iter().skip(keep) {
+        for image in generation.images {
+            for reference in delete_image(&mut client, &host, &image)? {
+                on_removed(&reference)?;
+                removed.push(reference);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+// A content version of a wrapped file held in a registry.
+#[derive(Default)]
+struct RemoteGeneration {
+    created: Option<(i64, u32)>,
+    images: Vec<RemoteImage>,
+}
+
+// A seavan image in a registry, with the tags pointing to its manifest.
+struct RemoteImage {
+    repository: String,
+    digest: String,
+    tags: Vec<String>,
+    created: String,
+    labels: BTreeMap<String, String>,
+}
+
+// Returns the host of `registry`, and the prefix of the repositories under
+// `package_root` in it.
+fn registry_host_and_prefix(registry: &Registry, package_root: &str) -> (String, String) {
+    let prefix = registry.repository(package_root, "");
+    match prefix.split_once('/') {
+        Some((host, prefix)) => (host.into(), prefix.into()),
+        None => (prefix, String::new()),
+    }
+}
+
+// Creates a registry API client for `host`, using the credentials `docker`
+// holds for it.
+fn registry_client<'a>(
+    docker: &Docker,
+    registry: &'a Registry,
+    host: &str,
+) -> SeavanResult<distribution::Client<'a>> {
+    let basic = match docker.registry_config(&format!("{}/", host)) {
+        Some(config) => Some(config.basic()?),
+        None => None,
+    };
+    Ok(distribution::Client::new(host, basic, registry.transport()))
+}
+
+// Lists the images in the registry's repositories starting with `prefix`.
+// Only manifests for which `select(repository, digest, tags)` returns true
+// are fetched; indexes of several images are skipped.
+fn remote_images<F: Fn(&str, &str, &[String]) -> bool>(
+    client: &mut distribution::Client<'_>,
+    prefix: &str,
+    select: F,
+) -> SeavanResult<Vec<RemoteImage>> {
+    let catalog = format!("_catalog?n={}", CATALOG_PAGE_SIZE);
+    let repositories: Vec<String> = get_pages::<Catalog>(client, &catalog)?
+        .into_iter()
+        .flat_map(|page| page.repositories)
+        .collect();
+
+    let mut images = Vec::new();
+    for repository in repositories.iter().filter(|r| r.starts_with(prefix)) {
+        let tags = get_pages::<TagList>(client, &format!("{}/tags/list", repository))?
+            .into_iter()
+            .flat_map(|page| page.tags.unwrap_or_default());
+
+        // Group the tags by the manifest they point to, as deleting a
+        // manifest removes all of its tags.
+        let mut manifests: BTreeMap<String, (Option<String>, Vec<String>)> = BTreeMap::new();
+        for tag in tags {
+            let response = client.manifest(repository, &tag)?;
+            distribution::expect(&response, 200, &format!("fetching {}:{}", repository, tag))?;
+            let digest = match response.header("Docker-Content-Digest") {
+                Some(digest) => digest.to_string(),
+                None => format!("sha256:{:x}", Sha256::digest(&response.body)),
+            };
+            let manifest: Manifest = serde_json::from_slice(&response.body)?;
+            let config = manifest.config.map(|config| config.digest);
+            manifests
+                .entry(digest)
+                .or_insert((config, Vec::new()))
+                .1
+                .push(tag);
+        }
+
+        for (digest, (config, tags)) in manifests {
+            if !select(repository, &digest, &tags) {
+                continue;
+            }
+            let config = match config {
+                Some(config) => config,
+                None => {
+                    debug!("Keeping {}@{}: not a single image", repository, digest);
+                    continue;
+                }
+            };
+            let image: ImageConfig = get_json(client, &format!("{}/blobs/{}", repository, config))?;
+            let labels = image.config.labels.unwrap_or_default();
+            if !labels.contains_key(labels::CONTENT_DIGEST) {
+                continue;
+            }
+            images.push(RemoteImage {
+                repository: repository.clone(),
+                digest,
+                tags,
+                created: image.created,
+                labels,
+            });
+        }
+    }
+    Ok(images)
+}
+
+// Deletes the manifest of `image` from the registry at `host`, returning the
+// references of the tags which were removed with it.
+fn delete_image(
+    client: &mut distribution::Client<'_>,
+    host: &str,
+    image: &RemoteImage,
+) -> SeavanResult<Vec<String>> {
+    let reference = format!("{}/{}@{}", host, image.repository, image.digest);
+    debug!("Pruning {}", reference);
+    let response = client.request(
+        "DELETE",
+        &format!("{}/manifests/{}", image.repository, image.digest),
+        None,
+    )?;
+    distribution::expect(&response, 202, &format!("deleting {}", reference))?;
+    Ok(image
+        .tags
+        .iter()
+        .map(|tag| format!("{}/{}:{}", host, image.repository, tag))
+        .collect())
+}
@@ -0,0 +1,125 @@
+//! Organizational rules which wrapped files and their images must follow.
+//!
+//! Rules are checked before images are built, and can be checked up front
+//! with `Seavan::validate`. They can be set in code with `Seavan::with_rules`,
+//! or in configuration files:
+//!
+//! ```toml
+//! [rules]
+//! allowed_registries = ["acr.azurecr.io", "registry.example.com/team"]
+//! max_size = 1073741824
+//! required_labels = ["com.example.team"]
+//! ```
+
+use serde::Deserialize;
+use std::fmt;
+
+/// Rules which wrapped files and their images must follow.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Rules {
+    /// Registries images may be stored in. A registry is allowed if its name
+    /// matches an entry, or is under an entry's path. When this is empty,
+    /// any registry is allowed.
+    #[serde(default)]
+    pub allowed_registries: Vec<String>,
+
+    /// The largest file, in bytes, which may be wrapped.
+    #[serde(default)]
+    pub max_size: Option<u64>,
+
+    /// Labels which every image must carry, e.g. from configuration files.
+    #[serde(default)]
+    pub required_labels: Vec<String>,
+}
+
+impl Rules {
+    /// Merges two sets of rules. The size limit in `overrides` takes
+    /// precedence, and the allowed registries and required labels from both
+    /// are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `overrides`: The rules to merge over these ones.
+    pub fn merge(mut self, overrides: Rules) -> Self {
+        self.allowed_registries.extend(overrides.allowed_registries);
+        if overrides.max_size.is_some() {
+            self.max_size = overrides.max_size;
+        }
+        self.required_labels.extend(overrides.required_labels);
+        self
+    }
+
+    // Returns whether images may be stored in the registry `name`, or with
+    // no registry if `name` is `None`.
+    pub(crate) fn allows_registry(&self, name: Option<&str>) -> bool {
+        if self.allowed_registries.is_empty() {
+            return true;
+        }
+        name.is_some_and(|name| {
+            self.allowed_registries.iter().any(|allowed| {
+                let allowed = allowed.trim_end_matches('/');
+                name == allowed
+                    || name
+                        .strip_prefix(allowed)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+        })
+    }
+}
+
+/// A rule or policy broken by a wrapped file, as found by
+/// `Seavan::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The file is rejected by a policy.
+    PolicyRejected,
+
+    /// The registry isn't in the allowed registries. Holds the registry name,
+    /// or `None` if no registry is set.
+    RegistryNotAllowed(Option<String>),
+
+    /// The image reference breaks the registry's naming rules. Holds the
+    /// reference and the reason.
+    InvalidReference(String, String),
+
+    /// The filename makes the repository name too long for the registry.
+    /// Holds the sanitized filename and the number of characters over the
+    /// limit.
+    FilenameTooLong(String, usize),
+
+    /// The file is larger than the size limit. Holds the size and the limit,
+    /// in bytes.
+    TooLarge(u64, u64),
+
+    /// The image would be missing a required label.
+    MissingLabel(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::PolicyRejected => f.write_str("rejected by policy"),
+            Violation::RegistryNotAllowed(Some(registry)) => {
+                write!(f, "registry {} is not allowed", registry)
+            }
+            Violation::RegistryNotAllowed(None) => f.write_str("a registry must be set"),
+            Violation::InvalidReference(reference, reason) => {
+                write!(f, "invalid reference {:?}: {}", reference, reason)
+            }
+            Violation::FilenameTooLong(filename, excess) => write!(
+                f,
+                "filename {:?} makes the repository name {} characters too long; \
+                 shorten it or use NamingStrategy::HashOnly",
+                filename, excess
+            ),
+            Violation::TooLarge(size, limit) => {
+                write!(
+                    f,
+                    "{} bytes is larger than the limit of {} bytes",
+                    size, limit
+                )
+            }
+            Violation::MissingLabel(key) => write!(f, "missing required label {}", key),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Execution of the docker commands run by seavan.
+//!
+//! By default docker is run as a local process. A different `CommandRunner`
+//! can be supplied to run the docker steps elsewhere, for example on another
+//! machine over SSH or inside another container.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::process::{Child, Command, Output, Stdio};
+
+/// Runs the processes seavan needs, usually `docker`.
+///
+/// Runners receive a fully configured `Command`; its program, arguments,
+/// environment and working directory are available through `get_program`,
+/// `get_args`, `get_envs` and `get_current_dir`. Input for the command is
+/// passed separately as `stdin` so runners can forward it.
+///
+/// This is implemented for closures with the same signature as `run`, so a
+/// callback can be used directly.
+pub trait CommandRunner: Send + Sync {
+    /// Runs `command` to completion and collects its output.
+    ///
+    /// # Arguments
+    ///
+    /// * `command`: The command to run.
+    /// * `stdin`: The file to use as the standard input of the command, if
+    ///   any.
+    fn run(&self, command: &mut Command, stdin: Option<File>) -> io::Result<Output>;
+
+    /// Starts `command` with piped standard input, output and error, so input
+    /// can be streamed to it. This is used by `Seavan::with_streaming`.
+    ///
+    /// The default implementation reports that streaming is unsupported.
+    ///
+    /// # Arguments
+    ///
+    /// * `command`: The command to start.
+    fn spawn(&self, command: &mut Command) -> io::Result<Child> {
+        let _ = command;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "streaming is not supported by this runner",
+        ))
+    }
+
+    /// Returns whether commands are run on this host, so they can read the
+    /// files seavan writes for them, such as the docker configurations
+    /// holding credentials given with `Seavan::with_credentials`. Runners
+    /// which run commands elsewhere should return `false`, so that commands
+    /// needing those files fail rather than running without them.
+    ///
+    /// The default implementation returns `true`.
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+impl<F> CommandRunner for F
+where
+    F: Fn(&mut Command, Option<File>) -> io::Result<Output> + Send + Sync,
+{
+    fn run(&self, command: &mut Command, stdin: Option<File>) -> io::Result<Output> {
+        self(command, stdin)
+    }
+}
+
+/// Runs commands as local processes. This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalRunner;
+
+impl CommandRunner for LocalRunner {
+    fn run(&self, command: &mut Command, stdin: Option<File>) -> io::Result<Output> {
+        if let Some(stdin) = stdin {
+            let _ = command.stdin(stdin);
+        }
+        command.output()
+    }
+
+    fn spawn(&self, command: &mut Command) -> io::Result<Child> {
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+}
+
+/// Runs commands on a remote host using the `ssh` client.
+///
+/// The command's environment and working directory are recreated on the
+/// remote host, and standard input is forwarded over the connection. The
+/// wrapped files must be available at the same paths on the remote host, for
+/// example on a shared filesystem.
+///
+/// # Examples
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{runner::SshRunner, Seavan};
+/// use std::sync::Arc;
+/// let runner = SshRunner::new("builder@build-host").with_option("-J").with_option("jump-host");
+/// let wrap = Seavan::new("README.md")?.with_command_runner(Arc::new(runner))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SshRunner {
+    destination: String,
+    options: Vec<String>,
+}
+
+impl SshRunner {
+    /// Creates a new `SshRunner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination`: The host to run commands on, in any form `ssh`
+    ///   accepts, e.g. `user@host` or a `Host` alias from the ssh config.
+    pub fn new(destination: &str) -> Self {
+        Self {
+            destination: destination.into(),
+            options: Vec::new(),
+        }
+    }
+
+    /// Adds an argument to pass to `ssh` before the destination, e.g. `-J`
+    /// and a jump host, or `-i` and an identity file.
+    ///
+    /// # Arguments
+    ///
+    /// * `option`: The argument to add.
+    pub fn with_option(mut self, option: &str) -> Self {
+        self.options.push(option.into());
+        self
+    }
+}
+
+impl SshRunner {
+    // Creates the ssh command which runs `command` on the remote host.
+    fn ssh_command(&self, command: &Command) -> Command {
+        let mut ssh = Command::new("ssh");
+        let _ = ssh
+            .args(&self.options)
+            .arg("--")
+            .arg(&self.destination)
+            .arg(remote_command(command));
+        ssh
+    }
+}
+
+impl CommandRunner for SshRunner {
+    fn run(&self, command: &mut Command, stdin: Option<File>) -> io::Result<Output> {
+        LocalRunner.run(&mut self.ssh_command(command), stdin)
+    }
+
+    fn spawn(&self, command: &mut Command) -> io::Result<Child> {
+        LocalRunner.spawn(&mut self.ssh_command(command))
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+// Quotes a value for a POSIX shell.
+fn shell_quote(value: &OsStr) -> String {
+    format!("'{}'", value.to_string_lossy().replace('\'', r"'\''"))
+}
+
+// Builds a shell command line which recreates `command` on a remote host.
+pub(crate) fn remote_command(command: &Command) -> String {
+    let mut words = Vec::new();
+    if let Some(dir) = command.get_current_dir() {
+        words.push(format!("cd {} &&", shell_quote(dir.as_os_str())));
+    }
+
+    // `env` needs its options before any assignments, so removed variables
+    // come first.
+    let (removed, set): (Vec<_>, Vec<_>) = command.get_envs().partition(|(_, v)| v.is_none());
+    if !removed.is_empty() || !set.is_empty() {
+        words.push("env".into());
+        for (key, _) in removed {
+            words.push(format!("-u {}", shell_quote(key)));
+        }
+        for (key, value) in set {
+            let mut assignment = key.to_os_string();
+            assignment.push("=");
+            assignment.push(value.unwrap_or_default());
+            words.push(shell_quote(&assignment));
+        }
+    }
+
+    words.push(shell_quote(command.get_program()));
+    words.extend(command.get_args().map(shell_quote));
+    words.join(" ")
+}
@@ -0,0 +1,235 @@
+//! Scanning files before they are wrapped, so secrets aren't accidentally
+//! stored in shared registries.
+
+use crate::error::SeavanResult;
+use regex::bytes::Regex;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+
+// Number of bytes `SecretScanner` reads from the start of each file.
+const DEFAULT_MAX_BYTES: u64 = 1 << 20;
+
+// Shortest token checked for high entropy.
+const MIN_TOKEN_LENGTH: usize = 32;
+
+// Entropy, in bits per character, above which a token looks like a secret.
+// Hex strings such as content hashes have at most 4 bits per character.
+const ENTROPY_THRESHOLD: f64 = 4.5;
+
+/// A check run on files before they are wrapped, which can veto wrapping.
+///
+/// This is implemented for closures with the same signature as `scan`, so a
+/// callback can be used directly.
+pub trait Scanner: Send + Sync {
+    /// Scans a file, returning the reason to refuse wrapping it, or `None` if
+    /// it may be wrapped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the file. For files wrapped from a handle with
+    ///   `Seavan::from_file`, this is only the filename.
+    /// * `content`: A reader over the file's content.
+    fn scan(&self, path: &Path, content: &mut dyn Read) -> SeavanResult<Option<String>>;
+}
+
+impl<F> Scanner for F
+where
+    F: Fn(&Path, &mut dyn Read) -> SeavanResult<Option<String>> + Send + Sync,
+{
+    fn scan(&self, path: &Path, content: &mut dyn Read) -> SeavanResult<Option<String>> {
+        self(path, content)
+    }
+}
+
+/// A basic scanner which refuses files that look like they hold secrets:
+/// private keys, well-known access token formats, and long high-entropy
+/// strings such as encoded credentials. Only the start of each file is
+/// scanned, 1 MiB by default.
+#[derive(Debug, Clone)]
+pub struct SecretScanner {
+    patterns: Vec<(&'static str, Regex)>,
+    token: Regex,
+    max_bytes: u64,
+}
+
+impl SecretScanner {
+    /// Creates a new `SecretScanner`.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{scan::SecretScanner, Seavan};
+    /// use std::sync::Arc;
+    /// let wrap = Seavan::new("README.md")?.with_scanner(Arc::new(SecretScanner::new()?))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new() -> SeavanResult<Self> {
+        Ok(Self {
+            patterns: vec![
+                (
+                    "private key",
+                    Regex::new(r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY( BLOCK)?-----")?,
+                ),
+                (
+                    "AWS access key",
+                    Regex::new(r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b")?,
+                ),
+                (
+                    "GitHub token",
+                    Regex::new(r"\bgh[pousr]_[A-Za-z0-9]{36,}\b")?,
+                ),
+                (
+                    "Slack token",
+                    Regex::new(r"\bxox[abprs]-[A-Za-z0-9-]{10,}")?,
+                ),
+            ],
+            token: Regex::new(&format!("[A-Za-z0-9+/=_-]{{{},}}", MIN_TOKEN_LENGTH))?,
+            max_bytes: DEFAULT_MAX_BYTES,
+        })
+    }
+
+    /// Sets how many bytes are scanned from the start of each file.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bytes`: The number of bytes to scan.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+/// A scanner which runs an external command, such as a virus scanner, on a
+/// staged copy of the file. The file is refused if the command exits with a
+/// nonzero status.
+///
+/// The content is copied to a temporary file with the same name as the
+/// wrapped file, whose path is passed as the last argument of the command.
+///
+/// # Examples
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use seavan::{scan::CommandScanner, Seavan};
+/// use std::sync::Arc;
+/// let clamscan = CommandScanner::new("clamscan").with_arg("--no-summary");
+/// let wrap = Seavan::new("README.md")?.with_pre_push_scanner(Arc::new(clamscan))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandScanner {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandScanner {
+    /// Creates a new `CommandScanner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `program`: The command to run.
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Adds an argument to pass to the command before the file path.
+    ///
+    /// # Arguments
+    ///
+    /// * `arg`: The argument to add.
+    pub fn with_arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl Scanner for CommandScanner {
+    fn scan(&self, path: &Path, content: &mut dyn Read) -> SeavanResult<Option<String>> {
+        let staging = tempfile::Builder::new().prefix("seavan").tempdir()?;
+        let staged = staging
+            .path()
+            .join(path.file_name().unwrap_or_else(|| "content".as_ref()));
+        let _ = io::copy(content, &mut File::create(&staged)?)?;
+
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .arg(&staged)
+            .output()?;
+        if output.status.success() {
+            return Ok(None);
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = match stderr.trim() {
+            "" => stdout.trim(),
+            stderr => stderr,
+        };
+        Ok(Some(format!(
+            "{} exited with {}: {}",
+            self.program, output.status, detail
+        )))
+    }
+}
+
+// Returns the Shannon entropy of `token` in bits per byte.
+fn entropy(token: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for b in token {
+        counts[*b as usize] += 1;
+    }
+    let len = token.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+impl Scanner for SecretScanner {
+    fn scan(&self, _path: &Path, content: &mut dyn Read) -> SeavanResult<Option<String>> {
+        let mut head = Vec::new();
+        let _ = content.take(self.max_bytes).read_to_end(&mut head)?;
+
+        if let Some((name, _)) = self.patterns.iter().find(|(_, re)| re.is_match(&head)) {
+            return Ok(Some(format!("contains a {}", name)));
+        }
+
+        // Binary files are naturally high-entropy, so only check text. The
+        // head may have been cut part way through a character, which leaves
+        // the text before it.
+        let text = match std::str::from_utf8(&head) {
+            Ok(_) => Some(&head[..]),
+            Err(e) if e.error_len().is_none() => Some(&head[..e.valid_up_to()]),
+            Err(_) => None,
+        };
+        if text.is_some_and(|text| {
+            self.token
+                .find_iter(text)
+                .any(|token| entropy(token.as_bytes()) > ENTROPY_THRESHOLD)
+        }) {
+            return Ok(Some("contains a high-entropy string".into()));
+        }
+        Ok(None)
+    }
+}
+
+// A shareable scanner.
+#[derive(Clone)]
+pub(crate) struct ScannerHandle(pub(crate) Arc<dyn Scanner>);
+
+impl fmt::Debug for ScannerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Scanner")
+    }
+}
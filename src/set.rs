@@ -0,0 +1,385 @@
+//! Batches of wrapped files built together.
+
+use crate::{
+    audit::AuditAction,
+    docker::{self, Docker},
+    engine::ContainerEngine,
+    error::{SeavanError, SeavanResult},
+    runner::CommandRunner,
+    state::StateStore,
+    BuildContext, Seavan,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::tempfile;
+
+/// An image created by a `SeavanSet`, as recorded in a `BatchReport`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchEntry {
+    /// The path of the wrapped file.
+    pub path: PathBuf,
+    /// The content hash of the file.
+    pub hash: String,
+    /// The repository name and tag of the image.
+    pub reference: String,
+}
+
+/// A record of the images created by a `SeavanSet`. Passing the report of
+/// one run to the next with `SeavanSet::with_previous_report` makes the next
+/// run only build files which have changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchReport {
+    /// The image created for each file, in the same order as the set.
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    /// Reads a report saved with `save`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the report file.
+    pub fn load<P: AsRef<Path>>(path: P) -> SeavanResult<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Saves the report as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the report file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> SeavanResult<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a report saved in a state store with `save_state`, or returns
+    /// `None` if there isn't one.
+    ///
+    /// # Arguments
+    ///
+    /// * `store`: The state store holding the report.
+    /// * `name`: The name the report was saved under.
+    pub fn load_state(store: &dyn StateStore, name: &str) -> SeavanResult<Option<Self>> {
+        match store.get(&format!("report/{}", name))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Saves the report in a state store, so it can be shared between
+    /// machines.
+    ///
+    /// # Arguments
+    ///
+    /// * `store`: The state store to save the report in.
+    /// * `name`: The name to save the report under, such as the name of the
+    ///   batch job.
+    pub fn save_state(&self, store: &dyn StateStore, name: &str) -> SeavanResult<()> {
+        store.put(&format!("report/{}", name), &serde_json::to_vec(self)?)
+    }
+
+    // Returns the entry for `path`, if there is one.
+    fn entry(&self, path: &Path) -> Option<&BatchEntry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+}
+
+/// The changes between a previous `BatchReport` and a new run of a
+/// `SeavanSet`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeltaSummary {
+    /// Files whose images were built in this run.
+    pub built: Vec<PathBuf>,
+    /// Files whose images were unchanged since the previous report, and
+    /// weren't rebuilt.
+    pub unchanged: Vec<PathBuf>,
+    /// Files in the previous report which are no longer in the set.
+    pub removed: Vec<PathBuf>,
+}
+
+/// A collection of `Seavan`s which are built together.
+///
+/// Building a set generates a single `docker buildx bake` definition covering
+/// every wrapped file, which avoids the per-build overhead of invoking Docker
+/// once per file.
+#[derive(Debug, Default)]
+pub struct SeavanSet {
+    wraps: Vec<Seavan>,
+    docker: Docker,
+    previous: Option<BatchReport>,
+}
+
+impl SeavanSet {
+    /// Creates a new `SeavanSet` from a list of `Seavan`s.
+    ///
+    /// # Arguments
+    ///
+    /// * `wraps`: The wrapped files to be built together.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{set::SeavanSet, Seavan};
+    /// let set = SeavanSet::new(vec![Seavan::new("README.md")?, Seavan::new("Cargo.toml")?]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(wraps: Vec<Seavan>) -> Self {
+        Self {
+            wraps,
+            docker: Docker::default(),
+            previous: None,
+        }
+    }
+
+    /// Adds another `Seavan` to the set.
+    ///
+    /// # Arguments
+    ///
+    /// * `wrap`: The wrapped file to add.
+    pub fn with_seavan(mut self, wrap: Seavan) -> Self {
+        self.wraps.push(wrap);
+        self
+    }
+
+    /// Adds an environment variable to pass to the `docker buildx bake`
+    /// process which builds the set. Environment variables set on the
+    /// individual `Seavan`s are used when tagging their images.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The name of the environment variable.
+    /// * `value`: The value of the environment variable.
+    pub fn with_docker_env(mut self, key: &str, value: &str) -> Self {
+        self.docker.add_env(key, value);
+        self
+    }
+
+    /// Sets the runner used to execute the `docker buildx bake` process which
+    /// builds the set. Images are tagged using the runner of each `Seavan`,
+    /// so the `Seavan`s should be given the same runner.
+    ///
+    /// # Arguments
+    ///
+    /// * `runner`: The runner to execute docker commands with.
+    pub fn with_command_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.docker.set_runner(runner);
+        self
+    }
+
+    /// Sets the container engine used in place of Docker to build the set,
+    /// as `Seavan::with_engine` does. Images are tagged using the engine of
+    /// each `Seavan`, so the `Seavan`s should be given the same engine.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine`: The container engine to use.
+    pub fn with_engine<E: ContainerEngine + 'static>(mut self, engine: E) -> Self {
+        self.docker.set_engine(Arc::new(engine));
+        self
+    }
+
+    /// Only builds images for files which have changed since `report` was
+    /// made. Files are unchanged if the report holds the same path, content
+    /// hash and repository name and tag; their images are assumed to still
+    /// exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `report`: The report of a previous run, from
+    ///   `create_images_with_report`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{set::{BatchReport, SeavanSet}, Seavan};
+    /// let set = SeavanSet::new(vec![Seavan::new("README.md")?, Seavan::new("Cargo.toml")?])
+    ///     .with_previous_report(BatchReport::load("seavan-report.json")?);
+    /// let (report, delta) = set.create_images_with_report()?;
+    /// println!("Built {} images, {} unchanged", delta.built.len(), delta.unchanged.len());
+    /// report.save("seavan-report.json")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_previous_report(mut self, report: BatchReport) -> Self {
+        self.previous = Some(report);
+        self
+    }
+
+    /// Returns the `Seavan`s in this set.
+    pub fn wraps(&self) -> &[Seavan] {
+        &self.wraps
+    }
+
+    // Helper method to generate the bake definition for the given wraps and
+    // their build contexts. Each target is tagged with the matching temporary
+    // reference.
+    fn bake_definition(
+        &self,
+        builds: &[(&Seavan, &String)],
+        contexts: &[BuildContext],
+        temporary_references: &[String],
+    ) -> SeavanResult<Value> {
+        let mut targets = Map::new();
+        for (index, (((wrap, _), context), temporary_reference)) in builds
+            .iter()
+            .zip(contexts)
+            .zip(temporary_references)
+            .enumerate()
+        {
+            let directory = context
+                .directory
+                .to_str()
+                .ok_or(SeavanError::FailedStrConversion)?;
+
+            let mut target = json!({
+                "context": directory,
+                "dockerfile-inline": context.dockerfile,
+                "tags": [temporary_reference],
+            });
+            if !wrap.cache_from.is_empty() {
+                target["cache-from"] = json!(wrap.cache_from);
+            }
+            if let Some(cache) = &wrap.cache_to {
+                target["cache-to"] = json!([cache]);
+            }
+            if let Some(platform) = &wrap.platform {
+                target["platforms"] = json!([platform]);
+            }
+            let _ = targets.insert(format!("wrap{}", index), target);
+        }
+
+        let names: Vec<&String> = targets.keys().collect();
+        Ok(json!({
+            "group": { "default": { "targets": names } },
+            "target": targets,
+        }))
+    }
+
+    /// Creates container images for every wrapped file in the set using a
+    /// single `docker buildx bake` invocation. The user must be able to run
+    /// Docker commands, and the buildx plugin must be installed.
+    ///
+    /// Returns the generated repository names and tags, in the same order as
+    /// the `Seavan`s in the set.
+    pub fn create_images(&self) -> SeavanResult<Vec<String>> {
+        let (report, _) = self.create_images_with_report()?;
+        Ok(report
+            .entries
+            .into_iter()
+            .map(|entry| entry.reference)
+            .collect())
+    }
+
+    /// Creates container images for the wrapped files in the set, as
+    /// `create_images` does, and returns a report of the images which can be
+    /// passed to a later run with `with_previous_report`, along with a
+    /// summary of the changes since the previous report.
+    pub fn create_images_with_report(&self) -> SeavanResult<(BatchReport, DeltaSummary)> {
+        for wrap in &self.wraps {
+            let _ = wrap.check_buildable()?;
+        }
+        let mut report = BatchReport::default();
+        for wrap in &self.wraps {
+            let hash = wrap.hash()?;
+            report.entries.push(BatchEntry {
+                path: wrap.path.clone(),
+                reference: wrap.resolve_repository_name_and_tag_for(&hash)?,
+                hash,
+            });
+        }
+
+        let mut delta = DeltaSummary::default();
+        let mut builds = Vec::new();
+        let mut repository_names_and_tags = Vec::new();
+        for (wrap, entry) in self.wraps.iter().zip(&report.entries) {
+            let previous = self.previous.as_ref().and_then(|p| p.entry(&entry.path));
+            if previous == Some(entry) {
+                delta.unchanged.push(entry.path.clone());
+            } else {
+                delta.built.push(entry.path.clone());
+                builds.push((wrap, &entry.hash));
+                repository_names_and_tags.push(&entry.reference);
+            }
+        }
+        if let Some(previous) = &self.previous {
+            delta.removed = previous
+                .entries
+                .iter()
+                .filter(|entry| report.entry(&entry.path).is_none())
+                .map(|entry| entry.path.clone())
+                .collect();
+        }
+        debug!(
+            "{} images to build, {} unchanged, {} removed",
+            delta.built.len(),
+            delta.unchanged.len(),
+            delta.removed.len()
+        );
+        if !builds.is_empty() {
+            self.bake(&builds, &repository_names_and_tags)?;
+        }
+        Ok((report, delta))
+    }
+
+    // Helper method to build the given wraps with their hashes in a single
+    // bake, naming each image with the matching repository name and tag.
+    fn bake(
+        &self,
+        builds: &[(&Seavan, &String)],
+        repository_names_and_tags: &[&String],
+    ) -> SeavanResult<()> {
+        let temporary_references: Vec<String> = builds
+            .iter()
+            .map(|_| docker::temporary_reference())
+            .collect();
+
+        let contexts = builds
+            .iter()
+            .map(|(wrap, hash)| wrap.build_context(hash))
+            .collect::<SeavanResult<Vec<_>>>()?;
+
+        // Write the bake definition to a temporary file, then rewind.
+        let mut tempbake = tempfile()?;
+        serde_json::to_writer(
+            &mut tempbake,
+            &self.bake_definition(builds, &contexts, &temporary_references)?,
+        )?;
+        tempbake.flush()?;
+        tempbake.rewind()?;
+
+        debug!("Baking {} images", builds.len());
+        let output = self.docker.run(
+            self.docker
+                .command()
+                .args(["buildx", "bake", "-f", "-", "--load"]),
+            Some(tempbake),
+        )?;
+
+        if !output.status.success() {
+            return Err(docker::build_failure(output));
+        }
+        docker::log_output(&output);
+
+        // Move each image to its final name.
+        for (((wrap, hash), temporary_reference), repository_name_and_tag) in builds
+            .iter()
+            .zip(&temporary_references)
+            .zip(repository_names_and_tags)
+        {
+            wrap.docker
+                .promote(temporary_reference, repository_name_and_tag)?;
+            wrap.audit(AuditAction::Create, repository_name_and_tag)?;
+            wrap.catalog(repository_name_and_tag, hash)?;
+        }
+        Ok(())
+    }
+}
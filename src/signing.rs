@@ -0,0 +1,74 @@
+//! Signing of pushed images, for registries whose policies only admit signed
+//! images.
+//!
+//! Images are signed by digest with the `cosign` or `notation` command line
+//! tools, which must be installed and able to authenticate to the registry
+//! wherever images are pushed.
+
+use crate::{
+    error::{SeavanError, SeavanResult},
+    naming,
+};
+use std::process::Command;
+
+/// The tools pushed images can be signed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signer {
+    /// Sigstore's cosign, storing the signature in the registry next to the
+    /// image.
+    Cosign {
+        /// The signing key: a path to a cosign key file, or a KMS URI such
+        /// as `awskms:///alias/signing`.
+        key: String,
+    },
+
+    /// Notary Project's notation, storing the signature as an OCI referrer
+    /// of the image.
+    Notation {
+        /// The name of the signing key, as listed by `notation key list`.
+        key: String,
+    },
+}
+
+impl Signer {
+    // Returns the command line tool for the signer.
+    fn program(&self) -> &'static str {
+        match self {
+            Signer::Cosign { .. } => "cosign",
+            Signer::Notation { .. } => "notation",
+        }
+    }
+
+    // Returns the command which signs `image`, a `repository@digest`
+    // reference.
+    pub(crate) fn command(&self, image: &str) -> Command {
+        let mut command = Command::new(self.program());
+        let _ = match self {
+            Signer::Cosign { key } => command.args(["sign", "--yes", "--key", key]),
+            Signer::Notation { key } => command.args(["sign", "--key", key]),
+        };
+        let _ = command.arg(image);
+        command
+    }
+
+    // Signs the image pushed as `reference` with the manifest digest
+    // `digest`. Images are always signed by digest, so the signature can't
+    // be attached to a different image pushed to the same tag.
+    pub(crate) fn sign(&self, reference: &str, digest: Option<&str>) -> SeavanResult<()> {
+        let failure = |detail: String| SeavanError::SigningFailure(reference.into(), detail);
+        let digest = digest
+            .ok_or_else(|| failure("the registry didn't report the image's digest".into()))?;
+        let image = format!("{}@{}", naming::repository_of(reference), digest);
+
+        let output = self
+            .command(&image)
+            .output()
+            .map_err(|e| failure(format!("{}: {}", self.program(), e)))?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(failure(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            )),
+        }
+    }
+}
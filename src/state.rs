@@ -0,0 +1,138 @@
+//! Storage for state seavan keeps between runs, such as cached content
+//! hashes and batch reports.
+//!
+//! State is kept in a `StateStore`, a simple key-value store. `FileStateStore`
+//! keeps state in a local directory; other implementations can keep it in a
+//! shared database so a fleet of build machines can share it.
+
+use crate::{error::SeavanResult, utils::hostname};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// A key-value store for state kept between runs.
+///
+/// Keys are short printable strings, such as `hash/<digest>`. Values are
+/// arbitrary bytes.
+pub trait StateStore: Send + Sync {
+    /// Returns the value stored under `key`, or `None` if there isn't one.
+    fn get(&self, key: &str) -> SeavanResult<Option<Vec<u8>>>;
+
+    /// Stores `value` under `key`, replacing any existing value.
+    fn put(&self, key: &str, value: &[u8]) -> SeavanResult<()>;
+}
+
+/// A `StateStore` which keeps each value in a file in a local directory.
+/// Values are replaced atomically, so the directory can be shared by
+/// concurrent processes.
+#[derive(Debug, Clone)]
+pub struct FileStateStore {
+    dir: PathBuf,
+}
+
+impl FileStateStore {
+    /// Creates a new `FileStateStore`. The directory is created if it doesn't
+    /// exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`: The directory to keep state in.
+    ///
+    /// # Examples
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use seavan::{state::FileStateStore, Seavan};
+    /// use std::sync::Arc;
+    /// let store = FileStateStore::new(std::env::temp_dir().join("seavan-state"))?;
+    /// let wrap = Seavan::new("README.md")?.with_state_store(Arc::new(store))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new<P: AsRef<Path>>(dir: P) -> SeavanResult<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    // Returns the path of the file holding `key`. Keys are hashed so they
+    // can't escape the directory.
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}", Sha256::digest(key.as_bytes())))
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn get(&self, key: &str) -> SeavanResult<Option<Vec<u8>>> {
+        match fs::read(self.path(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> SeavanResult<()> {
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        file.write_all(value)?;
+        let _ = file.persist(self.path(key)).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+// Returns the key caching the hash of the file at `path`, which changes
+// whenever the file is modified or the hash scheme changes. Stores can be
+// shared between machines, so the key includes the host and the file's
+// identity on disk as well as its path.
+pub(crate) fn hash_key(path: &Path, scheme: Option<&str>) -> SeavanResult<String> {
+    let metadata = File::open(path)?.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let identity = format!(
+        "{}\0{}\0{}\0{}\0{}\0{}",
+        hostname().unwrap_or_default(),
+        file_identity(&metadata),
+        path.display(),
+        metadata.len(),
+        modified,
+        scheme.unwrap_or("sha256")
+    );
+    Ok(format!("hash/{:x}", Sha256::digest(identity.as_bytes())))
+}
+
+// Returns the device, inode and change time of a file. The change time can't
+// be set by the file's owner, unlike the modification time.
+#[cfg(unix)]
+fn file_identity(metadata: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    format!(
+        "{}:{}:{}.{}",
+        metadata.dev(),
+        metadata.ino(),
+        metadata.ctime(),
+        metadata.ctime_nsec()
+    )
+}
+
+// Returns the device, inode and change time of a file, where known.
+#[cfg(not(unix))]
+fn file_identity(_metadata: &Metadata) -> String {
+    String::new()
+}
+
+// A shareable state store.
+#[derive(Clone)]
+pub(crate) struct StateStoreHandle(pub(crate) Arc<dyn StateStore>);
+
+impl fmt::Debug for StateStoreHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StateStore")
+    }
+}
@@ -1,20 +1,308 @@
 //! Useful utilities for seavan
 
-use crate::error::SeavanError;
-use regex::Captures;
+use crate::error::{SeavanError, SeavanResult};
+use regex::{Captures, Regex};
 use std::borrow::Cow;
+use std::fmt::Write;
+use std::fs::Metadata;
+use std::path::Path;
+use std::sync::OnceLock;
 
-// Converts a string into a "docker-safe" string; replacing all upper-case with
-// lower-case, and all other bad values with -.
-pub(crate) fn docker_safe_string(input: &str) -> Result<Cow<str>, SeavanError> {
-    let re = regex::Regex::new("([^a-z0-9-_]+)")?;
-    Ok(re.replace_all(input, |caps: &Captures| {
-        let cap = &caps[0];
-        cap.chars()
-            .map(|c| match c {
-                'A'..='Z' => c.to_ascii_lowercase(),
-                _ => '-',
-            })
-            .collect::<String>()
-    }))
+/// Longest filename or directory name supported, in bytes.
+const MAX_COMPONENT_LENGTH: usize = 255;
+
+/// Longest path supported, in bytes. Windows paths are canonicalized to
+/// `\\?\` paths, which aren't limited to `MAX_PATH`.
+#[cfg(windows)]
+const MAX_PATH_LENGTH: usize = 32767;
+#[cfg(target_os = "macos")]
+const MAX_PATH_LENGTH: usize = 1024;
+#[cfg(not(any(windows, target_os = "macos")))]
+const MAX_PATH_LENGTH: usize = 4096;
+
+/// Strategy used to make filenames and tags safe for use in image references.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sanitization {
+    /// Replace upper-case characters with lower-case, and all other
+    /// unsupported characters with `-`, as `docker_safe_string` does. This is
+    /// lossy, and is the default.
+    #[default]
+    ReplaceWithDash,
+
+    /// Encode upper-case and unsupported characters (and `x` itself) as `x`
+    /// followed by the hex value of each UTF-8 byte, in the style of percent
+    /// encoding, e.g. `Cargo.toml` as `x43argox2etoml`. A `-` at the start or
+    /// end is encoded too, so the result is always a valid name component.
+    /// Distinct inputs always produce distinct outputs.
+    PercentEncodeLike,
+
+    /// Reject any input containing unsupported characters with
+    /// `SeavanError::UnsupportedCharacters`.
+    Strict,
+}
+
+impl Sanitization {
+    // Makes `input` safe for use in an image reference using this strategy.
+    pub(crate) fn apply<'a>(&self, input: &'a str) -> Result<Cow<'a, str>, SeavanError> {
+        match self {
+            Sanitization::ReplaceWithDash => Ok(docker_safe_string(input)),
+            Sanitization::PercentEncodeLike => Ok(percent_encode_like(input)),
+            Sanitization::Strict => match docker_safe_string(input) {
+                Cow::Borrowed(safe) => Ok(Cow::Borrowed(safe)),
+                Cow::Owned(_) => Err(SeavanError::UnsupportedCharacters(input.to_string())),
+            },
+        }
+    }
+}
+
+// Character class of the characters kept by default.
+const DEFAULT_ALLOWED: &str = "a-z0-9_-";
+
+// Returns the default options, with their regex compiled once.
+fn default_options() -> &'static SanitizeOptions {
+    static DEFAULT: OnceLock<SanitizeOptions> = OnceLock::new();
+    DEFAULT.get_or_init(|| SanitizeOptions {
+        disallowed: disallowed(DEFAULT_ALLOWED).expect("default character class is valid"),
+        replacement: '-',
+        max_length: None,
+    })
+}
+
+// Compiles a regex matching runs of characters outside `allowed`.
+fn disallowed(allowed: &str) -> SeavanResult<Regex> {
+    Ok(Regex::new(&format!("[^{}]+", allowed))?)
+}
+
+/// Options for making strings safe for use in image references, as used by
+/// seavan for filenames and tags.
+///
+/// Upper-case letters are replaced with their lower-case equivalent if that
+/// is allowed, and every other character which isn't allowed is replaced with
+/// the replacement character. The default options match the names seavan
+/// generates.
+///
+/// # Examples
+/// ```
+/// use seavan::utils::SanitizeOptions;
+/// let options = SanitizeOptions::new().with_replacement('_').with_max_length(8);
+/// assert_eq!(options.sanitize("My Data.csv"), "my_data_");
+/// ```
+#[derive(Debug, Clone)]
+pub struct SanitizeOptions {
+    disallowed: Regex,
+    replacement: char,
+    max_length: Option<usize>,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        default_options().clone()
+    }
+}
+
+impl SanitizeOptions {
+    /// Creates new `SanitizeOptions` which keep lower-case letters, digits,
+    /// `_` and `-`, replace other characters with `-`, and don't limit the
+    /// length.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Specifies the characters which are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `charset`: The allowed characters, in the syntax of a regex
+    ///   character class without the brackets, e.g. `a-z0-9._-`.
+    pub fn with_allowed(mut self, charset: &str) -> SeavanResult<Self> {
+        self.disallowed = disallowed(charset)?;
+        Ok(self)
+    }
+
+    /// Specifies the character which replaces characters that aren't allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `replacement`: The replacement character.
+    pub fn with_replacement(mut self, replacement: char) -> Self {
+        self.replacement = replacement;
+        self
+    }
+
+    /// Truncates sanitized strings to at most `max_length` characters.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_length`: The maximum length in characters.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Makes `input` safe according to these options. The input is borrowed
+    /// if it's already safe.
+    ///
+    /// # Arguments
+    ///
+    /// * `input`: The string to be sanitized.
+    pub fn sanitize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        let safe = self.disallowed.replace_all(input, |caps: &Captures| {
+            caps[0]
+                .chars()
+                .map(|c| {
+                    let lower = c.to_ascii_lowercase().to_string();
+                    match c.is_ascii_uppercase() && !self.disallowed.is_match(&lower) {
+                        true => lower,
+                        false => self.replacement.to_string(),
+                    }
+                })
+                .collect::<String>()
+        });
+        match self.max_length {
+            Some(length) if safe.chars().count() > length => {
+                Cow::Owned(safe.chars().take(length).collect())
+            }
+            _ => safe,
+        }
+    }
+}
+
+/// Converts a string into a "docker-safe" string with the default
+/// `SanitizeOptions`; replacing all upper-case with lower-case, and all other
+/// bad values with -.
+///
+/// # Arguments
+///
+/// * `input`: The string to be sanitized.
+///
+/// # Examples
+/// ```
+/// use seavan::utils::docker_safe_string;
+/// assert_eq!(docker_safe_string("README.md"), "readme-md");
+/// ```
+pub fn docker_safe_string(input: &str) -> Cow<'_, str> {
+    default_options().sanitize(input)
+}
+
+// Converts a string into a "docker-safe" string without losing information;
+// every byte other than lower-case letters other than x, digits and - is
+// encoded as x followed by its hex value. Escapes only use letters and
+// digits, so they can start or end a repository name component; for the same
+// reason - is also encoded at the start or end of the string.
+fn percent_encode_like(input: &str) -> Cow<'_, str> {
+    let last = input.len().saturating_sub(1);
+    let is_safe = |(i, b): (usize, u8)| {
+        (b.is_ascii_lowercase() && b != b'x')
+            || b.is_ascii_digit()
+            || (b == b'-' && i != 0 && i != last)
+    };
+    if input.bytes().enumerate().all(is_safe) {
+        return Cow::Borrowed(input);
+    }
+
+    let mut encoded = String::with_capacity(input.len() * 3);
+    for (i, b) in input.bytes().enumerate() {
+        match is_safe((i, b)) {
+            true => encoded.push(b as char),
+            false => {
+                let _ = write!(encoded, "x{:02x}", b);
+            }
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+// Returns the name of the user running seavan, if it can be determined.
+pub(crate) fn current_user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+}
+
+// Returns the hostname of the machine running seavan, if it can be
+// determined.
+pub(crate) fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| {
+            std::fs::read_to_string("/proc/sys/kernel/hostname")
+                .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+                .ok()
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+// Escapes a value for a double-quoted curl configuration string, in which
+// curl reads backslash escapes and a line break would end the value.
+pub(crate) fn curl_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+// Checks that `path` and each of its components are short enough for the
+// platform, so overlong paths fail up front rather than somewhere inside
+// docker.
+pub(crate) fn check_path_length(path: &Path) -> SeavanResult<()> {
+    if let Some(component) = path
+        .iter()
+        .find(|component| component.len() > MAX_COMPONENT_LENGTH)
+    {
+        return Err(SeavanError::PathComponentTooLong(
+            path.into(),
+            component.to_string_lossy().into_owned(),
+            MAX_COMPONENT_LENGTH,
+        ));
+    }
+    if path.as_os_str().len() > MAX_PATH_LENGTH {
+        return Err(SeavanError::PathTooLong(path.into(), MAX_PATH_LENGTH));
+    }
+    Ok(())
+}
+
+// Checks that `destination` is an absolute path to a file inside an image,
+// without `.` or `..` components which could place it elsewhere, and
+// without characters which Dockerfiles would interpret.
+pub(crate) fn validate_destination(destination: &str) -> SeavanResult<()> {
+    let valid = match destination.strip_prefix('/') {
+        Some(relative) => relative.split('/').all(|component| {
+            !matches!(component, "" | "." | "..")
+                && component.len() <= MAX_COMPONENT_LENGTH
+                && !component
+                    .chars()
+                    .any(|c| c.is_control() || c == '$' || c == '\\')
+        }),
+        None => false,
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(SeavanError::InvalidDestination(destination.into())),
+    }
+}
+
+// Returns the number of bytes allocated on disk for a file, which is less
+// than its length if it has holes. Returns `None` where this isn't known.
+#[cfg(unix)]
+pub(crate) fn allocated_size(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    // Block counts are always in 512-byte units, whatever the filesystem.
+    Some(metadata.blocks() * 512)
+}
+
+// Returns the number of bytes allocated on disk for a file, which is less
+// than its length if it has holes. Returns `None` where this isn't known.
+#[cfg(not(unix))]
+pub(crate) fn allocated_size(_metadata: &Metadata) -> Option<u64> {
+    None
 }